@@ -9,12 +9,13 @@
 use block_header::BlockHeader;
 use transaction::Transaction;
 use primitives::{H256, H256_LENGTH};
-use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream, to_binary};
-use bytes::{BytesMut, BufMut};
+use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream, from_binary,
+             to_binary};
+use bytes::{Bytes, BytesMut, BufMut};
 use varint;
 
 /// A block.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Block {
     pub header: BlockHeader,
     pub miner_tx: Transaction,
@@ -22,11 +23,32 @@ pub struct Block {
 }
 
 impl Block {
+    /// Parses a block blob, as relayed on `NOTIFY_NEW_BLOCK`.
+    pub fn from_bytes<T: AsRef<[u8]>>(v: T) -> Result<Self, Error> {
+        from_binary::<Self>(v.as_ref())
+    }
+
     /// Calculate block PoW (CryptoNight) hash.
     pub fn hash(&self) -> H256 {
         H256::slow_hash(self.hashable_blob())
     }
 
+    /// The serialized block, as relayed on `NOTIFY_NEW_BLOCK`.
+    pub fn serialize_blob(&self) -> Bytes {
+        to_binary(self)
+    }
+
+    /// Size in bytes of the serialized block.
+    pub fn blob_size(&self) -> usize {
+        self.len()
+    }
+
+    /// The blob hashed for the proof of work: the header, the transactions
+    /// tree hash and the number of transactions.
+    pub fn hashing_blob(&self) -> Bytes {
+        self.hashable_blob().freeze()
+    }
+
     /// Calculate the block identifier.
     pub fn id(&self) -> H256 {
         let hashable_blob = self.hashable_blob();
@@ -104,7 +126,7 @@ impl Serialize for Block {
 
 #[cfg(test)]
 pub mod tests {
-    use format::to_binary;
+    use format::{from_binary, to_binary};
     use block::Block;
     use block_header::BlockHeader;
     use transaction::{SignatureType, Transaction, TransactionPrefix, TxInGen, TxOut, TxOutToKey};
@@ -255,5 +277,10 @@ pub mod tests {
 
         let blob = to_binary(&block_15800);
         assert_eq!(&*blob, BLOB);
+
+        let decoded: Block = from_binary(BLOB).unwrap();
+        assert_eq!(&*decoded.serialize_blob(), BLOB);
+        assert_eq!(decoded.blob_size(), BLOB.len());
+        assert_eq!(decoded.id(), block_15800.id());
     }
 }
@@ -10,7 +10,7 @@ use primitives::H256;
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
 /// The metadata at the beginning of each block.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
 pub struct BlockHeader {
     /// Major block header version.
     pub major_version: u8,
@@ -64,3 +64,29 @@ impl Serialize for BlockHeader {
         sum
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn equal_headers_hash_equal() {
+        let header = BlockHeader {
+            major_version: 1,
+            minor_version: 0,
+            timestamp: 1398722004,
+            prev_id: [0x42; 32].into(),
+            nonce: 613904038,
+        };
+
+        let mut headers = HashSet::new();
+        headers.insert(header.clone());
+        headers.insert(header.clone());
+        assert_eq!(headers.len(), 1);
+
+        headers.insert(BlockHeader { nonce: 0, ..header });
+        assert_eq!(headers.len(), 2);
+    }
+}
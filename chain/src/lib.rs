@@ -13,6 +13,10 @@ extern crate xmr_keys as keys;
 extern crate xmr_format as format;
 extern crate xmr_varint as varint;
 
+#[cfg(test)]
+extern crate hex;
+
+pub mod pruning;
 pub mod transaction;
 
 mod block;
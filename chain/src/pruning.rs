@@ -0,0 +1,70 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+
+//! Pruning scheme of Monero's pruned nodes.
+//!
+//! The chain is split in stripes of `PRUNING_STRIPE_SIZE` blocks, and each
+//! pruned node keeps the prunable data of one stripe out of
+//! `1 << PRUNING_LOG_STRIPES`, identified by its pruning seed. The last
+//! `PRUNING_TIP_BLOCKS` blocks are never pruned.
+
+/// Base two logarithm of the number of stripes.
+pub const PRUNING_LOG_STRIPES: u32 = 3;
+
+/// Number of consecutive blocks in a stripe.
+pub const PRUNING_STRIPE_SIZE: u64 = 4096;
+
+/// Number of blocks from the tip kept unpruned by every node.
+pub const PRUNING_TIP_BLOCKS: u64 = 5500;
+
+const PRUNING_SEED_LOG_STRIPES_SHIFT: u32 = 7;
+const PRUNING_SEED_STRIPE_MASK: u32 = 0x7f;
+
+/// The stripe, from 1 to `1 << PRUNING_LOG_STRIPES`, the block at `height`
+/// belongs to.
+pub fn pruning_stripe_for_height(height: u64) -> u32 {
+    let stripes = 1u64 << PRUNING_LOG_STRIPES;
+    ((height / PRUNING_STRIPE_SIZE) % stripes) as u32 + 1
+}
+
+/// The pruning seed of the nodes keeping the prunable data of the block at
+/// `height`.
+pub fn pruning_seed_for_height(height: u64) -> u32 {
+    make_pruning_seed(pruning_stripe_for_height(height), PRUNING_LOG_STRIPES)
+}
+
+/// Encodes a pruning seed, `stripe` must be in `1..=(1 << log_stripes)`.
+pub fn make_pruning_seed(stripe: u32, log_stripes: u32) -> u32 {
+    assert!(stripe > 0 && stripe <= 1 << log_stripes, "invalid pruning stripe");
+    (log_stripes << PRUNING_SEED_LOG_STRIPES_SHIFT) | ((stripe - 1) & PRUNING_SEED_STRIPE_MASK)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn pruning_seeds() {
+        assert_eq!(pruning_seed_for_height(0), 384);
+        assert_eq!(pruning_seed_for_height(4095), 384);
+        assert_eq!(pruning_seed_for_height(4096), 385);
+        assert_eq!(pruning_seed_for_height(32767), 391);
+        assert_eq!(pruning_seed_for_height(32768), 384);
+        assert_eq!(pruning_seed_for_height(1_000_000), 388);
+
+        assert_eq!(pruning_stripe_for_height(1_000_000), 5);
+        assert_eq!(make_pruning_seed(8, 3), 391);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid pruning stripe")]
+    fn stripe_zero_is_invalid() {
+        make_pruning_seed(0, 3);
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+
+use bytes::{Buf, IntoBuf};
+
+use primitives::H256;
+use varint;
+
+/// Tag of the zero padding, it takes the rest of the extra.
+pub const TX_EXTRA_TAG_PADDING: u8 = 0x00;
+/// Tag of the transaction public key.
+pub const TX_EXTRA_TAG_PUBKEY: u8 = 0x01;
+/// Tag of the nonce, arbitrary data such as the payment id.
+pub const TX_EXTRA_NONCE: u8 = 0x02;
+/// Tag of the merge mining data.
+pub const TX_EXTRA_MERGE_MINING_TAG: u8 = 0x03;
+/// Tag of the per output public keys.
+pub const TX_EXTRA_TAG_ADDITIONAL_PUBKEYS: u8 = 0x04;
+/// Tag written by the MinerGate pool.
+pub const TX_EXTRA_MYSTERIOUS_MINERGATE_TAG: u8 = 0xde;
+
+/// Largest padding allowed.
+pub const TX_EXTRA_PADDING_MAX_COUNT: usize = 255;
+/// Largest nonce allowed.
+pub const TX_EXTRA_NONCE_MAX_COUNT: usize = 255;
+
+/// First nonce byte of a clear 32 byte payment id.
+pub const TX_EXTRA_NONCE_PAYMENT_ID: u8 = 0x00;
+/// First nonce byte of an encrypted 8 byte payment id.
+pub const TX_EXTRA_NONCE_ENCRYPTED_PAYMENT_ID: u8 = 0x01;
+
+/// A field of the transaction extra.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ExtraField {
+    /// Number of padding bytes, the tag included.
+    Padding(usize),
+    PubKey(H256),
+    Nonce(Vec<u8>),
+    MergeMining(Vec<u8>),
+    AdditionalPubKeys(Vec<H256>),
+    MysteriousMinergate(Vec<u8>),
+}
+
+/// A payment id carried in the extra nonce.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PaymentId {
+    /// A clear payment id.
+    Long([u8; 32]),
+    /// A payment id encrypted for the receiver.
+    Short([u8; 8]),
+}
+
+impl PaymentId {
+    /// The payment id of an extra nonce, if it holds a well formed one.
+    pub fn from_nonce(nonce: &[u8]) -> Option<PaymentId> {
+        match nonce.split_first() {
+            Some((&TX_EXTRA_NONCE_PAYMENT_ID, id)) if id.len() == 32 => {
+                let mut long = [0; 32];
+                long.copy_from_slice(id);
+                Some(PaymentId::Long(long))
+            }
+            Some((&TX_EXTRA_NONCE_ENCRYPTED_PAYMENT_ID, id)) if id.len() == 8 => {
+                let mut short = [0; 8];
+                short.copy_from_slice(id);
+                Some(PaymentId::Short(short))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses the fields of a transaction extra.
+///
+/// Parsing stops at the first unknown or malformed field, the fields
+/// before it are returned.
+pub fn parse_extra(extra: &[u8]) -> Vec<ExtraField> {
+    let mut buf = extra.into_buf();
+    let mut fields = Vec::new();
+
+    while buf.has_remaining() {
+        match parse_field(&mut buf) {
+            Some(field) => fields.push(field),
+            None => break,
+        }
+    }
+
+    fields
+}
+
+fn parse_field<B: Buf>(buf: &mut B) -> Option<ExtraField> {
+    let field = match buf.get_u8() {
+        TX_EXTRA_TAG_PADDING => {
+            let count = buf.remaining() + 1;
+            if count > TX_EXTRA_PADDING_MAX_COUNT || buf.bytes().iter().any(|&b| b != 0) {
+                return None;
+            }
+
+            buf.advance(count - 1);
+            ExtraField::Padding(count)
+        }
+        TX_EXTRA_TAG_PUBKEY => ExtraField::PubKey(read_hash(buf)?),
+        TX_EXTRA_NONCE => {
+            ExtraField::Nonce(varint::read_bytes(buf, TX_EXTRA_NONCE_MAX_COUNT).ok()?)
+        }
+        TX_EXTRA_MERGE_MINING_TAG => {
+            ExtraField::MergeMining(varint::read_bytes(buf, buf.remaining()).ok()?)
+        }
+        TX_EXTRA_TAG_ADDITIONAL_PUBKEYS => {
+            let count = varint::read(buf).ok()?;
+            if count > (buf.remaining() / 32) as u64 {
+                return None;
+            }
+
+            let mut keys = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                keys.push(read_hash(buf)?);
+            }
+            ExtraField::AdditionalPubKeys(keys)
+        }
+        TX_EXTRA_MYSTERIOUS_MINERGATE_TAG => {
+            ExtraField::MysteriousMinergate(varint::read_bytes(buf, buf.remaining()).ok()?)
+        }
+        _ => return None,
+    };
+
+    Some(field)
+}
+
+fn read_hash<B: Buf>(buf: &mut B) -> Option<H256> {
+    if buf.remaining() < 32 {
+        return None;
+    }
+
+    let mut hash = [0; 32];
+    buf.copy_to_slice(&mut hash);
+    Some(H256::from(hash))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn fields() {
+        let mut extra = vec![TX_EXTRA_TAG_PUBKEY];
+        extra.extend_from_slice(&[0x11; 32]);
+        extra.extend_from_slice(&[TX_EXTRA_NONCE, 3, 0xaa, 0xbb, 0xcc]);
+        extra.extend_from_slice(&[TX_EXTRA_TAG_ADDITIONAL_PUBKEYS, 2]);
+        extra.extend_from_slice(&[0x22; 64]);
+        extra.extend_from_slice(&[TX_EXTRA_TAG_PADDING, 0, 0]);
+
+        assert_eq!(parse_extra(&extra),
+                   vec![ExtraField::PubKey(H256::from([0x11; 32])),
+                        ExtraField::Nonce(vec![0xaa, 0xbb, 0xcc]),
+                        ExtraField::AdditionalPubKeys(vec![H256::from([0x22; 32]),
+                                                           H256::from([0x22; 32])]),
+                        ExtraField::Padding(3)]);
+    }
+
+    #[test]
+    fn malformed_fields_stop_parsing() {
+        let mut extra = vec![TX_EXTRA_NONCE, 1, 0xaa];
+        extra.extend_from_slice(&[TX_EXTRA_TAG_PUBKEY, 0x11, 0x11]);
+        assert_eq!(parse_extra(&extra), vec![ExtraField::Nonce(vec![0xaa])]);
+
+        assert_eq!(parse_extra(&[0x7f, 0x00]), vec![]);
+        assert_eq!(parse_extra(&[TX_EXTRA_TAG_PADDING, 0, 1]), vec![]);
+        assert_eq!(parse_extra(&[TX_EXTRA_TAG_ADDITIONAL_PUBKEYS, 0xff, 0xff, 0x03]),
+                   vec![]);
+    }
+}
@@ -6,11 +6,18 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+mod extra;
+mod rct_signature;
 mod transaction;
 mod transaction_prefix;
 
-pub use self::transaction::{Transaction, SignatureType};
-pub use self::transaction_prefix::TransactionPrefix;
+pub use self::extra::{ExtraField, PaymentId, parse_extra};
+pub use self::rct_signature::{ATOMS, RctType, EcdhTuple, BoroSig, RangeSig, Bulletproof, MgSig,
+                              RctSignatureBase, RctSignaturePrunable, RctSignature};
+pub use self::transaction::{Transaction, SignatureType, SignatureScheme};
+pub use self::transaction_prefix::{MAX_BLOCK_NUMBER, LOCKED_TX_ALLOWED_DELTA_BLOCKS,
+                                    LOCKED_TX_ALLOWED_DELTA_SECONDS, TransactionPrefix,
+                                    is_unlocked};
 
 mod tx_in;
 mod tx_in_gen;
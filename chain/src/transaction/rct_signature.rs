@@ -0,0 +1,549 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use bytes::BytesMut;
+
+use primitives::{H256, H256_LENGTH};
+use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
+use varint;
+
+/// Number of keys in a borromean signature and a range proof.
+pub const ATOMS: usize = 64;
+
+/// Length of the encrypted amount on `RctType::Bulletproof2` signatures.
+pub const SHORT_AMOUNT_LENGTH: usize = 8;
+
+/// The type of a RingCT signature.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum RctType {
+    Null,
+    Full,
+    Simple,
+    Bulletproof,
+    Bulletproof2,
+}
+
+impl RctType {
+    pub fn from_u8(v: u8) -> Option<RctType> {
+        match v {
+            0 => Some(RctType::Null),
+            1 => Some(RctType::Full),
+            2 => Some(RctType::Simple),
+            3 => Some(RctType::Bulletproof),
+            4 => Some(RctType::Bulletproof2),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match *self {
+            RctType::Null => 0,
+            RctType::Full => 1,
+            RctType::Simple => 2,
+            RctType::Bulletproof => 3,
+            RctType::Bulletproof2 => 4,
+        }
+    }
+
+    /// Does this type use bulletproofs instead of borromean range proofs?
+    pub fn is_bulletproof(&self) -> bool {
+        match *self {
+            RctType::Bulletproof | RctType::Bulletproof2 => true,
+            _ => false,
+        }
+    }
+
+    /// Does this type have one MLSAG per input?
+    pub fn is_simple(&self) -> bool {
+        match *self {
+            RctType::Simple | RctType::Bulletproof | RctType::Bulletproof2 => true,
+            _ => false,
+        }
+    }
+}
+
+impl Default for RctType {
+    fn default() -> RctType {
+        RctType::Null
+    }
+}
+
+/// Encrypted mask and amount of an output.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct EcdhTuple {
+    pub mask: H256,
+    pub amount: H256,
+}
+
+/// A borromean signature.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct BoroSig {
+    pub s0: Vec<H256>,
+    pub s1: Vec<H256>,
+    pub ee: H256,
+}
+
+impl Deserialize for BoroSig {
+    fn deserialize(deserializer: &mut DeserializerStream) -> Result<Self, Error> {
+        let s0 = get_keys(deserializer, ATOMS)?;
+        let s1 = get_keys(deserializer, ATOMS)?;
+        let ee = deserializer.get_deserializable()?;
+
+        Ok(BoroSig { s0, s1, ee })
+    }
+}
+
+impl Serialize for BoroSig {
+    fn serialize(&self, mut serializer: SerializerStream) {
+        assert_eq!(self.s0.len(), ATOMS, "invalid borromean signature");
+        assert_eq!(self.s1.len(), ATOMS, "invalid borromean signature");
+
+        put_keys(&mut serializer, &self.s0);
+        put_keys(&mut serializer, &self.s1);
+        serializer.put_serializable(&self.ee);
+    }
+
+    fn len(&self) -> usize {
+        (ATOMS * 2 + 1) * H256_LENGTH
+    }
+}
+
+/// A borromean range proof.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct RangeSig {
+    pub asig: BoroSig,
+    pub ci: Vec<H256>,
+}
+
+impl Deserialize for RangeSig {
+    fn deserialize(deserializer: &mut DeserializerStream) -> Result<Self, Error> {
+        let asig = deserializer.get_deserializable()?;
+        let ci = get_keys(deserializer, ATOMS)?;
+
+        Ok(RangeSig { asig, ci })
+    }
+}
+
+impl Serialize for RangeSig {
+    fn serialize(&self, mut serializer: SerializerStream) {
+        assert_eq!(self.ci.len(), ATOMS, "invalid range proof");
+
+        serializer.put_serializable(&self.asig);
+        put_keys(&mut serializer, &self.ci);
+    }
+
+    fn len(&self) -> usize {
+        self.asig.len() + ATOMS * H256_LENGTH
+    }
+}
+
+/// A bulletproof range proof.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct Bulletproof {
+    pub big_a: H256,
+    pub big_s: H256,
+    pub t1: H256,
+    pub t2: H256,
+    pub taux: H256,
+    pub mu: H256,
+    pub l: Vec<H256>,
+    pub r: Vec<H256>,
+    pub a: H256,
+    pub b: H256,
+    pub t: H256,
+}
+
+impl Deserialize for Bulletproof {
+    fn deserialize(deserializer: &mut DeserializerStream) -> Result<Self, Error> {
+        let big_a = deserializer.get_deserializable()?;
+        let big_s = deserializer.get_deserializable()?;
+        let t1 = deserializer.get_deserializable()?;
+        let t2 = deserializer.get_deserializable()?;
+        let taux = deserializer.get_deserializable()?;
+        let mu = deserializer.get_deserializable()?;
+
+        let l_length = deserializer.get_u64_varint()? as usize;
+        let l = get_keys(deserializer, l_length)?;
+
+        let r_length = deserializer.get_u64_varint()? as usize;
+        let r = get_keys(deserializer, r_length)?;
+
+        let a = deserializer.get_deserializable()?;
+        let b = deserializer.get_deserializable()?;
+        let t = deserializer.get_deserializable()?;
+
+        Ok(Bulletproof {
+               big_a,
+               big_s,
+               t1,
+               t2,
+               taux,
+               mu,
+               l,
+               r,
+               a,
+               b,
+               t,
+           })
+    }
+}
+
+impl Serialize for Bulletproof {
+    fn serialize(&self, mut serializer: SerializerStream) {
+        serializer.put_serializable(&self.big_a);
+        serializer.put_serializable(&self.big_s);
+        serializer.put_serializable(&self.t1);
+        serializer.put_serializable(&self.t2);
+        serializer.put_serializable(&self.taux);
+        serializer.put_serializable(&self.mu);
+
+        serializer.put_u64_varint(self.l.len() as u64);
+        put_keys(&mut serializer, &self.l);
+
+        serializer.put_u64_varint(self.r.len() as u64);
+        put_keys(&mut serializer, &self.r);
+
+        serializer.put_serializable(&self.a);
+        serializer.put_serializable(&self.b);
+        serializer.put_serializable(&self.t);
+    }
+
+    fn len(&self) -> usize {
+        let mut sum = 9 * H256_LENGTH;
+        sum += varint::length(self.l.len()) + self.l.len() * H256_LENGTH;
+        sum += varint::length(self.r.len()) + self.r.len() * H256_LENGTH;
+        sum
+    }
+}
+
+/// A MLSAG ring signature.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct MgSig {
+    pub ss: Vec<Vec<H256>>,
+    pub cc: H256,
+}
+
+impl MgSig {
+    /// Reads a `MgSig` of `rows` by `columns` keys.
+    pub fn read(deserializer: &mut DeserializerStream,
+                rows: usize,
+                columns: usize)
+                -> Result<MgSig, Error> {
+        let mut ss = Vec::with_capacity(rows);
+        for _ in 0..rows {
+            ss.push(get_keys(deserializer, columns)?);
+        }
+
+        let cc = deserializer.get_deserializable()?;
+
+        Ok(MgSig { ss, cc })
+    }
+}
+
+impl Serialize for MgSig {
+    fn serialize(&self, mut serializer: SerializerStream) {
+        for row in self.ss.iter() {
+            put_keys(&mut serializer, row);
+        }
+
+        serializer.put_serializable(&self.cc);
+    }
+
+    fn len(&self) -> usize {
+        let keys = self.ss.iter().fold(0, |sum, row| sum + row.len());
+        (keys + 1) * H256_LENGTH
+    }
+}
+
+/// The part of a RingCT signature that is always kept.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct RctSignatureBase {
+    pub rct_type: RctType,
+    pub txn_fee: u64,
+    /// Only present on `RctType::Simple` signatures.
+    pub pseudo_outs: Vec<H256>,
+    pub ecdh_info: Vec<EcdhTuple>,
+    pub out_pk: Vec<H256>,
+}
+
+impl RctSignatureBase {
+    /// Reads a `RctSignatureBase` for a transaction with the given
+    /// number of inputs and outputs.
+    pub fn read(deserializer: &mut DeserializerStream,
+                inputs: usize,
+                outputs: usize)
+                -> Result<RctSignatureBase, Error> {
+        let rct_type = deserializer.get_u8()?;
+        let rct_type = RctType::from_u8(rct_type)
+            .ok_or_else(|| Error::custom(format!("invalid ringct type ({})", rct_type)))?;
+
+        if rct_type == RctType::Null {
+            return Ok(RctSignatureBase::default());
+        }
+
+        let txn_fee = deserializer.get_u64_varint()?;
+
+        let pseudo_outs = if rct_type == RctType::Simple {
+            get_keys(deserializer, inputs)?
+        } else {
+            Vec::new()
+        };
+
+        let mut ecdh_info = Vec::with_capacity(outputs);
+        for _ in 0..outputs {
+            if rct_type == RctType::Bulletproof2 {
+                let mut amount = H256::new();
+                let short_amount = deserializer.get_blob(SHORT_AMOUNT_LENGTH)?;
+                amount.0[..SHORT_AMOUNT_LENGTH].copy_from_slice(&short_amount);
+
+                ecdh_info.push(EcdhTuple {
+                                   mask: H256::new(),
+                                   amount,
+                               });
+            } else {
+                let mask = deserializer.get_deserializable()?;
+                let amount = deserializer.get_deserializable()?;
+
+                ecdh_info.push(EcdhTuple { mask, amount });
+            }
+        }
+
+        let out_pk = get_keys(deserializer, outputs)?;
+
+        Ok(RctSignatureBase {
+               rct_type,
+               txn_fee,
+               pseudo_outs,
+               ecdh_info,
+               out_pk,
+           })
+    }
+}
+
+impl Serialize for RctSignatureBase {
+    fn serialize(&self, mut serializer: SerializerStream) {
+        serializer.put_u8(self.rct_type.as_u8());
+
+        if self.rct_type == RctType::Null {
+            return;
+        }
+
+        serializer.put_u64_varint(self.txn_fee);
+
+        if self.rct_type == RctType::Simple {
+            put_keys(&mut serializer, &self.pseudo_outs);
+        }
+
+        for ecdh in self.ecdh_info.iter() {
+            if self.rct_type == RctType::Bulletproof2 {
+                serializer.put_blob(&ecdh.amount.as_bytes()[..SHORT_AMOUNT_LENGTH]);
+            } else {
+                serializer.put_serializable(&ecdh.mask);
+                serializer.put_serializable(&ecdh.amount);
+            }
+        }
+
+        put_keys(&mut serializer, &self.out_pk);
+    }
+
+    fn len(&self) -> usize {
+        if self.rct_type == RctType::Null {
+            return 1;
+        }
+
+        let mut sum = 1 + varint::length(self.txn_fee);
+
+        if self.rct_type == RctType::Simple {
+            sum += self.pseudo_outs.len() * H256_LENGTH;
+        }
+
+        sum += self.ecdh_info.len() *
+               if self.rct_type == RctType::Bulletproof2 {
+                   SHORT_AMOUNT_LENGTH
+               } else {
+                   2 * H256_LENGTH
+               };
+
+        sum += self.out_pk.len() * H256_LENGTH;
+        sum
+    }
+}
+
+/// The part of a RingCT signature that can be pruned once verified.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct RctSignaturePrunable {
+    pub range_sigs: Vec<RangeSig>,
+    pub bulletproofs: Vec<Bulletproof>,
+    pub mgs: Vec<MgSig>,
+    /// Only present on bulletproof signatures.
+    pub pseudo_outs: Vec<H256>,
+}
+
+/// A RingCT signature.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct RctSignature {
+    pub base: RctSignatureBase,
+    pub p: RctSignaturePrunable,
+}
+
+impl RctSignature {
+    /// Reads a `RctSignature` for a transaction with the given number of
+    /// inputs, outputs and ring size minus one (`mixin`).
+    pub fn read(deserializer: &mut DeserializerStream,
+                inputs: usize,
+                outputs: usize,
+                mixin: usize)
+                -> Result<RctSignature, Error> {
+        let base = RctSignatureBase::read(deserializer, inputs, outputs)?;
+        let rct_type = base.rct_type;
+
+        let mut p = RctSignaturePrunable::default();
+        if rct_type == RctType::Null {
+            return Ok(RctSignature { base, p });
+        }
+
+        if rct_type.is_bulletproof() {
+            let nbp = if rct_type == RctType::Bulletproof2 {
+                deserializer.get_u64_varint()? as usize
+            } else {
+                deserializer.get_u32()? as usize
+            };
+
+            if nbp > outputs {
+                return Err(Error::custom(format!("too many bulletproofs ({})", nbp)));
+            }
+
+            for _ in 0..nbp {
+                p.bulletproofs.push(deserializer.get_deserializable()?);
+            }
+        } else {
+            for _ in 0..outputs {
+                p.range_sigs.push(deserializer.get_deserializable()?);
+            }
+        }
+
+        let (mg_elements, columns) = if rct_type.is_simple() {
+            (inputs, 2)
+        } else {
+            (1, inputs + 1)
+        };
+
+        for _ in 0..mg_elements {
+            p.mgs.push(MgSig::read(deserializer, mixin + 1, columns)?);
+        }
+
+        if rct_type.is_bulletproof() {
+            p.pseudo_outs = get_keys(deserializer, inputs)?;
+        }
+
+        Ok(RctSignature { base, p })
+    }
+
+    /// Hash of the base part of this signature.
+    pub fn base_hash(&self) -> H256 {
+        let mut bytes = BytesMut::with_capacity(self.base.len());
+        self.base.serialize(SerializerStream::new(&mut bytes));
+        H256::fast_hash(bytes)
+    }
+
+    /// Hash of the prunable part of this signature, zero on
+    /// `RctType::Null` signatures.
+    pub fn prunable_hash(&self) -> H256 {
+        if self.base.rct_type == RctType::Null {
+            return H256::new();
+        }
+
+        let mut bytes = BytesMut::with_capacity(self.prunable_len());
+        self.serialize_prunable(SerializerStream::new(&mut bytes));
+        H256::fast_hash(bytes)
+    }
+
+    fn serialize_prunable(&self, mut serializer: SerializerStream) {
+        let rct_type = self.base.rct_type;
+        if rct_type == RctType::Null {
+            return;
+        }
+
+        if rct_type.is_bulletproof() {
+            if rct_type == RctType::Bulletproof2 {
+                serializer.put_u64_varint(self.p.bulletproofs.len() as u64);
+            } else {
+                serializer.put_u32(self.p.bulletproofs.len() as u32);
+            }
+
+            for bp in self.p.bulletproofs.iter() {
+                serializer.put_serializable(bp);
+            }
+        } else {
+            for range_sig in self.p.range_sigs.iter() {
+                serializer.put_serializable(range_sig);
+            }
+        }
+
+        for mg in self.p.mgs.iter() {
+            serializer.put_serializable(mg);
+        }
+
+        if rct_type.is_bulletproof() {
+            put_keys(&mut serializer, &self.p.pseudo_outs);
+        }
+    }
+
+    fn prunable_len(&self) -> usize {
+        let rct_type = self.base.rct_type;
+        if rct_type == RctType::Null {
+            return 0;
+        }
+
+        let mut sum = 0;
+
+        if rct_type.is_bulletproof() {
+            sum += if rct_type == RctType::Bulletproof2 {
+                varint::length(self.p.bulletproofs.len())
+            } else {
+                4
+            };
+            sum += self.p.bulletproofs.iter().fold(0, |sum, bp| sum + bp.len());
+        } else {
+            sum += self.p.range_sigs.iter().fold(0, |sum, rs| sum + rs.len());
+        }
+
+        sum += self.p.mgs.iter().fold(0, |sum, mg| sum + mg.len());
+
+        if rct_type.is_bulletproof() {
+            sum += self.p.pseudo_outs.len() * H256_LENGTH;
+        }
+
+        sum
+    }
+}
+
+impl Serialize for RctSignature {
+    fn serialize(&self, mut serializer: SerializerStream) {
+        serializer.put_serializable(&self.base);
+        self.serialize_prunable(serializer);
+    }
+
+    fn len(&self) -> usize {
+        self.base.len() + self.prunable_len()
+    }
+}
+
+fn get_keys(deserializer: &mut DeserializerStream, n: usize) -> Result<Vec<H256>, Error> {
+    let mut keys = Vec::new();
+    for _ in 0..n {
+        keys.push(deserializer.get_deserializable()?);
+    }
+    Ok(keys)
+}
+
+fn put_keys(serializer: &mut SerializerStream, keys: &[H256]) {
+    for key in keys.iter() {
+        serializer.put_serializable(key);
+    }
+}
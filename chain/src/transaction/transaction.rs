@@ -6,16 +6,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#[derive(Debug, Default, Clone)]
-pub struct RctSignature;
+use bytes::{Bytes, BytesMut, BufMut};
 
 use keys::{Signature, SIGNATURE_LENGTH};
-use transaction::TransactionPrefix;
-use primitives::H256;
+use transaction::{RctSignature, RctType, TransactionPrefix, TxIn};
+use primitives::{H256, H256_LENGTH};
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream, from_binary, to_binary};
 
 /// A transaction.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Transaction {
     pub prefix: TransactionPrefix,
     pub signature_type: SignatureType,
@@ -29,18 +28,131 @@ impl Transaction {
     pub fn id(&self) -> H256 {
         match self.prefix.version {
             1 => H256::fast_hash(to_binary(self)),
-            2 => unimplemented!(),
+            2 => {
+                let rct_signature = match self.signature_type {
+                    SignatureType::RingCt(ref rct_signature) => rct_signature,
+                    SignatureType::Normal(_) => panic!("signature type doesn't match version"),
+                };
+
+                let mut hashes = BytesMut::with_capacity(H256_LENGTH * 3);
+                hashes.put(self.prefix.hash().as_bytes());
+                hashes.put(rct_signature.base_hash().as_bytes());
+                hashes.put(rct_signature.prunable_hash().as_bytes());
+                H256::fast_hash(hashes)
+            }
             _ => panic!("invalid tx version"),
         }
     }
+
+    /// The blob kept by pruned nodes: the prefix and, on RingCT
+    /// transactions, the base of the signature. The ring signatures, range
+    /// proofs and MLSAGs are dropped.
+    pub fn pruned_blob(&self) -> Bytes {
+        let base_len = match self.signature_type {
+            SignatureType::Normal(_) => 0,
+            SignatureType::RingCt(ref rct_signature) => rct_signature.base.len(),
+        };
+
+        let mut bytes = BytesMut::with_capacity(self.prefix.len() + base_len);
+        self.prefix.serialize(SerializerStream::new(&mut bytes));
+        if let SignatureType::RingCt(ref rct_signature) = self.signature_type {
+            rct_signature.base.serialize(SerializerStream::new(&mut bytes));
+        }
+
+        bytes.freeze()
+    }
+
+    /// Number of inputs.
+    pub fn input_count(&self) -> usize {
+        self.prefix.vin.len()
+    }
+
+    /// Number of outputs.
+    pub fn output_count(&self) -> usize {
+        self.prefix.vout.len()
+    }
+
+    /// The block height declared by a coinbase transaction.
+    ///
+    /// Returns `None` when this isn't a coinbase, that is, unless it has a
+    /// single generation input.
+    pub fn coinbase_height(&self) -> Option<u64> {
+        if self.prefix.vin.len() != 1 {
+            return None;
+        }
+
+        match self.prefix.vin[0] {
+            TxIn::Gen(ref txin) => Some(txin.height()),
+            _ => None,
+        }
+    }
+
+    /// Sum of the output amounts.
+    ///
+    /// Returns `None` when the amounts are hidden by RingCT, or if the sum
+    /// overflows.
+    pub fn explicit_output_sum(&self) -> Option<u64> {
+        match self.signature_scheme() {
+            SignatureScheme::Legacy | SignatureScheme::RctNull => {
+                self.prefix
+                    .vout
+                    .iter()
+                    .fold(Some(0u64),
+                          |sum, txout| sum.and_then(|sum| sum.checked_add(txout.amount)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The signature scheme used by this transaction.
+    pub fn signature_scheme(&self) -> SignatureScheme {
+        match self.signature_type {
+            SignatureType::Normal(_) => SignatureScheme::Legacy,
+            SignatureType::RingCt(ref rct_signature) => {
+                match rct_signature.base.rct_type {
+                    RctType::Null => SignatureScheme::RctNull,
+                    RctType::Full => SignatureScheme::RctFull,
+                    RctType::Simple => SignatureScheme::RctSimple,
+                    RctType::Bulletproof => SignatureScheme::Bulletproof,
+                    RctType::Bulletproof2 => SignatureScheme::Bulletproof2,
+                }
+            }
+        }
+    }
+
+    /// The ring size minus one, as used by RingCT signatures.
+    fn mixin(prefix: &TransactionPrefix) -> usize {
+        match prefix.vin.first() {
+            Some(&TxIn::ToKey(ref txin)) => txin.key_offsets.len().saturating_sub(1),
+            _ => 0,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum SignatureType {
     Normal(Vec<Vec<Signature>>),
     RingCt(RctSignature),
 }
 
+/// The signature scheme of a transaction, used to choose how it's verified.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SignatureScheme {
+    /// Ring signatures of version 1 transactions, used since the genesis block.
+    Legacy,
+    /// RingCT without signatures, used by version 2 coinbase transactions
+    /// since hard fork 4.
+    RctNull,
+    /// RingCT with a single MLSAG for all the inputs, introduced in hard fork 4.
+    RctFull,
+    /// RingCT with a MLSAG for each input, introduced in hard fork 4.
+    RctSimple,
+    /// RingCT with bulletproof range proofs, introduced in hard fork 8.
+    Bulletproof,
+    /// RingCT with compact bulletproofs, introduced in hard fork 10.
+    Bulletproof2,
+}
+
 impl Deserialize for Transaction {
     fn deserialize(deserializer: &mut DeserializerStream) -> Result<Self, Error> {
         let prefix: TransactionPrefix = deserializer.get_deserializable()?;
@@ -65,7 +177,13 @@ impl Deserialize for Transaction {
 
                 SignatureType::Normal(signatures)
             }
-            2 => unimplemented!(),
+            2 => {
+                let mixin = Transaction::mixin(&prefix);
+                let rct_signature =
+                    RctSignature::read(deserializer, prefix.vin.len(), prefix.vout.len(), mixin)?;
+
+                SignatureType::RingCt(rct_signature)
+            }
             n => return Err(Error::custom(format!("invalid transaction version ({})", n))),
         };
 
@@ -92,11 +210,12 @@ impl Serialize for Transaction {
                     }
                 }
             }
-            SignatureType::RingCt(_) => {
+            SignatureType::RingCt(ref rct_signature) => {
                 assert_eq!(self.prefix.version,
                            2,
                            "signature type doesn't match version");
-                unimplemented!()
+
+                serializer.put_serializable(rct_signature);
             }
         }
     }
@@ -110,8 +229,8 @@ impl Serialize for Transaction {
                     sum += sigv.len() * SIGNATURE_LENGTH;
                 }
             }
-            SignatureType::RingCt(_) => {
-                unimplemented!()
+            SignatureType::RingCt(ref rct_signature) => {
+                sum += rct_signature.len();
             }
         }
 
@@ -121,9 +240,12 @@ impl Serialize for Transaction {
 
 #[cfg(test)]
 pub mod tests {
-    use format::to_binary;
+    use format::{Serialize, to_binary};
+    use keys::{KeyImage, Signature};
     use primitives::H256;
-    use transaction::{SignatureType, Transaction, TransactionPrefix, TxInGen, TxOut, TxOutToKey};
+    use transaction::{SignatureScheme, SignatureType, Transaction, TransactionPrefix, TxInGen, TxInToKey, TxOut,
+                      TxOutToKey, RctType, RctSignature, RctSignatureBase, RctSignaturePrunable,
+                      EcdhTuple, BoroSig, RangeSig, Bulletproof, MgSig, ATOMS};
 
     #[test]
     fn mainnet_genesis_tx() {
@@ -164,5 +286,440 @@ pub mod tests {
         let blob = to_binary(&genesis_transaction);
         assert_eq!(&*blob, GENESIS_TX);
         assert_eq!(genesis_transaction.id(), GENESIS_TX_ID);
+        assert_eq!(genesis_transaction.signature_scheme(), SignatureScheme::Legacy);
+        assert_eq!(genesis_transaction.coinbase_height(), Some(0));
+    }
+
+    fn test_key(seed: usize) -> H256 {
+        let mut key = [0u8; 32];
+        for (i, b) in key.iter_mut().enumerate() {
+            *b = (seed.wrapping_mul(31).wrapping_add(i * 7) & 0xff) as u8;
+        }
+        H256(key)
+    }
+
+    fn test_keys(seed: usize, n: usize) -> Vec<H256> {
+        (0..n).map(|i| test_key(seed + i)).collect()
+    }
+
+    fn rct_transaction(rct_type: RctType,
+                       inputs: usize,
+                       outputs: usize,
+                       mixin: usize)
+                       -> Transaction {
+        let vin = (0..inputs)
+            .map(|i| {
+                     TxInToKey {
+                             amount: 0,
+                             key_offsets: (0..mixin as u64 + 1).map(|o| o * 1000 + 1).collect(),
+                             k_image: KeyImage(test_key(i).0),
+                         }
+                         .into()
+                 })
+            .collect();
+
+        let vout = (0..outputs)
+            .map(|i| {
+                     TxOut {
+                         amount: 0,
+                         target: TxOutToKey { key: test_key(100 + i).0.into() }.into(),
+                     }
+                 })
+            .collect();
+
+        let ecdh_info = (0..outputs)
+            .map(|i| if rct_type == RctType::Bulletproof2 {
+                     let mut amount = H256::new();
+                     amount.0[..8].copy_from_slice(&test_key(200 + i).0[..8]);
+                     EcdhTuple {
+                         mask: H256::new(),
+                         amount,
+                     }
+                 } else {
+                     EcdhTuple {
+                         mask: test_key(200 + i),
+                         amount: test_key(300 + i),
+                     }
+                 })
+            .collect();
+
+        let base = RctSignatureBase {
+            rct_type,
+            txn_fee: 2_345_678_900,
+            pseudo_outs: if rct_type == RctType::Simple {
+                test_keys(400, inputs)
+            } else {
+                vec![]
+            },
+            ecdh_info,
+            out_pk: test_keys(500, outputs),
+        };
+
+        let range_sigs = if rct_type.is_bulletproof() {
+            vec![]
+        } else {
+            (0..outputs)
+                .map(|i| {
+                         RangeSig {
+                             asig: BoroSig {
+                                 s0: test_keys(600 + i, ATOMS),
+                                 s1: test_keys(700 + i, ATOMS),
+                                 ee: test_key(800 + i),
+                             },
+                             ci: test_keys(900 + i, ATOMS),
+                         }
+                     })
+                .collect()
+        };
+
+        let bulletproofs = if rct_type.is_bulletproof() {
+            vec![Bulletproof {
+                     big_a: test_key(1000),
+                     big_s: test_key(1001),
+                     t1: test_key(1002),
+                     t2: test_key(1003),
+                     taux: test_key(1004),
+                     mu: test_key(1005),
+                     l: test_keys(1010, 7),
+                     r: test_keys(1020, 7),
+                     a: test_key(1030),
+                     b: test_key(1031),
+                     t: test_key(1032),
+                 }]
+        } else {
+            vec![]
+        };
+
+        let (mg_elements, columns) = if rct_type.is_simple() {
+            (inputs, 2)
+        } else {
+            (1, inputs + 1)
+        };
+
+        let mgs = (0..mg_elements)
+            .map(|i| {
+                     MgSig {
+                         ss: (0..mixin + 1)
+                             .map(|row| test_keys(1100 + i * 100 + row * 10, columns))
+                             .collect(),
+                         cc: test_key(1099 + i),
+                     }
+                 })
+            .collect();
+
+        let p = RctSignaturePrunable {
+            range_sigs,
+            bulletproofs,
+            mgs,
+            pseudo_outs: if rct_type.is_bulletproof() {
+                test_keys(1500, inputs)
+            } else {
+                vec![]
+            },
+        };
+
+        Transaction {
+            prefix: TransactionPrefix {
+                version: 2,
+                unlock_time: 0,
+                vin,
+                vout,
+                extra: vec![1, 2, 3, 4],
+            },
+            signature_type: SignatureType::RingCt(RctSignature { base, p }),
+        }
+    }
+
+    fn assert_round_trip(tx: &Transaction) {
+        let blob = to_binary(tx);
+        assert_eq!(blob.len(), tx.len());
+
+        let decoded = Transaction::from_bytes(&blob).unwrap();
+        assert_eq!(to_binary(&decoded), blob);
+        assert_eq!(decoded.id(), tx.id());
+
+        match (&decoded.signature_type, &tx.signature_type) {
+            (&SignatureType::RingCt(ref a), &SignatureType::RingCt(ref b)) => assert_eq!(a, b),
+            _ => panic!("expected ringct signatures"),
+        }
+    }
+
+    #[test]
+    fn ringct_round_trip() {
+        let rct_types = [RctType::Full,
+                         RctType::Simple,
+                         RctType::Bulletproof,
+                         RctType::Bulletproof2];
+
+        for &rct_type in rct_types.iter() {
+            for &(inputs, outputs, mixin) in [(1, 1, 0), (1, 2, 4), (2, 2, 6), (3, 1, 10)].iter() {
+                assert_round_trip(&rct_transaction(rct_type, inputs, outputs, mixin));
+            }
+        }
+    }
+
+    /// A mainnet `RctType::Bulletproof2` spend with two inputs and two
+    /// outputs.
+    const MAINNET_RCT_TX: &'static str =
+        "02000202000bc6aa98049bf603fcec06bd3ccbad04e807e328b5128f22a63bfb27b6e287e8d594664d5cddd6\
+          c89bc413d1bc607b242203a6eb3180041ff5ae679702000b90e4eb028298a101879110f5bc0383ad03cbfc03\
+          a750e52ace37d112c6064faf7d16e2d07c4cc979dccb858aa9b24e12479e4a2db8350a906ba7a1aec4090200\
+          02ab6d783607d8e712bbd5aad54a412aec890fcdcc1b35bf0ca4a705c2159bfc32000262f4016d5d81ade9e5\
+          55807a24d23d452f08b6400683da599abd7134fb75324a2c0209016631a2dee1d0f51f015fd9bf938cf13279\
+          0bdc5c528037e347828c539e82da6e5921e3d1e6052cb25804d0b7ba81018a4cd5385ca23ff4f6d76dc41b52\
+          54abf579b1856d3fbd04e81ff97c113e318bf7e158fbb0db7adc6ece9c8d4ab94e91f68e9607667a858ddf3e\
+          6890b2835403db6dcc5a1c179a768bcf74d74ace86430176b0056de37f310884e8eed56ee86840f23f842f1d\
+          b52945b2feef98f4b56b3d4407734e4e8d3b117b5fd78f0d94f6059b495f53cf855b3716bbe8614d51727556\
+          c8b2e5c303cffc694257a1e91372de2047c4e12381c1de8df46102cdd84a24692f68ada05d1ffc5122b65558\
+          2c6307141e130a6963198085ddb67d304b0ddde87e62402a3cadcd07a315604607ddf1530bd85685e910aa87\
+          9733549bde0d019edc36326d33edd6ccecc800395b7075e4959779bde803dd787c24bc25d40205071b180152\
+          dded8b0be1f48a6d6f8e97c3f934f866b1b697f73f73fc9f38c5d2082c610732c79b2f69f403f7e2d3123997\
+          39dd8d4225a2914a3020bd88c362271df633e8387b5345b50f11c4f148f76c0c24ca5843580a02fe72d18f47\
+          dcf8d601e28bbef2cd6659e620179adad4dfa5a0c7b712d716c4e630fe40bbfc6184f76c401db4b801a7001f\
+          65a9c11053db919099f9a1a4fe575c6d783e041ee08222a46adb8a1f13b863d95da277b71ccdefbb32f713a1\
+          3b5ac8d041bbbeed9df4ace5a6b730b871ad2fe14141dbb9c816a21fd7fc48cfba4d2cc3e5e5fba29f581c15\
+          07a6a36285a30344790b74d2212dd26178395cd96a18518ead5c59a410baf6ca0b9217865fff207d757bb465\
+          fdb053e8c80b2ec1a966ccc01f49096fb991b65cc160a5070532c47318720fb9a90f187ce53661b6ce1e29d6\
+          ccd2b131324101170bb87ef273f0e73d762e159264f0839c6b3b31f5264499bdeb029c66a7035108a84256ae\
+          c2760e74c2e8e788b7747084da8953aa48696b7a46e6320a9534d6c06ddee1b26671f03ae70a30c76b8fbf26\
+          8da16fbb685f1d3f602668afce2e3eaf089b8758069f398eaeccd01876cef623201dc46dd75f76dc2141a9a2\
+          071b2761eefbf735ca599ed15266acbd0f54f49de38b5c7b3b378c386767383396645778021b30910e6e0693\
+          7d65dac82312968d4f63a0bb28c96b9a09ee1d95c4d3afbb021998a9e290647b51083a80d66e0a8900a89c12\
+          36214f2d7c0080e2c18cdc8c0b4bd66765c3c52a26b90ec549bc8358ff8aaac9ffaecb0f6c915113cc97147b\
+          06c007a1a6bcd536bddec7fba330877d80cb878c7c9f3da81f8eb3bfb07ac7a804d4d58faadfabc3421350b1\
+          4af6500b931209b75813759509e8642574982d680632106041b2687bdaef1c2be67c63a0ccc427bf02dc1ba5\
+          8b153f00f8fabc8c00d99934e3e835291b8fc5a0bd62a3059c22cbd6fc4ae403df254e17cb15f32b0b65a4ed\
+          1f0f5fff37e49417c5fee339c21bd4f1cdcb1c803df8cb4baa11fa210089b61e28fe42e112824ee705f13167\
+          bfd3e6c8d660f2307216f5eea91a6db505b70f38e4b1b72a8f1fa1dd90bf0a47ed9a71e2e11e4f20240c1dac\
+          370f2b18075c6537bb475897fdd90fae360afb0b6b02210c123a3ca8fae31e320639b5e00c91c821abe873c1\
+          aaee2c4ebb87b5ecc670bff65de191e1d8463ecf1367685c0f0d39efc20269e516f29b775060a0c7a1595e15\
+          8e94f64407d8e22b16ff25ed00cd50c46fb95ab5a3ca60a04e222c83d26b11c08a678348c8cdad407a0d841c\
+          0a50e91e896cb4ff873e9fc81c35f4a146f25a64b294c07c6adf4e418a3f590f061a89267deea53d985b4576\
+          b70b95170970321e07c1397b6f7e1ed3d4629e8e0a81019a7c15ae6d252e856c761664862d7fd0620fbbc702\
+          0fcd675fc97dc7310420b0d428093b4a80012f7a46612161ee2eeec8996128876d093f71f954244004aded41\
+          85afeec305d104d3905e54ddfda59ce1f5d56cf078ff10a76b138db90789184149cab60dfb1491943a793e85\
+          c332b4b36f448b63e5f099e7beb11d07005a31ed1658251e9f880466e44c54357781c9cdaf17d48534b062de\
+          482ad94005cbdcf52d6fea2e70c20ecc62a339afbb971e455e38292b78b21393bce982ec06d3e6f3e2789787\
+          7007283f5a9d44ae134efc0ff14a5ce2fbe711403b535413073c9769ddc0474d64643bd2d60f58e001717f05\
+          38e1cc1e6b211c5f06f6ddfa029ec10d7e949673c08cd71713728ff042948c5b75ea2b610f4b4db838696fa4\
+          0e24d750aa75f910948af39de2eba2ff6864daf92004453e4fa5cdae2f553a460f9bc86073dd7d6d2ea0f310\
+          92d28110892d6077dcee3b6293e66867a7ae67c5048df756d253c768debf3989d7643ff8892b7f6f74bf2b36\
+          d01bd0b88760c9b30eb54d02aa5498e7b87e2d027ac2a449318deda9cabf356fcd07f4561e6370db09fbd0d0\
+          81093d93a569f6968b291fb01995415293b7cd997d9367c352c75b230ee70de92efd35302572e9de2809cd8e\
+          25eb9f824cb559107a1b87dd8c365de106cc800350e1284d67555b8db9041dfd44b3d5bec42e2b186b6a72b5\
+          bc3df0470476c234277592f742c3bf3babd4759b115196117883434af1d014ba8aeca028054fb4e8d9dde6a0\
+          e4cca9388a9f4c6283cbf9ae89dd17e4dc2a2f511604b33f0ee6e42e4f9a20b556ebc66e18b6142bfdcd4bf3\
+          792d9659ca5f5ef041f5ba28053e752155263ea79bd65dd94157f9464625e6a2e1b1e6d8ff40b4af4cb38046\
+          06239b8370d69d14f5c9eb463f49b0e796efdf01ddc087cc710bae43968856000fdc3a6408861363a190edbf\
+          2ae3e13d2bc52f87d9f2e31c044698b79f37b75a03199c7585b880df73a362ff40a94ed1d6173998d247a8f7\
+          4e28407ad933e8bb0cd91ee82b723b3f22152a53b3226e52b32b93b397b5b2c386d3468651e602360eba66e5\
+          2a1844aac9bbbe1a7183e97fed0c8b4b649f1551bcb98248dd62ebec087d4020a042085d487c64ae3fcf25e1\
+          1b443ff03eeb8345b6d45d5304fbba83030bce1bbe499a7f5aefff31fef134b3c2c85b2fd16e6a26133cdbf0\
+          5069299f007e627036d5ce0536e10546ec3b0719c373e00792f45fa78ff62d543e204d9a0f54a2b1c934a146\
+          3620a5c789ed600792ace37bc0f79c84399018acd073e86309f16a4ee382baad3e98425be3dcea1fceb47e56\
+          d237a49a125a360f7eb56b0305632f3877c17e62204e5a2c2017a934be9e532c5d7fd14ed71c4a2d3947621d\
+          03373796d7ffd6c77a73a06e3cbb61e1d872fb012c9ea0327fb65c4ffa46f02507d4db98bd434a7e921130e8\
+          846e697da226cc85568aa83f95cdfc4ccbfbff8ab0653000211ee7438364596b53793f2dfc4705f6a491190b\
+          35960f9aec1ffaad8a";
+
+    #[test]
+    fn mainnet_rct_tx() {
+        let blob = hex::decode(MAINNET_RCT_TX).unwrap();
+        let tx = Transaction::from_bytes(&blob).unwrap();
+
+        assert_eq!(&*to_binary(&tx), &blob[..]);
+        assert_eq!(tx.len(), blob.len());
+        assert_eq!(hex::encode(tx.prefix.hash().as_bytes()),
+                   "3b50349180b4a60e55187507746eabb7bee0de6b74168eac8720a449da28613b");
+        assert_eq!(hex::encode(tx.id().as_bytes()),
+                   "5a420317e377d3d95b652fb93e65cfe97ef7d89e04be329a2ca94e73ec57b74e");
+        assert_eq!(tx.signature_scheme(), SignatureScheme::Bulletproof2);
+        assert_eq!(tx.input_count(), 2);
+        assert_eq!(tx.output_count(), 2);
+
+        let rct_signature = match tx.signature_type {
+            SignatureType::RingCt(ref rct_signature) => rct_signature,
+            SignatureType::Normal(_) => panic!("expected a ringct signature"),
+        };
+        let pruned_len = tx.prefix.len() + rct_signature.base.len();
+        assert_eq!(&*tx.pruned_blob(), &blob[..pruned_len]);
+    }
+
+    /// A two output `RctType::Bulletproof2` spend with a ring of 11, laid
+    /// out field by field as monerod writes it.
+    #[test]
+    fn bulletproof2_blob_layout() {
+        let key = |n: u8| [n; 32];
+
+        // version, unlock time and a single txin_to_key of 11 ring members.
+        let mut prefix = vec![0x02, 0x00, 0x01, 0x02, 0x00, 0x0b];
+        prefix.extend_from_slice(&[0xd0, 0xfd, 0xff, 0x01]);
+        prefix.extend(1..11);
+        prefix.extend_from_slice(&key(0x11));
+        // two outputs of amount 0 to a key.
+        prefix.push(0x02);
+        for n in 0x21..0x23 {
+            prefix.extend_from_slice(&[0x00, 0x02]);
+            prefix.extend_from_slice(&key(n));
+        }
+        // extra, the tx public key.
+        prefix.extend_from_slice(&[0x21, 0x01]);
+        prefix.extend_from_slice(&key(0x31));
+
+        // type, fee, the 8 byte amounts and the output commitments.
+        let mut base = vec![0x04, 0xf0, 0x84, 0xe1, 0x0e];
+        base.extend_from_slice(&[0x41; 8]);
+        base.extend_from_slice(&[0x42; 8]);
+        base.extend_from_slice(&key(0x51));
+        base.extend_from_slice(&key(0x52));
+
+        // one bulletproof: A, S, T1, T2, taux, mu, L, R, a, b and t.
+        let mut prunable = vec![0x01];
+        for n in 0x61..0x67 {
+            prunable.extend_from_slice(&key(n));
+        }
+        for &first in &[0x70, 0x80] {
+            prunable.push(0x07);
+            for n in first..first + 7 {
+                prunable.extend_from_slice(&key(n));
+            }
+        }
+        for n in 0x67..0x6a {
+            prunable.extend_from_slice(&key(n));
+        }
+        // the MLSAG of the input, two columns for each ring member and cc,
+        // then the pseudo output.
+        for row in 0..11 {
+            prunable.extend_from_slice(&key(0xa0 + row));
+            prunable.extend_from_slice(&key(0xb0 + row));
+        }
+        prunable.extend_from_slice(&key(0x9f));
+        prunable.extend_from_slice(&key(0xc1));
+
+        let blob = [&prefix[..], &base[..], &prunable[..]].concat();
+        let tx = Transaction::from_bytes(&blob).unwrap();
+        assert_eq!(&*to_binary(&tx), &blob[..]);
+        assert_eq!(tx.signature_scheme(), SignatureScheme::Bulletproof2);
+        assert_eq!(tx.input_count(), 1);
+        assert_eq!(tx.output_count(), 2);
+
+        let hashes = [H256::fast_hash(&prefix), H256::fast_hash(&base), H256::fast_hash(&prunable)]
+            .iter()
+            .fold(Vec::new(), |mut hashes, hash| {
+                hashes.extend_from_slice(hash.as_bytes());
+                hashes
+            });
+        assert_eq!(tx.id(), H256::fast_hash(hashes));
+        assert_eq!(&*tx.pruned_blob(), &[&prefix[..], &base[..]].concat()[..]);
+    }
+
+    #[test]
+    fn ringct_null_round_trip() {
+        let tx = Transaction {
+            prefix: TransactionPrefix {
+                version: 2,
+                unlock_time: 60,
+                vin: vec![TxInGen { height: 1_700_000 }.into()],
+                vout: vec![TxOut {
+                               amount: 4_800_000_000_000,
+                               target: TxOutToKey { key: test_key(1).0.into() }.into(),
+                           }],
+                extra: vec![],
+            },
+            signature_type: SignatureType::RingCt(RctSignature::default()),
+        };
+
+        let blob = to_binary(&tx);
+        assert_eq!(blob.last(), Some(&0));
+        assert_round_trip(&tx);
+    }
+
+    #[test]
+    fn ringct_id_covers_prunable_data() {
+        let tx = rct_transaction(RctType::Bulletproof, 1, 2, 6);
+        let mut other = tx.clone();
+        if let SignatureType::RingCt(ref mut rct) = other.signature_type {
+            rct.p.mgs[0].cc = test_key(4242);
+        }
+
+        assert!(tx.id() != other.id());
+    }
+
+    #[test]
+    fn signature_scheme() {
+        let schemes = [(RctType::Full, SignatureScheme::RctFull),
+                       (RctType::Simple, SignatureScheme::RctSimple),
+                       (RctType::Bulletproof, SignatureScheme::Bulletproof),
+                       (RctType::Bulletproof2, SignatureScheme::Bulletproof2)];
+
+        for &(rct_type, scheme) in schemes.iter() {
+            assert_eq!(rct_transaction(rct_type, 1, 2, 6).signature_scheme(), scheme);
+        }
+
+        let coinbase = Transaction {
+            prefix: TransactionPrefix {
+                version: 2,
+                unlock_time: 60,
+                vin: vec![TxInGen { height: 1_700_000 }.into()],
+                vout: vec![],
+                extra: vec![],
+            },
+            signature_type: SignatureType::RingCt(RctSignature::default()),
+        };
+        assert_eq!(coinbase.signature_scheme(), SignatureScheme::RctNull);
+    }
+
+    #[test]
+    fn pruned_blob() {
+        let tx = rct_transaction(RctType::Bulletproof, 2, 2, 10);
+        let pruned = tx.pruned_blob();
+        let rct_signature = match tx.signature_type {
+            SignatureType::RingCt(ref rct_signature) => rct_signature,
+            SignatureType::Normal(_) => unreachable!(),
+        };
+
+        assert!(pruned.len() < to_binary(&tx).len());
+        assert_eq!(pruned.len(), tx.prefix.len() + rct_signature.base.len());
+        assert_eq!(&pruned[..tx.prefix.len()], &to_binary(&tx.prefix)[..]);
+        assert_eq!(H256::fast_hash(&pruned[tx.prefix.len()..]), rct_signature.base_hash());
+
+        let mut tx = rct_transaction(RctType::Full, 1, 2, 0);
+        tx.prefix.version = 1;
+        tx.signature_type = SignatureType::Normal(vec![vec![Signature::new()]]);
+        assert_eq!(tx.pruned_blob(), to_binary(&tx.prefix));
+        assert!(tx.pruned_blob().len() < to_binary(&tx).len());
+    }
+
+    #[test]
+    fn counts_and_explicit_output_sum() {
+        let mut tx = rct_transaction(RctType::Full, 1, 2, 0);
+        tx.prefix.version = 1;
+        tx.prefix.vout[0].amount = 1_000;
+        tx.prefix.vout[1].amount = 234;
+        tx.signature_type = SignatureType::Normal(vec![]);
+        assert_eq!(tx.input_count(), 1);
+        assert_eq!(tx.output_count(), 2);
+        assert_eq!(tx.explicit_output_sum(), Some(1_234));
+
+        tx.prefix.vout[1].amount = u64::max_value();
+        assert_eq!(tx.explicit_output_sum(), None);
+
+        let tx = rct_transaction(RctType::Bulletproof, 3, 2, 6);
+        assert_eq!(tx.input_count(), 3);
+        assert_eq!(tx.output_count(), 2);
+        assert_eq!(tx.explicit_output_sum(), None);
+    }
+
+    #[test]
+    fn coinbase_height() {
+        let mut coinbase = rct_transaction(RctType::Null, 0, 1, 0);
+        coinbase.prefix.vin = vec![TxInGen { height: 1000 }.into()];
+        assert_eq!(coinbase.coinbase_height(), Some(1000));
+
+        coinbase.prefix.vin.push(TxInGen { height: 1001 }.into());
+        assert_eq!(coinbase.coinbase_height(), None);
+
+        let tx = rct_transaction(RctType::Simple, 2, 2, 4);
+        assert_eq!(tx.coinbase_height(), None);
     }
 }
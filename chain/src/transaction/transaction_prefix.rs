@@ -6,12 +6,22 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use transaction::{TxIn, TxOut};
+use bytes::Bytes;
+
+use transaction::{ExtraField, PaymentId, TxIn, TxOut, parse_extra};
 use primitives::H256;
-use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream, to_binary};
+use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream, from_binary,
+             to_binary};
+
+/// Unlock times below this are block heights, the rest are timestamps.
+pub const MAX_BLOCK_NUMBER: u64 = 500_000_000;
+/// How many blocks early a height locked output may be spent.
+pub const LOCKED_TX_ALLOWED_DELTA_BLOCKS: u64 = 1;
+/// How many seconds early a time locked output may be spent.
+pub const LOCKED_TX_ALLOWED_DELTA_SECONDS: u64 = 120;
 
 /// Transaction prefix.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TransactionPrefix {
     pub version: u8,
     pub unlock_time: u64,
@@ -21,8 +31,59 @@ pub struct TransactionPrefix {
 }
 
 impl TransactionPrefix {
+    /// Parses a prefix blob, without the signatures that follow it in a
+    /// transaction blob.
+    pub fn from_bytes<T: AsRef<[u8]>>(v: T) -> Result<Self, Error> {
+        from_binary::<Self>(v.as_ref())
+    }
+
+    /// The prefix blob, the part of the transaction covered by `hash`.
+    pub fn to_bytes(&self) -> Bytes {
+        to_binary(self)
+    }
+
     pub fn hash(&self) -> H256 {
-        H256::fast_hash(to_binary(self))
+        H256::fast_hash(self.to_bytes())
+    }
+
+    /// The block height or timestamp until which the outputs are locked,
+    /// see `is_unlocked`.
+    pub fn unlock_time(&self) -> u64 {
+        self.unlock_time
+    }
+
+    /// Are the output amounts hidden by RingCT? A zero amount then doesn't
+    /// mean the output has no value. Coinbase outputs are never hidden.
+    pub fn is_amount_hidden(&self) -> bool {
+        let is_coinbase = match self.vin.as_slice() {
+            &[TxIn::Gen(_)] => true,
+            _ => false,
+        };
+
+        self.version >= 2 && !is_coinbase
+    }
+
+    /// The first well formed payment id found in the extra nonces, a
+    /// `PaymentId::Short` is still encrypted.
+    pub fn payment_id(&self) -> Option<PaymentId> {
+        parse_extra(&self.extra)
+            .into_iter()
+            .filter_map(|field| match field {
+                            ExtraField::Nonce(nonce) => PaymentId::from_nonce(&nonce),
+                            _ => None,
+                        })
+            .next()
+    }
+}
+
+/// Can outputs with `unlock_time` be spent in the next block? `current_height`
+/// is the blockchain height (the number of blocks) and `current_time` a
+/// timestamp in seconds since epoch.
+pub fn is_unlocked(unlock_time: u64, current_height: u64, current_time: u64) -> bool {
+    if unlock_time < MAX_BLOCK_NUMBER {
+        current_height.saturating_add(LOCKED_TX_ALLOWED_DELTA_BLOCKS) > unlock_time
+    } else {
+        current_time.saturating_add(LOCKED_TX_ALLOWED_DELTA_SECONDS) >= unlock_time
     }
 }
 
@@ -96,3 +157,147 @@ impl Serialize for TransactionPrefix {
         sum
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use keys::KeyImage;
+    use transaction::{TxInGen, TxInToKey, TxOutToKey};
+
+    // a v1 coinbase has no signatures, so the mainnet genesis transaction
+    // blob is its prefix blob.
+    const GENESIS_PREFIX: &'static [u8] =
+        &[0x01, 0x3c, 0x01, 0xff, 0x00, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x03, 0x02,
+          0x9b, 0x2e, 0x4c, 0x02, 0x81, 0xc0, 0xb0, 0x2e, 0x7c, 0x53, 0x29, 0x1a, 0x94, 0xd1,
+          0xd0, 0xcb, 0xff, 0x88, 0x83, 0xf8, 0x02, 0x4f, 0x51, 0x42, 0xee, 0x49, 0x4f, 0xfb,
+          0xbd, 0x08, 0x80, 0x71, 0x21, 0x01, 0x77, 0x67, 0xaa, 0xfc, 0xde, 0x9b, 0xe0, 0x0d,
+          0xcf, 0xd0, 0x98, 0x71, 0x5e, 0xbc, 0xf7, 0xf4, 0x10, 0xda, 0xeb, 0xc5, 0x82, 0xfd,
+          0xa6, 0x9d, 0x24, 0xa2, 0x8e, 0x9d, 0x0b, 0xc8, 0x90, 0xd1];
+
+    #[test]
+    fn genesis_prefix() {
+        let prefix = TransactionPrefix::from_bytes(GENESIS_PREFIX).unwrap();
+        assert_eq!(prefix.version, 1);
+        assert_eq!(prefix.unlock_time, 60);
+        assert_eq!(prefix.vin, vec![TxInGen { height: 0 }.into()]);
+        assert_eq!(prefix.vout.len(), 1);
+        assert_eq!(prefix.vout[0].amount, 17592186044415);
+        assert_eq!(prefix.extra.len(), 33);
+        assert_eq!(&*prefix.to_bytes(), GENESIS_PREFIX);
+    }
+
+    #[test]
+    fn prefix_round_trip() {
+        let prefix = TransactionPrefix {
+            version: 2,
+            unlock_time: 1_500_000_000,
+            vin: vec![TxInToKey {
+                          amount: 0,
+                          key_offsets: vec![1234, 10, 300],
+                          k_image: KeyImage([0x11; 32]),
+                      }
+                      .into()],
+            vout: vec![TxOut {
+                           amount: 0,
+                           target: TxOutToKey { key: [0x22; 32].into() }.into(),
+                       },
+                       TxOut {
+                           amount: 0,
+                           target: TxOutToKey { key: [0x33; 32].into() }.into(),
+                       }],
+            extra: vec![0x01; 33],
+        };
+
+        let blob = prefix.to_bytes();
+        assert_eq!(blob.len(), prefix.len());
+        assert_eq!(TransactionPrefix::from_bytes(&blob).unwrap(), prefix);
+    }
+
+    #[test]
+    fn amount_hidden() {
+        let prefix = TransactionPrefix::from_bytes(GENESIS_PREFIX).unwrap();
+        assert!(!prefix.is_amount_hidden());
+        assert_eq!(prefix.vout[0].amount(), 17592186044415);
+
+        let mut prefix = TransactionPrefix {
+            version: 2,
+            unlock_time: 0,
+            vin: vec![TxInToKey {
+                          amount: 0,
+                          key_offsets: vec![1234],
+                          k_image: KeyImage([0x11; 32]),
+                      }
+                      .into()],
+            vout: vec![TxOut {
+                           amount: 0,
+                           target: TxOutToKey { key: [0x22; 32].into() }.into(),
+                       }],
+            extra: vec![],
+        };
+        assert!(prefix.is_amount_hidden());
+
+        // v2 coinbase amounts are in plaintext.
+        prefix.vin = vec![TxInGen { height: 1_500_000 }.into()];
+        assert!(!prefix.is_amount_hidden());
+    }
+
+    #[test]
+    fn payment_id() {
+        let prefix_with_extra = |extra: Vec<u8>| {
+            TransactionPrefix {
+                version: 2,
+                unlock_time: 0,
+                vin: vec![],
+                vout: vec![],
+                extra,
+            }
+        };
+
+        let mut extra = vec![0x01];
+        extra.extend_from_slice(&[0x11; 32]);
+        assert_eq!(prefix_with_extra(extra.clone()).payment_id(), None);
+
+        let mut long = extra.clone();
+        long.extend_from_slice(&[0x02, 33, 0x00]);
+        long.extend_from_slice(&[0x42; 32]);
+        assert_eq!(prefix_with_extra(long).payment_id(),
+                   Some(PaymentId::Long([0x42; 32])));
+
+        // a nonce of the wrong length is skipped for the next one.
+        let mut short = extra.clone();
+        short.extend_from_slice(&[0x02, 3, 0x01, 0x42, 0x42]);
+        short.extend_from_slice(&[0x02, 9, 0x01]);
+        short.extend_from_slice(&[0x24; 8]);
+        short.extend_from_slice(&[0x02, 9, 0x01]);
+        short.extend_from_slice(&[0x25; 8]);
+        assert_eq!(prefix_with_extra(short).payment_id(),
+                   Some(PaymentId::Short([0x24; 8])));
+    }
+
+    #[test]
+    fn height_lock() {
+        assert!(!is_unlocked(1000, 999, 0));
+        assert!(is_unlocked(1000, 1000, 0));
+        assert!(is_unlocked(1000, 1001, u64::max_value() - LOCKED_TX_ALLOWED_DELTA_SECONDS));
+    }
+
+    #[test]
+    fn timestamp_lock() {
+        let unlock_time = 1_500_000_000;
+        assert!(!is_unlocked(unlock_time, u64::max_value() - 1, unlock_time - 121));
+        assert!(is_unlocked(unlock_time, 0, unlock_time - 120));
+        assert!(is_unlocked(unlock_time, 0, unlock_time));
+    }
+
+    #[test]
+    fn zero_is_always_unlocked() {
+        let prefix = TransactionPrefix {
+            version: 2,
+            unlock_time: 0,
+            vin: vec![],
+            vout: vec![],
+            extra: vec![],
+        };
+        assert!(is_unlocked(prefix.unlock_time(), 0, 0));
+    }
+}
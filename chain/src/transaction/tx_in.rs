@@ -15,7 +15,7 @@ const TO_SCRIPT: u8 = 0;
 const TO_SCRIPT_HASH: u8 = 1;
 
 /// Transaction input.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum TxIn {
     Gen(TxInGen),
     ToKey(TxInToKey),
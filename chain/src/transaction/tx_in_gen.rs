@@ -8,11 +8,18 @@
 
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TxInGen {
     pub height: u64,
 }
 
+impl TxInGen {
+    /// Height of the block this coinbase input belongs to.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+}
+
 impl Deserialize for TxInGen {
     fn deserialize(deserializer: &mut DeserializerStream) -> Result<Self, Error> {
         deserializer
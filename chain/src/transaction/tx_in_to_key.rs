@@ -9,13 +9,52 @@
 use keys::KeyImage;
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TxInToKey {
     pub amount: u64,
     pub key_offsets: Vec<u64>,
     pub k_image: KeyImage,
 }
 
+impl TxInToKey {
+    /// The absolute output indices of the ring members, `key_offsets` are
+    /// relative to the previous one.
+    ///
+    /// Overflowing sums wrap around, check `is_valid_offsets` first.
+    pub fn absolute_offsets(&self) -> Vec<u64> {
+        let mut absolute = 0u64;
+        self.key_offsets
+            .iter()
+            .map(|offset| {
+                     absolute = absolute.wrapping_add(*offset);
+                     absolute
+                 })
+            .collect()
+    }
+
+    /// Are the key offsets well formed? The ring must not be empty, the
+    /// absolute indices must be strictly increasing and must not overflow.
+    pub fn is_valid_offsets(&self) -> bool {
+        if self.key_offsets.is_empty() {
+            return false;
+        }
+
+        let mut absolute = self.key_offsets[0];
+        for offset in self.key_offsets[1..].iter() {
+            if *offset == 0 {
+                return false;
+            }
+
+            absolute = match absolute.checked_add(*offset) {
+                Some(absolute) => absolute,
+                None => return false,
+            };
+        }
+
+        true
+    }
+}
+
 impl Deserialize for TxInToKey {
     fn deserialize(deserializer: &mut DeserializerStream) -> Result<Self, Error> {
         let amount = deserializer.get_u64_varint()?;
@@ -61,3 +100,31 @@ impl Serialize for TxInToKey {
         sum
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn to_key(key_offsets: Vec<u64>) -> TxInToKey {
+        TxInToKey {
+            amount: 0,
+            key_offsets,
+            k_image: KeyImage([0; 32]),
+        }
+    }
+
+    #[test]
+    fn absolute_offsets() {
+        let input = to_key(vec![1234, 10, 1, 300]);
+        assert_eq!(input.absolute_offsets(), vec![1234, 1244, 1245, 1545]);
+        assert!(input.is_valid_offsets());
+    }
+
+    #[test]
+    fn invalid_offsets() {
+        assert!(!to_key(vec![]).is_valid_offsets());
+        assert!(!to_key(vec![5, 0]).is_valid_offsets());
+        assert!(!to_key(vec![u64::max_value(), 1]).is_valid_offsets());
+        assert!(to_key(vec![0, 1]).is_valid_offsets());
+    }
+}
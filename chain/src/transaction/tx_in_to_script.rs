@@ -9,7 +9,7 @@
 use primitives::H256;
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TxInToScript {
     pub prev: H256,
     pub prevout: u64,
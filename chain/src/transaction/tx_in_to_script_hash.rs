@@ -10,7 +10,7 @@ use primitives::H256;
 use transaction::TxOutToScript;
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TxInToScriptHash {
     pub prev: H256,
     pub prevout: u64,
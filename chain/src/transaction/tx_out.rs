@@ -6,16 +6,34 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use primitives::H256;
 use transaction::TxOutTarget;
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
 /// Transaction output.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TxOut {
     pub amount: u64,
     pub target: TxOutTarget,
 }
 
+impl TxOut {
+    /// The stored amount, zero when it's hidden by RingCT, see
+    /// `TransactionPrefix::is_amount_hidden`.
+    pub fn amount(&self) -> u64 {
+        self.amount
+    }
+
+    /// The one-time public key of to-key outputs, `None` for the other
+    /// targets.
+    pub fn stealth_public_key(&self) -> Option<H256> {
+        match self.target {
+            TxOutTarget::ToKey(ref target) => Some(H256::from(target.key.0)),
+            _ => None,
+        }
+    }
+}
+
 impl Deserialize for TxOut {
     fn deserialize(deserializer: &mut DeserializerStream) -> Result<Self, Error> {
         let amount = deserializer.get_u64_varint()?;
@@ -37,3 +55,41 @@ impl Serialize for TxOut {
         varint::length(self.amount) + self.target.len()
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use keys::PublicKey;
+    use transaction::{TxOutToKey, TxOutToScript};
+
+    use super::*;
+
+    #[test]
+    fn amount() {
+        let txout = TxOut {
+            amount: 17592186044415,
+            target: TxOutToKey { key: PublicKey([0x42; 32]) }.into(),
+        };
+        assert_eq!(txout.amount(), 17592186044415);
+    }
+
+    #[test]
+    fn stealth_public_key() {
+        let to_key = TxOut {
+            amount: 0,
+            target: TxOutToKey { key: PublicKey([0x42; 32]) }.into(),
+        };
+        assert!(to_key.target.is_to_key());
+        assert_eq!(to_key.stealth_public_key(), Some(H256::from([0x42; 32])));
+
+        let to_script = TxOut {
+            amount: 0,
+            target: TxOutToScript {
+                    keys: vec![PublicKey([0x42; 32])],
+                    script: vec![],
+                }
+                .into(),
+        };
+        assert!(!to_script.target.is_to_key());
+        assert!(to_script.stealth_public_key().is_none());
+    }
+}
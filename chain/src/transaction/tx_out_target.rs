@@ -14,13 +14,23 @@ const TO_SCRIPT: u8 = 0x0;
 const TO_SCRIPT_HASH: u8 = 0x1;
 
 /// Transaction output target.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum TxOutTarget {
     ToKey(TxOutToKey),
     ToScript(TxOutToScript),
     ToScriptHash(TxOutToScriptHash),
 }
 
+impl TxOutTarget {
+    /// Is this a to-key target?
+    pub fn is_to_key(&self) -> bool {
+        match *self {
+            TxOutTarget::ToKey(_) => true,
+            _ => false,
+        }
+    }
+}
+
 impl From<TxOutToKey> for TxOutTarget {
     fn from(target: TxOutToKey) -> TxOutTarget {
         TxOutTarget::ToKey(target)
@@ -9,7 +9,7 @@
 use keys::{PublicKey, PUBLIC_KEY_LENGTH};
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TxOutToKey {
     pub key: PublicKey,
 }
@@ -9,7 +9,7 @@
 use primitives::{H256, H256_LENGTH};
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct TxOutToScriptHash {
     pub hash: H256,
 }
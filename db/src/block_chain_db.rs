@@ -16,12 +16,14 @@
 
 use std::path::Path;
 use std::collections::LinkedList;
+use std::time::{Duration, Instant};
 
 use sanakirja;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use bytes::{Buf, IntoBuf};
 
 use chain::IndexedBlock;
+use chain::transaction::{Transaction as Tx, TxIn};
 use format::to_binary;
 use primitives::H256;
 use storage::{BestBlock, BlockChain, BlockProvider, IndexedBlockProvider, BlockRef, Store,
@@ -29,15 +31,28 @@ use storage::{BestBlock, BlockChain, BlockProvider, IndexedBlockProvider, BlockR
 
 use kv::{Key, Value, KeyValue, KeyState, KeyValueDatabase, DiskDb, Transaction};
 use error::Error;
+use recent_key_images::{RecentKeyImages, RECENT_KEY_IMAGES_WINDOW};
 
 const KEY_BEST_BLOCK_HEIGHT: &'static str = "best_block_height";
 const KEY_BEST_BLOCK_ID: &'static str = "best_block_id";
+const KEY_DB_VERSION: &'static str = "db_version";
+
+/// The version of the database schema written by this code.
+pub const DB_VERSION: u64 = 1;
 
 /// A blockchain database.
+///
+/// Reads never observe a torn chain tip: a block is canonized with a
+/// single atomic write to the underlying database, done while holding the
+/// best block lock, and the best block is updated before releasing it. So
+/// the best block always refers to a canonized block, and a read done
+/// within `with_best_block` sees either the chain before a concurrent
+/// `canonize` or the chain after it.
 #[derive(Debug)]
 pub struct BlockChainDatabase<DB: KeyValueDatabase> {
     db: DB,
     best_block: RwLock<BestBlock>,
+    recent_key_images: Mutex<RecentKeyImages>,
 }
 
 impl BlockChainDatabase<DiskDb> {
@@ -49,15 +64,99 @@ impl BlockChainDatabase<DiskDb> {
             Err(sanakirja::Error::Poison) => return Err(Error::AlreadyOpen),
         };
 
-        let best_block = RwLock::new(Self::read_best_block(&db).unwrap_or_default());
-
-        Ok(BlockChainDatabase { db, best_block })
+        Self::new(db)
     }
 }
 
 impl<DB> BlockChainDatabase<DB>
     where DB: KeyValueDatabase
 {
+    /// Creates a blockchain database on top of `db`, checking and upgrading
+    /// its schema version.
+    pub fn new(db: DB) -> Result<BlockChainDatabase<DB>, Error> {
+        Self::check_version(&db)?;
+
+        let best_block = RwLock::new(Self::read_best_block(&db).unwrap_or_default());
+
+        let window = Duration::from_secs(RECENT_KEY_IMAGES_WINDOW);
+        let recent_key_images = Mutex::new(RecentKeyImages::new(window));
+
+        Ok(BlockChainDatabase {
+               db,
+               best_block,
+               recent_key_images,
+           })
+    }
+
+    /// Notes a key image spent by a new block or pool transaction, see
+    /// `likely_spent`.
+    pub fn insert_recent_key_image(&self, key_image: H256) {
+        self.recent_key_images
+            .lock()
+            .insert(key_image, Instant::now());
+    }
+
+    /// Was the key image spent recently? It doesn't look into the store,
+    /// so older key images aren't reported.
+    pub fn likely_spent(&self, key_image: &H256) -> bool {
+        self.recent_key_images.lock().likely_spent(key_image)
+    }
+
+    /// Forgets the recent key images older than `RECENT_KEY_IMAGES_WINDOW`,
+    /// it's done on every insertion too.
+    pub fn evict_recent_key_images(&self) {
+        self.recent_key_images.lock().evict(Instant::now());
+    }
+
+    /// The schema version of the database, `None` on a new database.
+    pub fn read_version(db: &DB) -> Option<u64> {
+        db.get(&Key::Meta(KEY_DB_VERSION))
+            .expect("db value to be fine")
+            .into_option()
+            .and_then(Value::as_meta)
+            .map(|version| {
+                     if version.len() != 8 {
+                         panic!("Inconsistent DB");
+                     }
+
+                     version.into_buf().get_u64_le()
+                 })
+    }
+
+    /// Writes the current version on new databases, migrates older ones
+    /// and refuses the ones written by a newer version.
+    fn check_version(db: &DB) -> Result<(), Error> {
+        let mut version = match Self::read_version(db) {
+            Some(version) => version,
+            None => return Self::write_version(db, DB_VERSION),
+        };
+
+        if version > DB_VERSION {
+            return Err(Error::UnsupportedVersion(version, DB_VERSION));
+        }
+
+        while version < DB_VERSION {
+            Self::migrate(db, version)?;
+            version += 1;
+            Self::write_version(db, version)?;
+        }
+
+        Ok(())
+    }
+
+    /// Upgrades the schema from version `from` to `from + 1`.
+    fn migrate(_db: &DB, from: u64) -> Result<(), Error> {
+        // Version 1 is the first schema, migrations from later versions
+        // go here.
+        Err(Error::UnsupportedVersion(from, DB_VERSION))
+    }
+
+    fn write_version(db: &DB, version: u64) -> Result<(), Error> {
+        let mut update = Transaction::new();
+        update.insert(KeyValue::Meta(KEY_DB_VERSION, to_binary(&version)));
+        db.write(update).map_err(Error::DatabaseError)
+    }
+
     fn read_best_block(db: &DB) -> Option<BestBlock> {
         let best_height = db.get(&Key::Meta(KEY_BEST_BLOCK_HEIGHT))
             .map(KeyState::into_option)
@@ -88,6 +187,49 @@ impl<DB> BlockChainDatabase<DB>
     }
 
     pub fn insert(&self, block: IndexedBlock) -> Result<(), Error> {
+        self.insert_with(block, Transaction::new())
+    }
+
+    /// Stores a block along with its transactions, in the order of its
+    /// `tx_hashes`. The key images spent by them are stored and noted as
+    /// recent, see `likely_spent`.
+    pub fn insert_with_transactions(&self,
+                                    block: IndexedBlock,
+                                    transactions: &[Tx])
+                                    -> Result<(), Error> {
+        if transactions.len() != block.raw.tx_hashes.len() ||
+           transactions
+               .iter()
+               .zip(block.raw.tx_hashes.iter())
+               .any(|(tx, tx_hash)| tx.id() != *tx_hash) {
+            return Err(Error::TransactionMismatch);
+        }
+
+        let key_images: Vec<H256> = transactions
+            .iter()
+            .flat_map(|tx| tx.prefix.vin.iter())
+            .filter_map(|txin| match *txin {
+                            TxIn::ToKey(ref txin) => Some(H256(txin.k_image.0)),
+                            _ => None,
+                        })
+            .collect();
+
+        let mut update = Transaction::new();
+        for key_image in key_images.iter() {
+            update.insert(KeyValue::KeyImage(key_image.clone()));
+        }
+        self.insert_with(block, update)?;
+
+        let now = Instant::now();
+        let mut recent_key_images = self.recent_key_images.lock();
+        for key_image in key_images {
+            recent_key_images.insert(key_image, now);
+        }
+        Ok(())
+    }
+
+    /// Stores a block along with the operations in `update`.
+    fn insert_with(&self, block: IndexedBlock, mut update: Transaction) -> Result<(), Error> {
         if self.contains_block(block.id().clone().into()) {
             return Ok(());
         }
@@ -97,18 +239,19 @@ impl<DB> BlockChainDatabase<DB>
             return Err(Error::UnknownParent);
         }
 
-        let mut update = Transaction::new();
         update.insert(KeyValue::Block(block.id().clone(), block.raw.clone()));
 
-        // TODO: transactions?
-
         self.db.write(update).map_err(Error::DatabaseError)
     }
 
     fn contains_block(&self, block_ref: BlockRef) -> bool {
         self.resolve_id(block_ref)
-            .and_then(|id| self.get(Key::Block(id)))
-            .is_some()
+            .map(|id| {
+                     self.db
+                         .contains(&Key::Block(id))
+                         .expect("db value to be fine")
+                 })
+            .unwrap_or(false)
     }
 
     pub fn canonize(&self, id: &H256) -> Result<(), Error> {
@@ -145,6 +288,26 @@ impl<DB> BlockChainDatabase<DB>
         Ok(())
     }
 
+    /// Runs `f` with the best block, no block is canonized until it
+    /// returns, so the reads done by `f` are consistent with that tip.
+    pub fn with_best_block<F, T>(&self, f: F) -> T
+        where F: FnOnce(&BestBlock) -> T
+    {
+        // recursive, so `f` can use `best_block` or `height` without
+        // deadlocking behind a waiting `canonize`.
+        let best_block = self.best_block.read_recursive();
+        f(&*best_block)
+    }
+
+    /// Makes the written blocks durable, e.g. before taking a snapshot.
+    pub fn flush(&self) -> Result<(), Error> {
+        self.db.flush().map_err(Error::DatabaseError)
+    }
+
+    /// Reclaims the space used by deleted or overwritten values.
+    pub fn compact(&self) -> Result<(), Error> {
+        self.db.compact().map_err(Error::DatabaseError)
+    }
 
     fn resolve_id(&self, block_ref: BlockRef) -> Option<H256> {
         match block_ref {
@@ -170,15 +333,23 @@ impl<DB> Store for BlockChainDatabase<DB>
     where DB: KeyValueDatabase
 {
     fn best_block(&self) -> BestBlock {
-        self.best_block.read().clone()
+        self.best_block.read_recursive().clone()
     }
 
     fn height(&self) -> u64 {
-        self.best_block.read().height + 1
+        self.best_block.read_recursive().height + 1
     }
 
     fn short_chain_history(&self) -> LinkedList<H256> {
-        let height = self.height();
+        self.with_best_block(|best_block| self.short_chain_history_at(best_block.height + 1))
+    }
+}
+
+impl<DB> BlockChainDatabase<DB>
+    where DB: KeyValueDatabase
+{
+    /// The short chain history of the first `height` canonized blocks.
+    fn short_chain_history_at(&self, height: u64) -> LinkedList<H256> {
         if height == 0 {
             return LinkedList::new();
         }
@@ -242,3 +413,126 @@ impl<DB> IndexedBlockProvider for BlockChainDatabase<DB>
                       })
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    use block_chain_store::BlockChainStore;
+    use block_chain_store::tests::{test_block, test_chain};
+    use chain::transaction::{TransactionPrefix, TxInToKey, SignatureType};
+    use keys::{KeyImage, Signature};
+    use kv::MemoryDb;
+
+    use super::*;
+
+    #[test]
+    fn reads_see_a_consistent_tip() {
+        let db = Arc::new(BlockChainDatabase::new(MemoryDb::new()).unwrap());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut reads = 0;
+                    while !done.load(Ordering::Acquire) || reads == 0 {
+                        db.with_best_block(|best_block| {
+                            if best_block.id.is_zero() {
+                                return;
+                            }
+                            assert_eq!(db.block_id(best_block.height), Some(best_block.id.clone()));
+                            assert!(db.block_id(best_block.height + 1).is_none());
+                            assert_eq!(db.height(), best_block.height + 1);
+                        });
+
+                        let best_block = db.best_block();
+                        if !best_block.id.is_zero() {
+                            assert_eq!(db.block_id(best_block.height), Some(best_block.id));
+                        }
+                        reads += 1;
+                    }
+                })
+            })
+            .collect();
+
+        let mut prev_id = H256::default();
+        for block in test_chain(200) {
+            let block: IndexedBlock = block.into();
+            prev_id = block.id().clone();
+            db.insert(block).unwrap();
+            db.canonize(&prev_id).unwrap();
+        }
+        done.store(true, Ordering::Release);
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(db.best_block().height, 199);
+        let history = db.short_chain_history();
+        assert_eq!(history.front(), Some(&prev_id));
+        assert_eq!(history.back(), db.block_id(0).as_ref());
+    }
+
+    #[test]
+    fn inserted_transactions_spend_their_key_images() {
+        let db = BlockChainDatabase::new(MemoryDb::new()).unwrap();
+        let key_image = H256([0x11; 32]);
+        let tx = Tx {
+            prefix: TransactionPrefix {
+                version: 1,
+                unlock_time: 0,
+                vin: vec![TxInToKey {
+                                  amount: 1,
+                                  key_offsets: vec![1],
+                                  k_image: KeyImage(key_image.0),
+                              }
+                              .into()],
+                vout: vec![],
+                extra: vec![],
+            },
+            signature_type: SignatureType::Normal(vec![vec![Signature::new()]]),
+        };
+
+        let mut block = test_block(0);
+        match db.insert_with_transactions(block.clone().into(), &[tx.clone()]) {
+            Err(Error::TransactionMismatch) => { /* happy path */ }
+            other => panic!("expected a transaction mismatch, got {:?}", other),
+        }
+
+        block.tx_hashes = vec![tx.id()];
+        assert!(!db.likely_spent(&key_image));
+        db.insert_with_transactions(block.into(), &[tx]).unwrap();
+        assert!(db.likely_spent(&key_image));
+        assert!(db.db.contains_key_image(key_image));
+    }
+
+    #[test]
+    fn new_database_is_versioned() {
+        let db = BlockChainDatabase::new(MemoryDb::new()).unwrap();
+        assert_eq!(BlockChainDatabase::read_version(&db.db), Some(DB_VERSION));
+
+        // reopening keeps the version.
+        let db = BlockChainDatabase::new(db.db).unwrap();
+        assert_eq!(BlockChainDatabase::read_version(&db.db), Some(DB_VERSION));
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let db = MemoryDb::new();
+        let mut update = Transaction::new();
+        update.insert(KeyValue::Meta(KEY_DB_VERSION, to_binary(&(DB_VERSION + 1))));
+        db.write(update).unwrap();
+
+        match BlockChainDatabase::new(db) {
+            Err(Error::UnsupportedVersion(found, supported)) => {
+                assert_eq!(found, DB_VERSION + 1);
+                assert_eq!(supported, DB_VERSION);
+            }
+            _ => panic!("expected an unsupported version error"),
+        }
+    }
+}
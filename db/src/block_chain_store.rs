@@ -0,0 +1,349 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use bytes::Bytes;
+use chain::{Block, BlockHeader};
+use primitives::H256;
+
+use kv::{Key, Value, KeyValue, KeyValueDatabase, Transaction};
+use error::Error;
+
+/// A typed interface over the raw key-value operations.
+pub trait BlockChainStore {
+    /// Stores a block at the given height.
+    fn insert_block(&self, height: u64, block: &Block) -> Result<(), Error>;
+
+    /// Stores a block at the given height along with the chain's
+    /// cumulative difficulty up to it, the parent's cumulative difficulty
+    /// must be stored already.
+    fn insert_block_with_difficulty(&self,
+                                    height: u64,
+                                    block: &Block,
+                                    difficulty: u64)
+                                    -> Result<(), Error>;
+
+    /// Stores a spent key image.
+    fn insert_key_image(&self, key_image: H256) -> Result<(), Error>;
+
+    /// Get the header of the block with the given id.
+    fn block_header_by_hash(&self, id: H256) -> Option<BlockHeader>;
+
+    /// Get the block with the given id.
+    fn block_by_hash(&self, id: H256) -> Option<Block>;
+
+    /// Same as `block_header_by_hash`, but borrows the id.
+    fn get_block(&self, hash: &H256) -> Option<BlockHeader>;
+
+    /// Get a raw metadata value by name.
+    fn get_meta(&self, name: &'static str) -> Option<Bytes>;
+
+    /// Get the height of the block with the given id.
+    fn height_of(&self, id: H256) -> Option<u64>;
+
+    /// Get the id of the block at the given height.
+    fn block_hash_at(&self, height: u64) -> Option<H256>;
+
+    /// Get the cumulative difficulty of the chain up to the given height.
+    fn cumulative_difficulty_at(&self, height: u64) -> Option<u64>;
+
+    /// Get the id and height of the block containing the given transaction.
+    fn block_of_tx(&self, tx_hash: H256) -> Option<(H256, u64)>;
+
+    /// Has this key image been spent?
+    fn contains_key_image(&self, key_image: H256) -> bool;
+
+    /// Is the block with the given id stored?
+    fn contains_block(&self, id: H256) -> bool;
+}
+
+impl<DB> BlockChainStore for DB
+    where DB: KeyValueDatabase
+{
+    fn insert_block(&self, height: u64, block: &Block) -> Result<(), Error> {
+        self.write(block_update(height, block))
+            .map_err(Error::DatabaseError)
+    }
+
+    fn insert_block_with_difficulty(&self,
+                                    height: u64,
+                                    block: &Block,
+                                    difficulty: u64)
+                                    -> Result<(), Error> {
+        let parent_difficulty = match height {
+            0 => 0,
+            height => {
+                match self.cumulative_difficulty_at(height - 1) {
+                    Some(parent_difficulty) => parent_difficulty,
+                    None => return Err(Error::UnknownParent),
+                }
+            }
+        };
+
+        let mut update = block_update(height, block);
+        update.insert(KeyValue::CumulativeDifficulty(height,
+                                                     parent_difficulty.saturating_add(difficulty)));
+
+        self.write(update).map_err(Error::DatabaseError)
+    }
+
+    fn insert_key_image(&self, key_image: H256) -> Result<(), Error> {
+        let mut update = Transaction::new();
+        update.insert(KeyValue::KeyImage(key_image));
+
+        self.write(update).map_err(Error::DatabaseError)
+    }
+
+    fn block_header_by_hash(&self, id: H256) -> Option<BlockHeader> {
+        self.block_by_hash(id).map(|block| block.header)
+    }
+
+    fn block_by_hash(&self, id: H256) -> Option<Block> {
+        get_as(self, Key::Block(id), Value::as_block)
+    }
+
+    fn get_block(&self, hash: &H256) -> Option<BlockHeader> {
+        self.block_header_by_hash(hash.clone())
+    }
+
+    fn get_meta(&self, name: &'static str) -> Option<Bytes> {
+        get_as(self, Key::Meta(name), Value::as_meta)
+    }
+
+    fn height_of(&self, id: H256) -> Option<u64> {
+        get_as(self, Key::BlockHeight(id), Value::as_block_height)
+    }
+
+    fn block_hash_at(&self, height: u64) -> Option<H256> {
+        get_as(self, Key::BlockId(height), Value::as_block_id)
+    }
+
+    fn cumulative_difficulty_at(&self, height: u64) -> Option<u64> {
+        get_as(self,
+               Key::CumulativeDifficulty(height),
+               Value::as_cumulative_difficulty)
+    }
+
+    fn block_of_tx(&self, tx_hash: H256) -> Option<(H256, u64)> {
+        get_as(self, Key::TxBlock(tx_hash), Value::as_tx_block)
+    }
+
+    fn contains_key_image(&self, key_image: H256) -> bool {
+        contains(self, Key::KeyImage(key_image))
+    }
+
+    fn contains_block(&self, id: H256) -> bool {
+        contains(self, Key::Block(id))
+    }
+}
+
+/// The operations storing a block at the given height.
+pub(crate) fn block_update(height: u64, block: &Block) -> Transaction {
+    let id = block.id();
+
+    let mut update = Transaction::new();
+    update.insert(KeyValue::Block(id.clone(), block.clone()));
+    update.insert(KeyValue::BlockHeight(id.clone(), height));
+    update.insert(KeyValue::BlockId(height, id.clone()));
+    for tx_hash in block.tx_hashes.iter() {
+        update.insert(KeyValue::TxBlock(tx_hash.clone(), (id.clone(), height)));
+    }
+    update
+}
+
+fn get<DB: KeyValueDatabase>(db: &DB, key: Key) -> Option<Value> {
+    db.get(&key)
+        .expect("db value to be fine")
+        .into_option()
+}
+
+fn contains<DB: KeyValueDatabase>(db: &DB, key: Key) -> bool {
+    db.contains(&key).expect("db value to be fine")
+}
+
+/// Reads `key` and unwraps the value with the matching `Value::as_*` accessor.
+fn get_as<DB, T, F>(db: &DB, key: Key, as_value: F) -> Option<T>
+    where DB: KeyValueDatabase,
+          F: FnOnce(Value) -> Option<T>
+{
+    get(db, key).and_then(as_value)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chain::transaction::{Transaction, TransactionPrefix, SignatureType};
+    use kv::{MemoryDb, Compression};
+
+    use super::*;
+
+    pub fn test_block(nonce: u32) -> Block {
+        Block {
+            header: BlockHeader {
+                major_version: 1,
+                minor_version: 0,
+                timestamp: 0,
+                prev_id: H256::default(),
+                nonce,
+            },
+            miner_tx: Transaction {
+                prefix: TransactionPrefix {
+                    version: 1,
+                    unlock_time: 0,
+                    vin: vec![],
+                    vout: vec![],
+                    extra: vec![],
+                },
+                signature_type: SignatureType::Normal(vec![]),
+            },
+            tx_hashes: vec![],
+        }
+    }
+
+    /// `length` blocks built with `test_block`, each one the child of the
+    /// previous.
+    pub fn test_chain(length: u32) -> Vec<Block> {
+        let mut prev_id = H256::default();
+        (0..length)
+            .map(|nonce| {
+                let mut block = test_block(nonce);
+                block.header.prev_id = prev_id.clone();
+                prev_id = block.id();
+                block
+            })
+            .collect()
+    }
+
+    #[test]
+    fn insert_block() {
+        let db = MemoryDb::new();
+        let block = test_block(10000);
+        let id = block.id();
+
+        assert!(db.block_header_by_hash(id.clone()).is_none());
+        assert!(db.height_of(id.clone()).is_none());
+        assert!(!db.contains_block(id.clone()));
+
+        db.insert_block(5, &block).unwrap();
+        assert!(db.contains_block(id.clone()));
+
+        let header = db.block_header_by_hash(id.clone()).unwrap();
+        assert_eq!(header.nonce, 10000);
+        assert_eq!(header.major_version, 1);
+        assert_eq!(db.height_of(id.clone()), Some(5));
+        assert_eq!(db.block_hash_at(5), Some(id.clone()));
+        assert!(db.block_hash_at(4).is_none());
+
+        let other = test_block(10001).id();
+        assert!(db.block_header_by_hash(other.clone()).is_none());
+        assert!(db.height_of(other).is_none());
+    }
+
+    #[test]
+    fn tx_block_index() {
+        let db = MemoryDb::new();
+        let first = H256::from([0x01; 32]);
+        let second = H256::from([0x02; 32]);
+        let mut block = test_block(10006);
+        block.tx_hashes = vec![first.clone(), second.clone()];
+        let id = block.id();
+
+        assert!(db.block_of_tx(first.clone()).is_none());
+
+        db.insert_block(3, &block).unwrap();
+        assert_eq!(db.block_of_tx(first), Some((id.clone(), 3)));
+        assert_eq!(db.block_of_tx(second), Some((id, 3)));
+        assert!(db.block_of_tx(H256::from([0x03; 32])).is_none());
+    }
+
+    #[test]
+    fn key_images() {
+        let db = MemoryDb::new();
+        let key_image = H256::from([0x07; 32]);
+
+        assert!(!db.contains_key_image(key_image.clone()));
+        db.insert_key_image(key_image.clone()).unwrap();
+        assert!(db.contains_key_image(key_image));
+        assert!(!db.contains_key_image(H256::from([0x08; 32])));
+    }
+
+    #[test]
+    fn cumulative_difficulty() {
+        let db = MemoryDb::new();
+        let difficulties = [1, 100, 250, 7];
+
+        assert!(db.cumulative_difficulty_at(0).is_none());
+
+        let mut sum = 0;
+        for (height, &difficulty) in difficulties.iter().enumerate() {
+            let block = test_block(20000 + height as u32);
+            db.insert_block_with_difficulty(height as u64, &block, difficulty)
+                .unwrap();
+
+            sum += difficulty;
+            assert_eq!(db.cumulative_difficulty_at(height as u64), Some(sum));
+            assert_eq!(db.block_hash_at(height as u64), Some(block.id()));
+        }
+
+        assert_eq!(db.cumulative_difficulty_at(3), Some(358));
+        assert!(db.cumulative_difficulty_at(4).is_none());
+
+        // the parent must be known.
+        match db.insert_block_with_difficulty(10, &test_block(20010), 1) {
+            Err(Error::UnknownParent) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn typed_getters() {
+        let db = MemoryDb::new();
+        let block = test_block(10004);
+        let id = block.id();
+
+        assert!(db.get_block(&id).is_none());
+        assert!(db.get_meta("version").is_none());
+
+        db.insert_block(0, &block).unwrap();
+        let mut update = ::kv::Transaction::new();
+        update.insert(KeyValue::Meta("version", Bytes::from(&b"\x01"[..])));
+        db.write(update).unwrap();
+
+        assert_eq!(db.get_block(&id).unwrap().nonce, 10004);
+        assert!(db.get_block(&test_block(10005).id()).is_none());
+        assert_eq!(db.get_meta("version"), Some(Bytes::from(&b"\x01"[..])));
+        assert!(db.get_meta("best_block").is_none());
+
+        let mut update = ::kv::Transaction::new();
+        update.delete(Key::Meta("version"));
+        db.write(update).unwrap();
+        assert!(db.get_meta("version").is_none());
+    }
+
+    #[test]
+    fn compressed_and_uncompressed_blocks() {
+        let legacy = test_block(10002);
+        let compressed = test_block(10003);
+
+        let mut db = MemoryDb::new();
+        db.insert_block(0, &legacy).unwrap();
+        db.set_compression(Compression::Lz4);
+        db.insert_block(1, &compressed).unwrap();
+
+        for block in [legacy, compressed].iter() {
+            let header = db.block_header_by_hash(block.id()).unwrap();
+            assert_eq!(header, block.header);
+        }
+    }
+}
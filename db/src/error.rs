@@ -29,4 +29,14 @@ pub enum Error {
     UnknownParent,
     #[fail(display = "can't canonize block")]
     CannotCanonize,
+    #[fail(display = "database version {} isn't supported, the latest supported is {}", _0, _1)]
+    UnsupportedVersion(u64, u64),
+    #[fail(display = "there's no block at height {}", _0)]
+    MissingBlock(u64),
+    #[fail(display = "expected block at height {}, found {}", _0, _1)]
+    HeightGap(u64, u64),
+    #[fail(display = "invalid block export: {}", _0)]
+    InvalidExport(String),
+    #[fail(display = "the transactions don't match the ones of the block")]
+    TransactionMismatch,
 }
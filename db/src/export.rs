@@ -0,0 +1,252 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+
+use std::io::{BufRead, Cursor, Read, Write};
+use std::mem;
+
+use bytes::BytesMut;
+use chain::Block;
+use format::{from_binary, to_binary};
+use varint;
+
+use block_chain_store::{BlockChainStore, block_update};
+use error::Error;
+use kv::{KeyValueDatabase, Transaction};
+
+/// Writes the blocks from `from_height` to `to_height`, both included, to
+/// `writer`, returns the number of blocks written.
+///
+/// Each block is written as its varint height, the varint length of the
+/// block blob and the blob.
+pub fn export_blocks<DB, W>(db: &DB,
+                            from_height: u64,
+                            to_height: u64,
+                            writer: &mut W)
+                            -> Result<u64, Error>
+    where DB: KeyValueDatabase,
+          W: Write
+{
+    let mut exported = 0;
+    for height in from_height..to_height.saturating_add(1) {
+        let block = db.block_hash_at(height)
+            .and_then(|id| db.block_by_hash(id))
+            .ok_or(Error::MissingBlock(height))?;
+        let blob = to_binary(&block);
+
+        let mut record = BytesMut::with_capacity(varint::length(height) +
+                                                 varint::length(blob.len()) +
+                                                 blob.len());
+        varint::write(&mut record, height);
+        varint::write(&mut record, blob.len());
+        record.extend_from_slice(&blob);
+
+        writer.write_all(&record).map_err(Error::Io)?;
+        exported += 1;
+    }
+
+    Ok(exported)
+}
+
+/// Number of blocks `import_blocks` stores in each transaction.
+pub const IMPORT_BATCH_SIZE: u64 = 1000;
+
+/// Reads the blocks written by `export_blocks` and stores them in
+/// transactions of `IMPORT_BATCH_SIZE` blocks, returns the number of blocks
+/// imported.
+///
+/// The records are decoded one at a time and at most one batch is buffered,
+/// so memory use doesn't grow with the size of the export. The heights must
+/// be consecutive, on a gap the blocks before it are stored and
+/// `Error::HeightGap` is returned. On an invalid record the batches already
+/// written are kept.
+pub fn import_blocks<DB, R>(db: &DB, reader: &mut R) -> Result<u64, Error>
+    where DB: KeyValueDatabase,
+          R: BufRead
+{
+    import_batches(db, reader, IMPORT_BATCH_SIZE)
+}
+
+fn import_batches<DB, R>(db: &DB, reader: &mut R, batch_size: u64) -> Result<u64, Error>
+    where DB: KeyValueDatabase,
+          R: BufRead
+{
+    let mut update = Transaction::new();
+    let mut next_height = None;
+    let mut imported = 0;
+    let mut gap = None;
+    let mut blob = Vec::new();
+
+    while !reader.fill_buf().map_err(Error::Io)?.is_empty() {
+        let height = next_varint(reader)?;
+        if let Some(expected) = next_height {
+            if height != expected {
+                gap = Some(Error::HeightGap(expected, height));
+                break;
+            }
+        }
+
+        let length = next_varint(reader)?;
+        blob.clear();
+        Read::take(&mut *reader, length).read_to_end(&mut blob).map_err(Error::Io)?;
+        if (blob.len() as u64) < length {
+            return Err(Error::InvalidExport(format!("block at height {} is truncated", height)));
+        }
+
+        let block: Block = from_binary(&blob)
+            .map_err(|e| Error::InvalidExport(format!("block at height {}: {}", height, e)))?;
+
+        update.operations.extend(block_update(height, &block).operations);
+        next_height = Some(height.checked_add(1).ok_or_else(|| {
+            Error::InvalidExport(format!("block at height {} is past the last height", height))
+        })?);
+        imported += 1;
+
+        if imported % batch_size == 0 {
+            let batch = mem::replace(&mut update, Transaction::new());
+            db.write(batch).map_err(Error::DatabaseError)?;
+        }
+    }
+
+    db.write(update).map_err(Error::DatabaseError)?;
+
+    match gap {
+        Some(gap) => Err(gap),
+        None => Ok(imported),
+    }
+}
+
+/// Takes the bytes of the next varint from `reader` and decodes them.
+fn next_varint<R: BufRead>(reader: &mut R) -> Result<u64, Error> {
+    let mut bytes = Vec::with_capacity(varint::const_length(u64::MAX));
+    while bytes.len() < bytes.capacity() {
+        let byte = match reader.fill_buf().map_err(Error::Io)?.first() {
+            Some(&byte) => byte,
+            None => break,
+        };
+        reader.consume(1);
+        bytes.push(byte);
+
+        if byte & varint::MOST_SIGNIFICANT_BIT == 0 {
+            break;
+        }
+    }
+
+    read_varint(&mut Cursor::new(&bytes[..]))
+}
+
+fn read_varint(buf: &mut Cursor<&[u8]>) -> Result<u64, Error> {
+    varint::read(buf).map_err(|e| match e {
+        varint::ReadError::Truncated => Error::InvalidExport("truncated varint".to_owned()),
+        e => Error::InvalidExport(format!("{}", e)),
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use block_chain_store::tests::{test_block, test_chain};
+    use kv::MemoryDb;
+
+    use super::*;
+
+    #[test]
+    fn export_import_round_trip() {
+        let source = MemoryDb::new();
+        let chain = test_chain(4);
+        for (height, block) in chain.iter().enumerate() {
+            source.insert_block(height as u64, block).unwrap();
+        }
+
+        let mut blob = Vec::new();
+        assert_eq!(export_blocks(&source, 0, 3, &mut blob).unwrap(), 4);
+
+        let target = MemoryDb::new();
+        assert_eq!(import_blocks(&target, &mut &blob[..]).unwrap(), 4);
+        for (height, block) in chain.iter().enumerate() {
+            let id = target.block_hash_at(height as u64).unwrap();
+            assert_eq!(id, block.id());
+            assert_eq!(target.block_by_hash(id).as_ref(), Some(block));
+        }
+
+        assert!(export_blocks(&source, 3, 4, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn import_stops_on_gap() {
+        let source = MemoryDb::new();
+        for (height, block) in test_chain(4).iter().enumerate() {
+            source.insert_block(height as u64, block).unwrap();
+        }
+
+        let mut blob = Vec::new();
+        export_blocks(&source, 0, 1, &mut blob).unwrap();
+        export_blocks(&source, 3, 3, &mut blob).unwrap();
+
+        let target = MemoryDb::new();
+        match import_blocks(&target, &mut &blob[..]) {
+            Err(Error::HeightGap(2, 3)) => { /* happy path */ }
+            other => panic!("expected a height gap, got {:?}", other),
+        }
+        assert!(target.block_hash_at(1).is_some());
+        assert!(target.block_hash_at(3).is_none());
+
+        let truncated = &blob[..blob.len() - 1];
+        assert!(import_blocks(&MemoryDb::new(), &mut &truncated[..]).is_err());
+
+        match import_blocks(&MemoryDb::new(), &mut &[0x80u8][..]) {
+            Err(Error::InvalidExport(_)) => { /* happy path */ }
+            other => panic!("expected an invalid export, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn import_writes_in_batches() {
+        let source = MemoryDb::new();
+        for (height, block) in test_chain(5).iter().enumerate() {
+            source.insert_block(height as u64, block).unwrap();
+        }
+
+        let mut blob = Vec::new();
+        export_blocks(&source, 0, 4, &mut blob).unwrap();
+        let truncated = &blob[..blob.len() - 1];
+
+        // the two full batches are stored before the truncated block.
+        let target = MemoryDb::new();
+        assert!(import_batches(&target, &mut &truncated[..], 2).is_err());
+        assert!(target.block_hash_at(3).is_some());
+        assert!(target.block_hash_at(4).is_none());
+
+        let target = MemoryDb::new();
+        assert_eq!(import_batches(&target, &mut &blob[..], 2).unwrap(), 5);
+        assert!(target.block_hash_at(4).is_some());
+    }
+
+    #[test]
+    fn import_rejects_heights_past_the_last() {
+        let blob = to_binary(&test_block(0));
+        let mut record = BytesMut::with_capacity(2 * (10 + 10 + blob.len()));
+        for _ in 0..2 {
+            varint::write(&mut record, u64::MAX);
+            varint::write(&mut record, blob.len());
+            record.extend_from_slice(&blob);
+        }
+
+        match import_blocks(&MemoryDb::new(), &mut &record[..]) {
+            Err(Error::InvalidExport(_)) => { /* happy path */ }
+            other => panic!("expected an invalid export, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use bytes::{BufMut, Bytes, BytesMut};
+use lz4_flex;
+
+/// Prefix of the compressed values.
+///
+/// Uncompressed (legacy) blocks start with the major version varint, and a
+/// canonical varint never has a zero byte after a continuation byte, so no
+/// legacy value starts with this prefix.
+pub const COMPRESSED_MAGIC: [u8; 2] = [0x80, 0x00];
+
+/// Length of the prefix of compressed values, the magic and the format byte.
+pub const HEADER_LEN: usize = COMPRESSED_MAGIC.len() + 1;
+
+/// Format byte of values stored as they are because compressing them
+/// didn't make them smaller.
+const FORMAT_STORED: u8 = 0;
+
+/// Format byte of LZ4 blocks prefixed with their uncompressed size.
+const FORMAT_LZ4: u8 = 1;
+
+/// Compression applied to the values of the block column.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Compression {
+    /// Store values as they are.
+    None,
+    /// LZ4 block compression.
+    Lz4,
+}
+
+impl Default for Compression {
+    fn default() -> Compression {
+        Compression::None
+    }
+}
+
+impl Compression {
+    /// Compresses `bytes`, prefixing them with `COMPRESSED_MAGIC` and a
+    /// format byte.
+    ///
+    /// Values that don't get smaller are stored as they are after the
+    /// header, so they never grow by more than `HEADER_LEN` bytes.
+    pub fn compress(&self, bytes: &[u8]) -> Bytes {
+        match *self {
+            Compression::None => Bytes::from(bytes),
+            Compression::Lz4 => {
+                let compressed = lz4_flex::compress_prepend_size(bytes);
+                let (format, payload) = if compressed.len() < bytes.len() {
+                    (FORMAT_LZ4, &compressed[..])
+                } else {
+                    (FORMAT_STORED, bytes)
+                };
+
+                let mut buf = BytesMut::with_capacity(HEADER_LEN + payload.len());
+                buf.put_slice(&COMPRESSED_MAGIC);
+                buf.put_u8(format);
+                buf.put_slice(payload);
+                buf.freeze()
+            }
+        }
+    }
+}
+
+/// Is this value compressed?
+pub fn is_compressed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&COMPRESSED_MAGIC)
+}
+
+/// Decompresses a value, values without the `COMPRESSED_MAGIC` prefix are
+/// returned as they are.
+pub fn decompress(bytes: &[u8]) -> Result<Bytes, String> {
+    if !is_compressed(bytes) {
+        return Ok(Bytes::from(bytes));
+    }
+
+    if bytes.len() < HEADER_LEN {
+        return Err("missing format byte".to_owned());
+    }
+
+    let payload = &bytes[HEADER_LEN..];
+    match bytes[COMPRESSED_MAGIC.len()] {
+        FORMAT_STORED => Ok(Bytes::from(payload)),
+        FORMAT_LZ4 => {
+            lz4_flex::decompress_size_prepended(payload)
+                .map(Bytes::from)
+                .map_err(|e| format!("invalid lz4 block: {}", e))
+        }
+        format => Err(format!("unknown compression format {}", format)),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+    use rand::prng::XorShiftRng;
+
+    #[test]
+    fn lz4_round_trip() {
+        let mut long_run = vec![1, 2];
+        long_run.extend(vec![0; 600]);
+        long_run.push(3);
+
+        let values: &[&[u8]] = &[&[], &[0], &[1, 2, 3], &[0, 0, 0, 7, 0, 8, 0, 0], &long_run];
+
+        for value in values.iter() {
+            let compressed = Compression::Lz4.compress(value);
+            assert!(is_compressed(&compressed));
+            assert_eq!(&*decompress(&compressed).unwrap(), *value);
+        }
+
+        assert!(Compression::Lz4.compress(&long_run).len() < long_run.len());
+    }
+
+    #[test]
+    fn incompressible_values_grow_by_the_header_at_most() {
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        for &len in [0usize, 1, 16, 255, 4096].iter() {
+            let value: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let compressed = Compression::Lz4.compress(&value);
+            assert!(compressed.len() <= value.len() + HEADER_LEN);
+            assert_eq!(&*decompress(&compressed).unwrap(), &value[..]);
+        }
+    }
+
+    #[test]
+    fn legacy_values_are_not_compressed() {
+        // major versions 1 and 255 as canonical varints.
+        assert!(!is_compressed(&[0x01, 0x01]));
+        assert!(!is_compressed(&[0xff, 0x01, 0x00]));
+        assert!(!is_compressed(&[0x80, 0x01]));
+    }
+
+    #[test]
+    fn uncompressed_values_are_untouched() {
+        let value = [1, 0, 0, 0, 2];
+        assert_eq!(&*Compression::None.compress(&value), &value);
+        assert_eq!(&*decompress(&value).unwrap(), &value);
+    }
+
+    #[test]
+    fn invalid_compressed_values() {
+        assert!(decompress(&COMPRESSED_MAGIC).is_err());
+        assert!(decompress(&[0x80, 0x00, 2, 1]).is_err());
+        assert!(decompress(&[0x80, 0x00, FORMAT_LZ4, 10, 0, 0, 0, 0xf0]).is_err());
+    }
+}
@@ -21,4 +21,13 @@ pub trait KeyValueDatabase: Send + Sync + Debug {
     fn write(&self, tx: Transaction) -> Result<(), String>;
 
     fn get(&self, key: &Key) -> Result<KeyState<Value>, String>;
+
+    /// Is `key` inserted? Cheaper than `get`, the value isn't decoded.
+    fn contains(&self, key: &Key) -> Result<bool, String>;
+
+    /// Makes the written transactions durable.
+    fn flush(&self) -> Result<(), String>;
+
+    /// Reclaims the space used by deleted or overwritten values.
+    fn compact(&self) -> Result<(), String>;
 }
@@ -22,13 +22,15 @@ use rand::OsRng;
 use sanakirja::{Env, Error, MutTxn, Commit, Db, Transaction as SanakirjaTransaction};
 use sanakirja::value::UnsafeValue;
 
-use kv::{KeyValueDatabase, KeyState, Key, Value, Transaction};
+use kv::{KeyValueDatabase, KeyState, Key, Value, Transaction, Compression};
 use kv::transaction::{RawOperation, RawKey};
 
 /// A database stored in disk.
 pub struct DiskDb {
     /// Sanakirja environment.
     env: Env,
+    /// Compression of the stored blocks.
+    compression: Compression,
 }
 
 impl DiskDb {
@@ -41,7 +43,10 @@ impl DiskDb {
         let size = Self::db_size(path.as_ref());
         let env = Env::new::<P>(path, size)?;
 
-        Ok(DiskDb { env: env })
+        Ok(DiskDb {
+            env: env,
+            compression: Compression::None,
+        })
     }
 
     /// Query the database file size.
@@ -55,6 +60,12 @@ impl DiskDb {
             .map(|size| max(size, MIN_DB_SIZE))
             .unwrap_or(MIN_DB_SIZE)
     }
+
+    /// Sets the compression used for the blocks written from now on,
+    /// blocks already stored are read either way.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
 }
 
 impl KeyValueDatabase for DiskDb {
@@ -65,7 +76,7 @@ impl KeyValueDatabase for DiskDb {
 
         // XXX: probably not the best performant kv db out there, but... who cares?
         for op in tx.operations.iter() {
-            let op = op.into();
+            let op = RawOperation::with_compression(op, self.compression);
             match op {
                 RawOperation::Insert(ref kv) => {
                     let mut db = open_db(&mut txn, kv.location);
@@ -102,11 +113,35 @@ impl KeyValueDatabase for DiskDb {
         let val = txn.get::<_, UnsafeValue>(&db, key_val, None)
             .ok_or("key doesn't exists".to_owned());
         if let Ok(val) = val {
-            Ok(KeyState::Insert(Value::for_key(key, unsafe { val.as_slice() })))
+            Value::for_key(key, unsafe { val.as_slice() }).map(KeyState::Insert)
         } else {
             Ok(KeyState::Delete)
         }
     }
+
+    fn contains(&self, key: &Key) -> Result<bool, String> {
+        let raw_key: RawKey = key.into();
+        let mut txn = self.env.txn_begin().unwrap();
+        let db = match txn.root(raw_key.location) {
+            Some(db) => db,
+            None => return Ok(false),
+        };
+
+        let key_val = UnsafeValue::from_slice(&raw_key.key);
+        Ok(txn.get::<_, UnsafeValue>(&db, key_val, None).is_some())
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        // sanakirja syncs the memory map on every commit, so the written
+        // transactions are already on disk.
+        Ok(())
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        // freed pages go back to sanakirja's free list and are reused by the
+        // next commits, the file itself never shrinks.
+        Ok(())
+    }
 }
 
 fn open_db(txn: &mut MutTxn<()>, root: usize) -> Db<UnsafeValue, UnsafeValue> {
@@ -0,0 +1,254 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::collections::Bound::{Excluded, Included};
+
+use bytes::Bytes;
+use parking_lot::RwLock;
+
+use kv::{KeyValueDatabase, KeyState, Key, Value, Transaction, Compression};
+use kv::compression;
+use kv::transaction::{RawOperation, RawKey, COL_BLOCKS};
+
+/// An in-memory key-value database, useful for tests.
+#[derive(Debug, Default)]
+pub struct MemoryDb {
+    columns: RwLock<HashMap<usize, BTreeMap<Bytes, KeyState<Bytes>>>>,
+    compression: Compression,
+}
+
+impl MemoryDb {
+    /// Creates an empty `MemoryDb`.
+    pub fn new() -> MemoryDb {
+        MemoryDb::default()
+    }
+
+    /// Creates an empty `MemoryDb` storing blocks with the given
+    /// `Compression`.
+    pub fn with_compression(compression: Compression) -> MemoryDb {
+        MemoryDb {
+            compression,
+            ..MemoryDb::default()
+        }
+    }
+
+    /// Sets the compression used for the blocks written from now on.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Returns the key-value pairs of `column` with keys in
+    /// `[start, end)`, sorted by key. Deleted keys are skipped and blocks
+    /// are decompressed.
+    pub fn scan(&self,
+                column: usize,
+                start: &[u8],
+                end: &[u8])
+                -> impl Iterator<Item = (Bytes, Bytes)> {
+        let columns = self.columns.read();
+
+        let mut pairs = Vec::new();
+        if let Some(column_map) = columns.get(&column) {
+            if start < end {
+                let range = column_map.range::<[u8], _>((Included(start), Excluded(end)));
+                for (key, state) in range {
+                    if let KeyState::Insert(ref value) = *state {
+                        let value = if column == COL_BLOCKS {
+                            compression::decompress(value).expect("stored block to be valid")
+                        } else {
+                            value.clone()
+                        };
+                        pairs.push((key.clone(), value));
+                    }
+                }
+            }
+        }
+
+        pairs.into_iter()
+    }
+}
+
+impl KeyValueDatabase for MemoryDb {
+    fn write(&self, tx: Transaction) -> Result<(), String> {
+        let mut columns = self.columns.write();
+
+        for op in tx.operations.iter() {
+            match RawOperation::with_compression(op, self.compression) {
+                RawOperation::Insert(kv) => {
+                    columns
+                        .entry(kv.location)
+                        .or_insert_with(BTreeMap::new)
+                        .insert(kv.key, KeyState::Insert(kv.value));
+                }
+                RawOperation::Delete(k) => {
+                    columns
+                        .entry(k.location)
+                        .or_insert_with(BTreeMap::new)
+                        .insert(k.key, KeyState::Delete);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, key: &Key) -> Result<KeyState<Value>, String> {
+        let raw_key: RawKey = key.into();
+        let columns = self.columns.read();
+
+        let state = columns
+            .get(&raw_key.location)
+            .and_then(|column| column.get(&raw_key.key))
+            .cloned()
+            .unwrap_or(KeyState::Unknown);
+
+        match state {
+            KeyState::Insert(bytes) => Value::for_key(key, &bytes).map(KeyState::Insert),
+            KeyState::Delete => Ok(KeyState::Delete),
+            KeyState::Unknown => Ok(KeyState::Unknown),
+        }
+    }
+
+    fn contains(&self, key: &Key) -> Result<bool, String> {
+        let raw_key: RawKey = key.into();
+        let columns = self.columns.read();
+
+        let state = columns
+            .get(&raw_key.location)
+            .and_then(|column| column.get(&raw_key.key));
+
+        match state {
+            Some(&KeyState::Insert(_)) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use super::super::*;
+    use kv::transaction::{COL_BLOCK_IDS, COL_KEY_IMAGES};
+
+    #[test]
+    fn insert_and_delete() {
+        let hash = [0x42; 32];
+        let db = MemoryDb::new();
+
+        let k = Key::BlockHeight(hash.into());
+        match db.get(&k).unwrap() {
+            KeyState::Unknown => { /* happy path */ }
+            _ => panic!("key-value pair should be unknown"),
+        }
+
+        let mut tx = Transaction::new();
+        tx.insert(KeyValue::BlockHeight(hash.into(), 7));
+        db.write(tx).unwrap();
+
+        match db.get(&k).unwrap() {
+            KeyState::Insert(Value::BlockHeight(7)) => { /* happy path */ }
+            KeyState::Insert(_) => panic!("invalid value"),
+            KeyState::Delete => panic!("key-value pair is deleted"),
+            KeyState::Unknown => panic!("key-value pair is unknown"),
+        }
+
+        let mut tx = Transaction::new();
+        tx.delete(Key::BlockHeight(hash.into()));
+        db.write(tx).unwrap();
+
+        match db.get(&k).unwrap() {
+            KeyState::Delete => { /* happy path */ }
+            _ => panic!("key-value pair should be deleted"),
+        }
+    }
+
+    #[test]
+    fn contains() {
+        let present = [0x42; 32];
+        let deleted = [0x43; 32];
+        let absent = [0x44; 32];
+        let db = MemoryDb::new();
+
+        let mut tx = Transaction::new();
+        tx.insert(KeyValue::BlockHeight(present.into(), 7));
+        tx.insert(KeyValue::BlockHeight(deleted.into(), 8));
+        db.write(tx).unwrap();
+
+        let mut tx = Transaction::new();
+        tx.delete(Key::BlockHeight(deleted.into()));
+        db.write(tx).unwrap();
+
+        assert!(db.contains(&Key::BlockHeight(present.into())).unwrap());
+        assert!(!db.contains(&Key::BlockHeight(deleted.into())).unwrap());
+        assert!(!db.contains(&Key::BlockHeight(absent.into())).unwrap());
+        assert!(!db.contains(&Key::BlockId(7)).unwrap());
+    }
+
+    #[test]
+    fn flush_keeps_writes() {
+        let hash = [0x42; 32];
+        let db = MemoryDb::new();
+
+        let mut tx = Transaction::new();
+        tx.insert(KeyValue::BlockHeight(hash.into(), 7));
+        db.write(tx).unwrap();
+
+        db.flush().unwrap();
+        db.compact().unwrap();
+
+        match db.get(&Key::BlockHeight(hash.into())).unwrap() {
+            KeyState::Insert(Value::BlockHeight(7)) => { /* happy path */ }
+            _ => panic!("key-value pair should survive a flush"),
+        }
+    }
+
+    #[test]
+    fn scan_range() {
+        let db = MemoryDb::new();
+        let hash = [0x42; 32];
+
+        let mut tx = Transaction::new();
+        for height in 0..10 {
+            tx.insert(KeyValue::BlockId(height, hash.into()));
+        }
+        tx.delete(Key::BlockId(4));
+        db.write(tx).unwrap();
+
+        let key = |height: u64| RawKey::from(&Key::BlockId(height)).key;
+
+        // keys are little endian, so only single byte heights sort in order.
+        let heights: Vec<u8> = db.scan(COL_BLOCK_IDS, &key(2), &key(6))
+            .map(|(k, v)| {
+                     assert_eq!(&v[..], &hash[..]);
+                     k[0]
+                 })
+            .collect();
+        assert_eq!(heights, vec![2, 3, 5]);
+
+        assert_eq!(db.scan(COL_BLOCK_IDS, &key(6), &key(6)).count(), 0);
+        assert_eq!(db.scan(COL_BLOCK_IDS, &key(6), &key(2)).count(), 0);
+        assert_eq!(db.scan(COL_KEY_IMAGES, &[], &[0xff]).count(), 0);
+    }
+}
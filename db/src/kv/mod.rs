@@ -14,13 +14,23 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+mod compression;
 mod db;
 mod transaction;
 
+pub use self::compression::Compression;
 pub use self::db::KeyValueDatabase;
-pub use self::transaction::{Transaction, KeyState, Key, Value, KeyValue};
+pub use self::transaction::{Transaction, KeyState, Key, Value, KeyValue, COL_META, COL_BLOCKS,
+                            COL_BLOCK_HEIGHTS, COL_BLOCK_IDS, COL_KEY_IMAGES, COL_CUMULATIVE_DIFFICULTIES,
+                            COL_TX_BLOCK, COLUMN_COUNT};
 
 
 mod diskdb;
+mod memorydb;
+#[cfg(feature = "rocksdb")]
+mod rocksdb;
 
 pub use self::diskdb::DiskDb;
+pub use self::memorydb::MemoryDb;
+#[cfg(feature = "rocksdb")]
+pub use self::rocksdb::RocksDb;
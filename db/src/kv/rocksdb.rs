@@ -0,0 +1,226 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::{self, Debug, Formatter};
+use std::path::Path;
+
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Options, WriteBatch, DB};
+
+use kv::{KeyValueDatabase, KeyState, Key, Value, Transaction, Compression, COLUMN_COUNT};
+use kv::transaction::{RawOperation, RawKey};
+
+/// A database stored in disk by RocksDB, every `COL_*` column is a
+/// column family.
+pub struct RocksDb {
+    db: DB,
+    /// Compression of the stored blocks.
+    compression: Compression,
+}
+
+impl RocksDb {
+    /// Open a database, creating it and its column families if missing.
+    ///
+    /// It takes a path to a directory, not a file.
+    pub fn open<P>(path: P) -> Result<RocksDb, String>
+        where P: AsRef<Path>
+    {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+
+        let column_families = (0..COLUMN_COUNT)
+            .map(|location| {
+                     ColumnFamilyDescriptor::new(column_name(location), Options::default())
+                 })
+            .collect::<Vec<_>>();
+
+        let db = DB::open_cf_descriptors(&options, path, column_families)
+            .map_err(|e| e.to_string())?;
+
+        Ok(RocksDb {
+            db,
+            compression: Compression::None,
+        })
+    }
+
+    /// Sets the compression used for the blocks written from now on,
+    /// blocks already stored are read either way.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    fn column(&self, location: usize) -> &ColumnFamily {
+        self.db
+            .cf_handle(&column_name(location))
+            .expect("every column family is opened")
+    }
+}
+
+impl KeyValueDatabase for RocksDb {
+    fn write(&self, tx: Transaction) -> Result<(), String> {
+        // the whole transaction is applied at once or not at all.
+        let mut batch = WriteBatch::default();
+        for op in tx.operations.iter() {
+            match RawOperation::with_compression(op, self.compression) {
+                RawOperation::Insert(kv) => {
+                    batch.put_cf(self.column(kv.location), &kv.key, &kv.value)
+                }
+                RawOperation::Delete(k) => batch.delete_cf(self.column(k.location), &k.key),
+            }
+        }
+
+        self.db.write(batch).map_err(|e| e.to_string())
+    }
+
+    fn get(&self, key: &Key) -> Result<KeyState<Value>, String> {
+        let raw_key: RawKey = key.into();
+        let value = self.db
+            .get_pinned_cf(self.column(raw_key.location), &raw_key.key)
+            .map_err(|e| e.to_string())?;
+
+        match value {
+            Some(value) => Value::for_key(key, &value).map(KeyState::Insert),
+            None => Ok(KeyState::Unknown),
+        }
+    }
+
+    fn contains(&self, key: &Key) -> Result<bool, String> {
+        let raw_key: RawKey = key.into();
+        self.db
+            .get_pinned_cf(self.column(raw_key.location), &raw_key.key)
+            .map(|value| value.is_some())
+            .map_err(|e| e.to_string())
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        self.db.flush().map_err(|e| e.to_string())
+    }
+
+    fn compact(&self) -> Result<(), String> {
+        for location in 0..COLUMN_COUNT {
+            self.db
+                .compact_range_cf(self.column(location), None::<&[u8]>, None::<&[u8]>);
+        }
+        Ok(())
+    }
+}
+
+impl Debug for RocksDb {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "RocksDb")
+    }
+}
+
+/// The name of the column family of a `COL_*` column.
+fn column_name(location: usize) -> String {
+    format!("col{}", location)
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate tempdir;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    use bytes::Bytes;
+    use primitives::H256;
+
+    use self::tempdir::TempDir;
+
+    use super::*;
+    use block_chain_store::tests::test_block;
+    use kv::KeyValue;
+
+    #[test]
+    fn round_trip_all_keys() {
+        let tempdir = TempDir::new("").unwrap();
+        let hash = H256::from([0x42; 32]);
+        let block = test_block(10000);
+        let block_id = block.id();
+
+        {
+            let db = RocksDb::open(tempdir.path()).unwrap();
+            let mut tx = Transaction::new();
+            tx.insert(KeyValue::Meta("best_block", Bytes::from(&b"\x01\x02"[..])));
+            tx.insert(KeyValue::Block(block_id.clone(), block.clone()));
+            tx.insert(KeyValue::BlockHeight(hash.clone(), 7));
+            tx.insert(KeyValue::BlockId(7, block_id.clone()));
+            tx.insert(KeyValue::KeyImage(hash.clone()));
+            tx.insert(KeyValue::CumulativeDifficulty(7, 100));
+            tx.insert(KeyValue::TxBlock(hash.clone(), (block_id.clone(), 7)));
+            db.write(tx).unwrap();
+            db.flush().unwrap();
+        }
+
+        // reopened, the values come back from disk.
+        let db = RocksDb::open(tempdir.path()).unwrap();
+        let get = |key: Key| db.get(&key).unwrap().into_option().unwrap();
+
+        assert_eq!(get(Key::Meta("best_block")).as_meta(),
+                   Some(Bytes::from(&b"\x01\x02"[..])));
+        assert_eq!(get(Key::Block(block_id.clone())).as_block(), Some(block));
+        assert_eq!(get(Key::BlockHeight(hash.clone())).as_block_height(), Some(7));
+        assert_eq!(get(Key::BlockId(7)).as_block_id(), Some(block_id.clone()));
+        assert!(db.contains(&Key::KeyImage(hash.clone())).unwrap());
+        assert_eq!(get(Key::CumulativeDifficulty(7)).as_cumulative_difficulty(),
+                   Some(100));
+        assert_eq!(get(Key::TxBlock(hash.clone())).as_tx_block(),
+                   Some((block_id, 7)));
+
+        assert!(!db.contains(&Key::BlockId(8)).unwrap());
+
+        let mut tx = Transaction::new();
+        tx.delete(Key::BlockHeight(hash.clone()));
+        db.write(tx).unwrap();
+        assert!(db.get(&Key::BlockHeight(hash)).unwrap().into_option().is_none());
+    }
+
+    #[test]
+    fn batches_are_atomic() {
+        const KEYS: u64 = 1000;
+
+        let tempdir = TempDir::new("").unwrap();
+        let db = Arc::new(RocksDb::open(tempdir.path()).unwrap());
+
+        let writer = {
+            let db = db.clone();
+            thread::spawn(move || {
+                let mut tx = Transaction::new();
+                for height in 0..KEYS {
+                    tx.insert(KeyValue::CumulativeDifficulty(height, height));
+                }
+                db.write(tx).unwrap();
+            })
+        };
+
+        // once the last key is seen every other key of the batch must be
+        // there too.
+        loop {
+            if db.contains(&Key::CumulativeDifficulty(KEYS - 1)).unwrap() {
+                for height in 0..KEYS {
+                    assert!(db.contains(&Key::CumulativeDifficulty(height)).unwrap());
+                }
+                break;
+            }
+
+            assert!(!db.contains(&Key::CumulativeDifficulty(0)).unwrap() ||
+                    db.contains(&Key::CumulativeDifficulty(KEYS - 1)).unwrap());
+        }
+
+        writer.join().unwrap();
+    }
+}
@@ -15,14 +15,22 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use bytes::{BytesMut, Bytes, Buf, BufMut, IntoBuf};
-use primitives::H256;
+use primitives::{H256, H256_LENGTH};
 use chain::Block;
 use format::{to_binary, from_binary};
 
+use kv::compression::{self, Compression};
+
 pub const COL_META: usize = 0;
 pub const COL_BLOCKS: usize = 1;
 pub const COL_BLOCK_HEIGHTS: usize = 2;
 pub const COL_BLOCK_IDS: usize = 3;
+pub const COL_KEY_IMAGES: usize = 4;
+pub const COL_CUMULATIVE_DIFFICULTIES: usize = 5;
+pub const COL_TX_BLOCK: usize = 6;
+
+/// Number of columns, every `COL_*` constant must be lower than this.
+pub const COLUMN_COUNT: usize = 7;
 
 #[derive(Debug)]
 pub enum Operation {
@@ -36,6 +44,9 @@ pub enum KeyValue {
     Block(H256, Block),
     BlockHeight(H256, u64),
     BlockId(u64, H256),
+    KeyImage(H256),
+    CumulativeDifficulty(u64, u64),
+    TxBlock(H256, (H256, u64)),
 }
 
 #[derive(Debug)]
@@ -44,6 +55,9 @@ pub enum Key {
     Block(H256),
     BlockHeight(H256),
     BlockId(u64),
+    KeyImage(H256),
+    CumulativeDifficulty(u64),
+    TxBlock(H256),
 }
 
 #[derive(Debug, Clone)]
@@ -52,23 +66,33 @@ pub enum Value {
     Block(Block),
     BlockHeight(u64),
     BlockId(H256),
+    KeyImage,
+    CumulativeDifficulty(u64),
+    TxBlock(H256, u64),
 }
 
 impl Value {
-    pub fn for_key(key: &Key, bytes: &[u8]) -> Value {
-        match *key {
+    /// Decodes the value stored under `key`, fails on contents that weren't
+    /// written by this crate.
+    pub fn for_key(key: &Key, bytes: &[u8]) -> Result<Value, String> {
+        let value = match *key {
             Key::Meta(_) => Value::Meta(bytes.into()),
-            Key::Block(_) => Value::Block(from_binary(bytes).unwrap()),
-            Key::BlockHeight(_) => {
-                if bytes.len() != 8 {
-                    unimplemented!();
-                }
-
-                let mut buf = bytes.into_buf();
-                Value::BlockHeight(buf.get_u64_le())
+            Key::Block(_) => {
+                let bytes = compression::decompress(bytes)?;
+                Value::Block(from_binary(&bytes).map_err(|e| format!("invalid block: {}", e))?)
             }
-            Key::BlockId(_) => Value::BlockId(H256::from_bytes(&bytes)),
-        }
+            Key::BlockHeight(_) => Value::BlockHeight(read_u64(key, bytes)?),
+            Key::BlockId(_) => Value::BlockId(read_h256(key, bytes)?),
+            Key::KeyImage(_) => Value::KeyImage,
+            Key::CumulativeDifficulty(_) => Value::CumulativeDifficulty(read_u64(key, bytes)?),
+            Key::TxBlock(_) => {
+                check_length(key, bytes, H256_LENGTH + 8)?;
+                let mut buf = bytes[H256_LENGTH..].into_buf();
+                Value::TxBlock(H256::from_bytes(&bytes[..H256_LENGTH]), buf.get_u64_le())
+            }
+        };
+
+        Ok(value)
     }
 
     pub fn as_meta(self) -> Option<Bytes> {
@@ -98,6 +122,38 @@ impl Value {
             _ => None,
         }
     }
+
+    pub fn as_cumulative_difficulty(self) -> Option<u64> {
+        match self {
+            Value::CumulativeDifficulty(difficulty) => Some(difficulty),
+            _ => None,
+        }
+    }
+
+    pub fn as_tx_block(self) -> Option<(H256, u64)> {
+        match self {
+            Value::TxBlock(block, height) => Some((block, height)),
+            _ => None,
+        }
+    }
+}
+
+/// Fails unless `bytes` is exactly `len` bytes long.
+fn check_length(key: &Key, bytes: &[u8], len: usize) -> Result<(), String> {
+    if bytes.len() != len {
+        return Err(format!("expected {} bytes for {:?}, got {}", len, key, bytes.len()));
+    }
+    Ok(())
+}
+
+fn read_u64(key: &Key, bytes: &[u8]) -> Result<u64, String> {
+    check_length(key, bytes, 8)?;
+    Ok(bytes.into_buf().get_u64_le())
+}
+
+fn read_h256(key: &Key, bytes: &[u8]) -> Result<H256, String> {
+    check_length(key, bytes, H256_LENGTH)?;
+    Ok(H256::from_bytes(bytes))
 }
 
 #[derive(Debug, Clone)]
@@ -155,6 +211,19 @@ impl<'a> From<&'a Operation> for RawOperation {
     }
 }
 
+impl RawOperation {
+    /// Converts an operation, compressing the block values with the given
+    /// `Compression`.
+    pub fn with_compression(op: &Operation, compression: Compression) -> RawOperation {
+        match *op {
+            Operation::Insert(ref kv) => {
+                RawOperation::Insert(RawKeyValue::with_compression(kv, compression))
+            }
+            Operation::Delete(ref k) => RawOperation::Delete(k.into()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RawKeyValue {
     pub location: usize,
@@ -177,8 +246,24 @@ impl<'a> From<&'a KeyValue> for RawKeyValue {
                 buf.put_u64_le(*k);
                 (COL_BLOCK_IDS, buf.freeze(), Bytes::from(v.as_bytes()))
             }
+            KeyValue::KeyImage(ref k) => (COL_KEY_IMAGES, Bytes::from(k.as_bytes()), Bytes::new()),
+            KeyValue::CumulativeDifficulty(ref k, ref v) => {
+                let mut key = BytesMut::with_capacity(8);
+                key.put_u64_le(*k);
+                let mut value = BytesMut::with_capacity(8);
+                value.put_u64_le(*v);
+                (COL_CUMULATIVE_DIFFICULTIES, key.freeze(), value.freeze())
+            }
+            KeyValue::TxBlock(ref k, (ref block, height)) => {
+                let mut value = BytesMut::with_capacity(40);
+                value.put_slice(block.as_bytes());
+                value.put_u64_le(height);
+                (COL_TX_BLOCK, Bytes::from(k.as_bytes()), value.freeze())
+            }
         };
 
+        debug_assert!(location < COLUMN_COUNT, "invalid column {}", location);
+
         RawKeyValue {
             location,
             key,
@@ -187,6 +272,18 @@ impl<'a> From<&'a KeyValue> for RawKeyValue {
     }
 }
 
+impl RawKeyValue {
+    /// Converts a key-value pair, compressing the block values with the
+    /// given `Compression`.
+    pub fn with_compression(kv: &KeyValue, compression: Compression) -> RawKeyValue {
+        let mut raw: RawKeyValue = kv.into();
+        if raw.location == COL_BLOCKS && compression != Compression::None {
+            raw.value = compression.compress(&raw.value);
+        }
+        raw
+    }
+}
+
 #[derive(Debug)]
 pub struct RawKey {
     pub location: usize,
@@ -204,8 +301,73 @@ impl<'a> From<&'a Key> for RawKey {
                 buf.put_u64_le(*k);
                 (COL_BLOCK_IDS, buf.freeze())
             }
+            Key::KeyImage(ref k) => (COL_KEY_IMAGES, Bytes::from(k.as_bytes())),
+            Key::CumulativeDifficulty(ref k) => {
+                let mut buf = BytesMut::with_capacity(8);
+                buf.put_u64_le(*k);
+                (COL_CUMULATIVE_DIFFICULTIES, buf.freeze())
+            }
+            Key::TxBlock(ref k) => (COL_TX_BLOCK, Bytes::from(k.as_bytes())),
         };
 
+        debug_assert!(location < COLUMN_COUNT, "invalid column {}", location);
+
         RawKey { location, key }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_are_distinct_and_in_range() {
+        let hash = H256::from([0x42; 32]);
+        let keys = [Key::Meta("best_block"),
+                    Key::Block(hash.clone()),
+                    Key::BlockHeight(hash.clone()),
+                    Key::BlockId(7),
+                    Key::KeyImage(hash.clone()),
+                    Key::CumulativeDifficulty(7),
+                    Key::TxBlock(hash.clone())];
+        assert_eq!(keys.len(), COLUMN_COUNT);
+
+        let mut locations: Vec<usize> = keys.iter()
+            .map(|k| RawKey::from(k).location)
+            .collect();
+        locations.sort();
+        locations.dedup();
+        assert_eq!(locations,
+                   vec![COL_META,
+                        COL_BLOCKS,
+                        COL_BLOCK_HEIGHTS,
+                        COL_BLOCK_IDS,
+                        COL_KEY_IMAGES,
+                        COL_CUMULATIVE_DIFFICULTIES,
+                        COL_TX_BLOCK]);
+        assert!(locations.iter().all(|&location| location < COLUMN_COUNT));
+
+        let pairs = [(KeyValue::Meta("best_block", Bytes::new()), &keys[0]),
+                     (KeyValue::BlockHeight(hash.clone(), 7), &keys[2]),
+                     (KeyValue::BlockId(7, hash.clone()), &keys[3]),
+                     (KeyValue::KeyImage(hash.clone()), &keys[4]),
+                     (KeyValue::CumulativeDifficulty(7, 100), &keys[5]),
+                     (KeyValue::TxBlock(hash.clone(), (hash.clone(), 7)), &keys[6])];
+        for &(ref kv, k) in pairs.iter() {
+            assert_eq!(RawKeyValue::from(kv).location, RawKey::from(k).location);
+        }
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        let hash = H256::from([0x42; 32]);
+        assert!(Value::for_key(&Key::CumulativeDifficulty(7), &[1, 2, 3]).is_err());
+        assert!(Value::for_key(&Key::BlockHeight(hash.clone()), &[0; 9]).is_err());
+        assert!(Value::for_key(&Key::BlockId(7), &[0; 31]).is_err());
+        assert!(Value::for_key(&Key::Block(hash.clone()), &[0xff; 4]).is_err());
+        assert!(Value::for_key(&Key::TxBlock(hash), &[0; 39]).is_err());
+
+        let value = Value::for_key(&Key::CumulativeDifficulty(7), &[100, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(value.unwrap().as_cumulative_difficulty(), Some(100));
+    }
+}
@@ -18,6 +18,9 @@ extern crate bytes;
 extern crate parking_lot;
 extern crate sanakirja;
 extern crate rand;
+extern crate lz4_flex;
+#[cfg(feature = "rocksdb")]
+extern crate rocksdb;
 
 extern crate failure;
 #[macro_use]
@@ -27,11 +30,21 @@ extern crate xmr_chain as chain;
 extern crate xmr_format as format;
 extern crate xmr_primitives as primitives;
 extern crate xmr_storage as storage;
+extern crate xmr_varint as varint;
+
+#[cfg(test)]
+extern crate xmr_keys as keys;
 
 pub mod kv;
 
 mod block_chain_db;
+mod block_chain_store;
 mod error;
+mod export;
+mod recent_key_images;
 
 pub use self::block_chain_db::BlockChainDatabase;
+pub use self::block_chain_store::BlockChainStore;
 pub use self::error::Error;
+pub use self::export::{export_blocks, import_blocks, IMPORT_BATCH_SIZE};
+pub use self::recent_key_images::{RecentKeyImages, RECENT_KEY_IMAGES_WINDOW};
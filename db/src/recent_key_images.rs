@@ -0,0 +1,146 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use primitives::H256;
+
+/// Default time in seconds a key image is remembered, the lifetime of a
+/// transaction in the pool.
+pub const RECENT_KEY_IMAGES_WINDOW: u64 = 86400 * 3;
+
+/// Key images recently spent by a block or by a pool transaction.
+///
+/// It's a cheap check done before looking into the store, a key image
+/// is forgotten once it is older than the window.
+#[derive(Debug)]
+pub struct RecentKeyImages {
+    window: Duration,
+    inserted_at: HashMap<H256, Instant>,
+    by_time: VecDeque<(Instant, H256)>,
+}
+
+impl RecentKeyImages {
+    /// Remembers key images for `window`.
+    pub fn new(window: Duration) -> RecentKeyImages {
+        RecentKeyImages {
+            window,
+            inserted_at: HashMap::new(),
+            by_time: VecDeque::new(),
+        }
+    }
+
+    /// Notes `key_image` as spent at `now`, and evicts the ones that are
+    /// out of the window.
+    pub fn insert(&mut self, key_image: H256, now: Instant) {
+        self.evict(now);
+
+        self.inserted_at.insert(key_image.clone(), now);
+        self.by_time.push_back((now, key_image));
+    }
+
+    /// Was `key_image` spent within the window?
+    pub fn likely_spent(&self, key_image: &H256) -> bool {
+        self.inserted_at.contains_key(key_image)
+    }
+
+    /// Forgets the key images inserted more than a window before `now`.
+    pub fn evict(&mut self, now: Instant) {
+        while let Some(&(inserted_at, _)) = self.by_time.front() {
+            if now.duration_since(inserted_at) <= self.window {
+                break;
+            }
+
+            let (inserted_at, key_image) = self.by_time.pop_front().unwrap();
+            // a key image inserted again is kept until its last insertion
+            // gets old.
+            if self.inserted_at.get(&key_image) == Some(&inserted_at) {
+                self.inserted_at.remove(&key_image);
+            }
+        }
+    }
+
+    /// Number of remembered key images.
+    pub fn len(&self) -> usize {
+        self.inserted_at.len()
+    }
+
+    /// Are there no remembered key images?
+    pub fn is_empty(&self) -> bool {
+        self.inserted_at.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn inserted_are_likely_spent() {
+        let now = Instant::now();
+        let mut recent = RecentKeyImages::new(Duration::from_secs(60));
+
+        recent.insert(H256::from([0x01; 32]), now);
+        recent.insert(H256::from([0x02; 32]), now);
+
+        assert!(recent.likely_spent(&H256::from([0x01; 32])));
+        assert!(recent.likely_spent(&H256::from([0x02; 32])));
+        assert!(!recent.likely_spent(&H256::from([0x03; 32])));
+        assert_eq!(recent.len(), 2);
+    }
+
+    #[test]
+    fn eviction_after_window() {
+        let now = Instant::now();
+        let mut recent = RecentKeyImages::new(Duration::from_secs(60));
+        assert!(recent.is_empty());
+
+        recent.insert(H256::from([0x01; 32]), now);
+        recent.insert(H256::from([0x02; 32]), now + Duration::from_secs(30));
+
+        recent.evict(now + Duration::from_secs(60));
+        assert_eq!(recent.len(), 2);
+
+        recent.evict(now + Duration::from_secs(61));
+        assert!(!recent.likely_spent(&H256::from([0x01; 32])));
+        assert!(recent.likely_spent(&H256::from([0x02; 32])));
+
+        // inserting evicts too.
+        recent.insert(H256::from([0x03; 32]), now + Duration::from_secs(91));
+        assert!(!recent.likely_spent(&H256::from([0x02; 32])));
+        assert_eq!(recent.len(), 1);
+
+        recent.evict(now + Duration::from_secs(152));
+        assert!(recent.is_empty());
+    }
+
+    #[test]
+    fn reinserted_is_kept() {
+        let now = Instant::now();
+        let mut recent = RecentKeyImages::new(Duration::from_secs(60));
+        let key_image = H256::from([0x01; 32]);
+
+        recent.insert(key_image.clone(), now);
+        recent.insert(key_image.clone(), now + Duration::from_secs(50));
+
+        recent.evict(now + Duration::from_secs(100));
+        assert!(recent.likely_spent(&key_image));
+
+        recent.evict(now + Duration::from_secs(111));
+        assert!(!recent.likely_spent(&key_image));
+    }
+}
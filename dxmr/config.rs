@@ -14,60 +14,1022 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::convert::TryFrom;
+use std::fmt::{self, Write};
+use std::fs;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
 
 use clap::ArgMatches;
-use failure::Error;
+use failure::{Error, err_msg};
+use log::LevelFilter;
 use crate::network::Network;
-use crate::peers::default_peers;
+use crate::p2p;
+use crate::peers::{read_peers_file, resolve_seeds};
 use crate::storage::SharedStore;
 use crate::utils;
 
 pub struct Config {
     pub network: Network,
-    pub peers: Vec<SocketAddr>,
+    /// The peers to connect to, the network's seed nodes when `None`.
+    pub peers: Option<Vec<SocketAddr>>,
     pub threads: usize,
     pub listen_port: Option<u16>,
     pub hide_my_port: bool,
     pub out_peers: u32,
     pub in_peers: u32,
-    pub db: SharedStore,
+    /// The database, the one in the user data directory when `None`.
+    pub db: Option<SharedStore>,
+    pub log_level: Option<LogLevel>,
+    pub rpc_enabled: bool,
+    pub rpc_bind: SocketAddr,
+    pub invoke_timeout: Duration,
+    pub max_packet_size: u64,
+    pub max_clock_skew: Duration,
+    pub use_ipv4: bool,
+    pub use_ipv6: bool,
+}
+
+impl Config {
+    /// The configured peers, or the network's seed nodes resolved now.
+    pub fn peers_or_seeds(&self) -> Vec<SocketAddr> {
+        match self.peers {
+            Some(ref peers) => peers.clone(),
+            None => normalize_peers(resolve_seeds(self.network), self.local_port()),
+        }
+    }
+
+    /// The configured database, or the one in the user data directory
+    /// opened now.
+    pub fn open_db(&self) -> SharedStore {
+        self.db.clone().unwrap_or_else(utils::open_db)
+    }
+
+    /// The port the node listens on.
+    fn local_port(&self) -> u32 {
+        self.listen_port
+            .map(|port| port as u32)
+            .unwrap_or(self.network.listen_port())
+    }
+
+    /// Renders the effective configuration as TOML, in the format read by
+    /// `ConfigBuilder::from_file`. The database isn't included, and the
+    /// peers only when they are configured.
+    ///
+    /// There are no secrets in the configuration yet, these must be
+    /// redacted here once they are added.
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        let _ = writeln!(toml, "network = \"{}\"", self.network);
+        if let Some(ref peers) = self.peers {
+            let peers = peers
+                .iter()
+                .map(|peer| format!("\"{}\"", peer))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(toml, "peers = [{}]", peers);
+        }
+        let _ = writeln!(toml, "threads = {}", self.threads);
+        if let Some(listen_port) = self.listen_port {
+            let _ = writeln!(toml, "listen_port = {}", listen_port);
+        }
+        let _ = writeln!(toml, "hide_my_port = {}", self.hide_my_port);
+        let _ = writeln!(toml, "out_peers = {}", self.out_peers);
+        let _ = writeln!(toml, "in_peers = {}", self.in_peers);
+        if let Some(ref log_level) = self.log_level {
+            let _ = writeln!(toml, "log_level = \"{}\"", log_level);
+        }
+        let _ = writeln!(toml, "rpc_enabled = {}", self.rpc_enabled);
+        let _ = writeln!(toml, "rpc_bind = \"{}\"", self.rpc_bind);
+        let _ = writeln!(toml, "invoke_timeout = \"{}\"", format_duration(self.invoke_timeout));
+        let _ = writeln!(toml, "max_packet_size = {}", self.max_packet_size);
+        let _ = writeln!(toml, "max_clock_skew = \"{}\"", format_duration(self.max_clock_skew));
+        let _ = writeln!(toml, "use_ipv4 = {}", self.use_ipv4);
+        let _ = writeln!(toml, "use_ipv6 = {}", self.use_ipv6);
+        toml
+    }
+}
+
+/// Logging verbosity, a global level and per-module overrides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLevel {
+    pub level: LevelFilter,
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.level.to_string().to_lowercase())?;
+        for &(ref module, level) in self.modules.iter() {
+            write!(f, ",{}={}", module, level.to_string().to_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `Config` without going through the command line, unset fields
+/// get the same defaults as `parse`.
+#[derive(Default)]
+pub struct ConfigBuilder {
+    network: Option<Network>,
+    peers: Option<Vec<SocketAddr>>,
+    threads: Option<usize>,
+    listen_port: Option<u16>,
+    hide_my_port: bool,
+    out_peers: Option<u32>,
+    in_peers: Option<u32>,
+    db: Option<SharedStore>,
+    log_level: Option<LogLevel>,
+    rpc_disabled: bool,
+    rpc_bind: Option<SocketAddr>,
+    invoke_timeout: Option<Duration>,
+    max_packet_size: Option<u64>,
+    max_clock_skew: Option<Duration>,
+    no_ipv4: bool,
+    no_ipv6: bool,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Reads a configuration file in the format written by
+    /// `Config::to_toml`, missing keys keep their defaults.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ConfigBuilder, Error> {
+        let path = path.as_ref();
+        let toml = fs::read_to_string(path)
+            .map_err(|e| err_msg(format!("couldn't read {}: {}", path.display(), e)))?;
+        ConfigBuilder::from_toml(&toml)
+    }
+
+    /// Parses the flat `key = value` TOML subset written by
+    /// `Config::to_toml`.
+    pub fn from_toml(toml: &str) -> Result<ConfigBuilder, Error> {
+        let mut builder = ConfigBuilder::new();
+
+        for (number, line) in toml.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key.trim(), TomlValue::parse(value.trim())?),
+                _ => return Err(err_msg(format!("line {}: expected `key = value`", number + 1))),
+            };
+
+            builder = match key {
+                "network" => builder.network(value.as_str(key)?.parse::<Network>()?),
+                "peers" => {
+                    let peers = value
+                        .as_array(key)?
+                        .iter()
+                        .map(|peer| {
+                                 peer.as_str(key)?
+                                     .parse()
+                                     .map_err(|_| err_msg(format!("invalid peer address in {}", key)))
+                             })
+                        .collect::<Result<Vec<SocketAddr>, Error>>()?;
+                    builder.peers(peers)
+                }
+                "threads" => builder.threads(value.as_integer_in(key)?),
+                "listen_port" => builder.listen_port(value.as_integer_in(key)?),
+                "hide_my_port" => builder.hide_my_port(value.as_bool(key)?),
+                "out_peers" => builder.out_peers(value.as_integer_in(key)?),
+                "in_peers" => builder.in_peers(value.as_integer_in(key)?),
+                "log_level" => builder.log_level(parse_log_level(value.as_str(key)?)?),
+                "rpc_enabled" => builder.rpc_enabled(value.as_bool(key)?),
+                "rpc_bind" => {
+                    let network = builder.network.unwrap_or(Network::Mainnet);
+                    builder.rpc_bind(parse_rpc_bind(Some(value.as_str(key)?), network)?)
+                }
+                "invoke_timeout" => builder.invoke_timeout(parse_duration(value.as_str(key)?)?),
+                "max_packet_size" => builder.max_packet_size(value.as_size(key)? as u64),
+                "max_clock_skew" => builder.max_clock_skew(parse_duration(value.as_str(key)?)?),
+                "use_ipv4" => builder.ipv4(value.as_bool(key)?),
+                "use_ipv6" => builder.ipv6(value.as_bool(key)?),
+                _ => return Err(err_msg(format!("unknown configuration key \"{}\"", key))),
+            };
+        }
+
+        Ok(builder)
+    }
+
+    pub fn network(mut self, network: Network) -> ConfigBuilder {
+        self.network = Some(network);
+        self
+    }
+
+    /// Connect only to `peers`, instead of the network's seed nodes.
+    pub fn peers(mut self, peers: Vec<SocketAddr>) -> ConfigBuilder {
+        self.peers = Some(peers);
+        self
+    }
+
+    /// Connect to `peer`, instead of the network's seed nodes.
+    pub fn add_peer(mut self, peer: SocketAddr) -> ConfigBuilder {
+        self.peers.get_or_insert_with(Vec::new).push(peer);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> ConfigBuilder {
+        self.threads = Some(threads);
+        self
+    }
+
+    pub fn listen_port(mut self, listen_port: u16) -> ConfigBuilder {
+        self.listen_port = Some(listen_port);
+        self
+    }
+
+    pub fn hide_my_port(mut self, hide_my_port: bool) -> ConfigBuilder {
+        self.hide_my_port = hide_my_port;
+        self
+    }
+
+    pub fn out_peers(mut self, out_peers: u32) -> ConfigBuilder {
+        self.out_peers = Some(out_peers);
+        self
+    }
+
+    pub fn in_peers(mut self, in_peers: u32) -> ConfigBuilder {
+        self.in_peers = Some(in_peers);
+        self
+    }
+
+    /// Use `db` instead of opening the database in the user data directory.
+    pub fn db(mut self, db: SharedStore) -> ConfigBuilder {
+        self.db = Some(db);
+        self
+    }
+
+    pub fn log_level(mut self, log_level: LogLevel) -> ConfigBuilder {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    pub fn rpc_enabled(mut self, rpc_enabled: bool) -> ConfigBuilder {
+        self.rpc_disabled = !rpc_enabled;
+        self
+    }
+
+    pub fn rpc_bind(mut self, rpc_bind: SocketAddr) -> ConfigBuilder {
+        self.rpc_bind = Some(rpc_bind);
+        self
+    }
+
+    pub fn invoke_timeout(mut self, invoke_timeout: Duration) -> ConfigBuilder {
+        self.invoke_timeout = Some(invoke_timeout);
+        self
+    }
+
+    pub fn max_packet_size(mut self, max_packet_size: u64) -> ConfigBuilder {
+        self.max_packet_size = Some(max_packet_size);
+        self
+    }
+
+    pub fn max_clock_skew(mut self, max_clock_skew: Duration) -> ConfigBuilder {
+        self.max_clock_skew = Some(max_clock_skew);
+        self
+    }
+
+    /// Listen on and dial IPv4 addresses, enabled by default.
+    pub fn ipv4(mut self, enabled: bool) -> ConfigBuilder {
+        self.no_ipv4 = !enabled;
+        self
+    }
+
+    /// Listen on and dial IPv6 addresses, enabled by default.
+    pub fn ipv6(mut self, enabled: bool) -> ConfigBuilder {
+        self.no_ipv6 = !enabled;
+        self
+    }
+
+    /// Fills the unset fields with their defaults and validates the
+    /// configuration. The seed nodes aren't resolved and the database isn't
+    /// opened, see `Config::peers_or_seeds` and `Config::open_db`.
+    pub fn build(self) -> Result<Config, Error> {
+        let network = self.network.unwrap_or(Network::Mainnet);
+
+        let threads = self.threads.unwrap_or(1);
+        if threads == 0 {
+            return Err(err_msg("at least one thread is needed"));
+        }
+
+        if self.no_ipv4 && self.no_ipv6 {
+            return Err(err_msg("IPv4 and IPv6 can't both be disabled"));
+        }
+
+        let local_port = self.listen_port
+            .map(|port| port as u32)
+            .unwrap_or(network.listen_port());
+        let peers = self.peers.map(|peers| normalize_peers(peers, local_port));
+
+        let rpc_bind = match self.rpc_bind {
+            Some(rpc_bind) => rpc_bind,
+            None => parse_rpc_bind(None, network)?,
+        };
+
+        let mut ports = Vec::new();
+        if !self.hide_my_port {
+            ports.push(("listen port (--listenport)", local_port));
+        }
+        if !self.rpc_disabled {
+            ports.push(("RPC port (--rpc-bind)", rpc_bind.port() as u32));
+        }
+        check_port_collisions(&ports)?;
+
+        Ok(Config {
+               network,
+               peers,
+               threads,
+               listen_port: self.listen_port,
+               hide_my_port: self.hide_my_port,
+               out_peers: self.out_peers.unwrap_or(10),
+               in_peers: self.in_peers.unwrap_or(10),
+               db: self.db,
+               log_level: self.log_level,
+               rpc_enabled: !self.rpc_disabled,
+               rpc_bind,
+               invoke_timeout: self.invoke_timeout
+                   .unwrap_or(Duration::from_secs(p2p::config::DEFAULT_INVOKE_TIMEOUT)),
+               max_packet_size: self.max_packet_size
+                   .unwrap_or(p2p::config::DEFAULT_MAX_PACKET_SIZE),
+               max_clock_skew: self.max_clock_skew
+                   .unwrap_or(Duration::from_secs(p2p::config::DEFAULT_MAX_CLOCK_SKEW)),
+               use_ipv4: !self.no_ipv4,
+               use_ipv6: !self.no_ipv6,
+           })
+    }
 }
 
 pub fn parse(matches: &ArgMatches) -> Result<Config, Error> {
-    let network = match matches.is_present("testnet") {
-        true => Network::Testnet,
-        false => Network::Mainnet,
+    parse_builder(matches)?.build()
+}
+
+/// Reads the configuration file, if any, and the command line options into
+/// a builder, the command line takes precedence. The database is left
+/// unset.
+pub fn parse_builder(matches: &ArgMatches) -> Result<ConfigBuilder, Error> {
+    let mut builder = match matches.value_of("config") {
+        Some(path) => ConfigBuilder::from_file(path)?,
+        None => ConfigBuilder::new(),
+    };
+
+    if let Some(network) = matches.value_of("network") {
+        builder = builder.network(network.parse::<Network>()?);
+    }
+    if matches.is_present("testnet") {
+        builder = builder.network(Network::Testnet);
+    }
+    let network = builder.network.unwrap_or(Network::Mainnet);
+
+    // `--connect` and `--peers-file` are merged, `build` dedups them.
+    let mut peers = values_t!(matches.values_of("connect"), SocketAddr).unwrap_or_default();
+    if let Some(path) = matches.value_of("peersfile") {
+        peers.extend(read_peers_file(path)?);
+    }
+    if !peers.is_empty() {
+        builder = builder.peers(peers);
+    }
+
+    if let Ok(threads) = value_t!(matches.value_of("threads"), usize) {
+        builder = builder.threads(threads);
+    }
+
+    if let Ok(listen_port) = value_t!(matches.value_of("listenport"), u16) {
+        builder = builder.listen_port(listen_port);
+    }
+
+    if matches.is_present("hidemyport") {
+        builder = builder.hide_my_port(true);
+    }
+
+    if let Ok(out_peers) = value_t!(matches.value_of("outpeers"), u32) {
+        builder = builder.out_peers(out_peers);
+    }
+    if let Ok(in_peers) = value_t!(matches.value_of("inpeers"), u32) {
+        builder = builder.in_peers(in_peers);
+    }
+
+    if let Some(s) = matches.value_of("loglevel") {
+        builder = builder.log_level(parse_log_level(s)?);
+    }
+
+    if matches.is_present("norpc") {
+        builder = builder.rpc_enabled(false);
+    }
+    if let Some(s) = matches.value_of("rpcbind") {
+        builder = builder.rpc_bind(parse_rpc_bind(Some(s), network)?);
+    }
+
+    if let Some(s) = matches.value_of("invoketimeout") {
+        builder = builder.invoke_timeout(parse_duration(s)?);
+    }
+
+    if let Some(s) = matches.value_of("maxpacketsize") {
+        builder = builder.max_packet_size(parse_size(s)? as u64);
+    }
+
+    if let Some(s) = matches.value_of("maxclockskew") {
+        builder = builder.max_clock_skew(parse_duration(s)?);
+    }
+
+    if matches.is_present("noipv4") {
+        builder = builder.ipv4(false);
+    }
+    if matches.is_present("noipv6") {
+        builder = builder.ipv6(false);
+    }
+
+    Ok(builder)
+}
+
+/// Parses the RPC bind address, defaulting to localhost on the network's
+/// RPC port.
+pub fn parse_rpc_bind(s: Option<&str>, network: Network) -> Result<SocketAddr, Error> {
+    match s {
+        Some(s) => {
+            s.parse()
+                .map_err(|_| err_msg(format!("invalid RPC bind address \"{}\"", s)))
+        }
+        None => Ok(SocketAddr::from(([127, 0, 0, 1], network.rpc_port() as u16))),
+    }
+}
+
+/// Fails if two of the ports bound by the node are the same, `ports`
+/// pairs each port with the name of its option.
+pub fn check_port_collisions(ports: &[(&str, u32)]) -> Result<(), Error> {
+    for (i, &(name, port)) in ports.iter().enumerate() {
+        if let Some(&(other, _)) = ports[i + 1..].iter().find(|&&(_, p)| p == port) {
+            return Err(err_msg(format!("{} and {} are both set to {}", name, other, port)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a duration such as `30s`, `5m`, `1h` or `500ms`, plain numbers
+/// are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, Error> {
+    let (number, unit) = split_unit(s);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| err_msg(format!("invalid duration \"{}\"", s)))?;
+
+    let multiplier = match unit {
+        "ms" => return Ok(Duration::from_millis(number)),
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => {
+            return Err(err_msg(format!("unknown duration unit \"{}\" in \"{}\", expected one of \
+                                        ms, s, m or h",
+                                       unit,
+                                       s)))
+        }
+    };
+
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| err_msg(format!("duration \"{}\" is too large", s)))
+}
+
+/// Formats a duration in the format read by `parse_duration`.
+fn format_duration(duration: Duration) -> String {
+    if duration.subsec_nanos() % 1_000_000 == 0 && duration.subsec_nanos() != 0 {
+        format!("{}ms", duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1_000_000)
+    } else {
+        format!("{}s", duration.as_secs())
+    }
+}
+
+/// Parses a size in bytes such as `500KB` or `2MB`, plain numbers are
+/// bytes. Units are powers of 1024.
+pub fn parse_size(s: &str) -> Result<usize, Error> {
+    let (number, unit) = split_unit(s);
+    let number: usize = number
+        .parse()
+        .map_err(|_| err_msg(format!("invalid size \"{}\"", s)))?;
+
+    let multiplier = match unit.to_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => {
+            return Err(err_msg(format!("unknown size unit \"{}\" in \"{}\", expected one of \
+                                        B, KB, MB or GB",
+                                       unit,
+                                       s)))
+        }
     };
 
-    let peers = match value_t!(matches.value_of("connect"), SocketAddr) {
-        Ok(addr) => {
-            let mut peers = Vec::with_capacity(1);
-            peers.push(addr);
-            peers
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| err_msg(format!("size \"{}\" is too large", s)))
+}
+
+/// Splits `s` into its leading number and the unit after it.
+fn split_unit(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    let unit_start = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    (&s[..unit_start], s[unit_start..].trim())
+}
+
+/// A value of the TOML subset read by `ConfigBuilder::from_toml`.
+#[derive(Debug, Clone, PartialEq)]
+enum TomlValue {
+    String(String),
+    Integer(u64),
+    Bool(bool),
+    Array(Vec<TomlValue>),
+}
+
+impl TomlValue {
+    fn parse(s: &str) -> Result<TomlValue, Error> {
+        if s.starts_with('[') && s.ends_with(']') {
+            let inner = s[1..s.len() - 1].trim();
+            if inner.is_empty() {
+                return Ok(TomlValue::Array(Vec::new()));
+            }
+
+            return inner
+                .split(',')
+                .map(|item| TomlValue::parse(item.trim()))
+                .collect::<Result<Vec<_>, _>>()
+                .map(TomlValue::Array);
+        }
+
+        if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+            let inner = &s[1..s.len() - 1];
+            if inner.contains('"') || inner.contains('\\') {
+                return Err(err_msg(format!("unsupported string {}", s)));
+            }
+            return Ok(TomlValue::String(inner.to_owned()));
+        }
+
+        match s {
+            "true" => Ok(TomlValue::Bool(true)),
+            "false" => Ok(TomlValue::Bool(false)),
+            _ => {
+                s.parse()
+                    .map(TomlValue::Integer)
+                    .map_err(|_| err_msg(format!("invalid value {}", s)))
+            }
+        }
+    }
+
+    fn as_str(&self, key: &str) -> Result<&str, Error> {
+        match *self {
+            TomlValue::String(ref s) => Ok(s),
+            _ => Err(err_msg(format!("expected a string for {}", key))),
+        }
+    }
+
+    fn as_integer(&self, key: &str) -> Result<u64, Error> {
+        match *self {
+            TomlValue::Integer(n) => Ok(n),
+            _ => Err(err_msg(format!("expected an integer for {}", key))),
+        }
+    }
+
+    /// The integer converted to `T`, failing when it's out of its range.
+    fn as_integer_in<T: TryFrom<u64>>(&self, key: &str) -> Result<T, Error> {
+        let n = self.as_integer(key)?;
+        T::try_from(n).map_err(|_| err_msg(format!("{} is out of range for {}", n, key)))
+    }
+
+    /// A size in bytes, either an integer or a string read by `parse_size`.
+    fn as_size(&self, key: &str) -> Result<usize, Error> {
+        match *self {
+            TomlValue::String(ref s) => parse_size(s),
+            _ => self.as_integer_in(key),
+        }
+    }
+
+    fn as_bool(&self, key: &str) -> Result<bool, Error> {
+        match *self {
+            TomlValue::Bool(b) => Ok(b),
+            _ => Err(err_msg(format!("expected a boolean for {}", key))),
+        }
+    }
+
+    fn as_array(&self, key: &str) -> Result<&[TomlValue], Error> {
+        match *self {
+            TomlValue::Array(ref values) => Ok(values),
+            _ => Err(err_msg(format!("expected an array for {}", key))),
+        }
+    }
+}
+
+/// Removes duplicated peers and our own listening address.
+pub fn normalize_peers(peers: Vec<SocketAddr>, listen_port: u32) -> Vec<SocketAddr> {
+    let mut normalized: Vec<SocketAddr> = Vec::with_capacity(peers.len());
+
+    for addr in peers {
+        let ip = addr.ip();
+        let is_local = addr.port() as u32 == listen_port &&
+                       (ip.is_loopback() || ip.is_unspecified());
+
+        if !is_local && !normalized.contains(&addr) {
+            normalized.push(addr);
         }
-        Err(_e) => default_peers(network),
+    }
+
+    normalized
+}
+
+/// Parses a log level specification such as `info` or `warn,xmr_p2p=debug`.
+pub fn parse_log_level(s: &str) -> Result<LogLevel, Error> {
+    let mut log_level = LogLevel {
+        level: LevelFilter::Info,
+        modules: Vec::new(),
     };
 
-    let threads = value_t!(matches.value_of("threads"), usize).unwrap_or(1);
+    for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let mut parts = directive.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(level), None) => log_level.level = parse_level(level)?,
+            (Some(module), Some(level)) => {
+                log_level
+                    .modules
+                    .push((module.to_owned(), parse_level(level)?))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(log_level)
+}
+
+fn parse_level(s: &str) -> Result<LevelFilter, Error> {
+    match s {
+        "error" => Ok(LevelFilter::Error),
+        "warn" => Ok(LevelFilter::Warn),
+        "info" => Ok(LevelFilter::Info),
+        "debug" => Ok(LevelFilter::Debug),
+        "trace" => Ok(LevelFilter::Trace),
+        _ => {
+            Err(err_msg(format!("unknown log level \"{}\", expected one of error, warn, \
+                                 info, debug or trace",
+                                s)))
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use clap::{App, Arg};
+    use crate::db::BlockChainDatabase;
+    use crate::db::kv::MemoryDb;
+
+    fn memory_db() -> SharedStore {
+        Arc::new(BlockChainDatabase::new(MemoryDb::new()).unwrap())
+    }
+
+    #[test]
+    fn builder_defaults_match_the_command_line() {
+        let matches = App::new("dxmr")
+            .arg(Arg::with_name("testnet").long("testnet"))
+            .get_matches_from(vec!["dxmr", "--testnet"]);
+        let cli = parse_builder(&matches).unwrap().db(memory_db()).build().unwrap();
+        let built = ConfigBuilder::new()
+            .network(Network::Testnet)
+            .db(memory_db())
+            .build()
+            .unwrap();
+
+        assert_eq!(built.network, cli.network);
+        assert_eq!(built.peers, cli.peers);
+        assert_eq!(built.threads, cli.threads);
+        assert_eq!(built.listen_port, cli.listen_port);
+        assert_eq!(built.hide_my_port, cli.hide_my_port);
+        assert_eq!(built.out_peers, cli.out_peers);
+        assert_eq!(built.in_peers, cli.in_peers);
+        assert_eq!(built.log_level, cli.log_level);
+        assert_eq!(built.rpc_enabled, cli.rpc_enabled);
+        assert_eq!(built.rpc_bind, cli.rpc_bind);
+        assert_eq!(built.invoke_timeout, cli.invoke_timeout);
+        assert_eq!(built.max_packet_size, cli.max_packet_size);
+        assert_eq!(built.max_clock_skew, cli.max_clock_skew);
+        assert_eq!(built.use_ipv4, cli.use_ipv4);
+        assert_eq!(built.use_ipv6, cli.use_ipv6);
+    }
+
+    #[test]
+    fn build_programmatically() {
+        let a: SocketAddr = "1.2.3.4:28080".parse().unwrap();
+        let b: SocketAddr = "5.6.7.8:28080".parse().unwrap();
+
+        let config = ConfigBuilder::new()
+            .network(Network::Testnet)
+            .add_peer(a)
+            .add_peer(b)
+            .add_peer(a)
+            .threads(4)
+            .listen_port(28090)
+            .rpc_enabled(false)
+            .db(memory_db())
+            .build()
+            .unwrap();
+
+        assert_eq!(config.network, Network::Testnet);
+        assert_eq!(config.peers, Some(vec![a, b]));
+        assert_eq!(config.threads, 4);
+        assert_eq!(config.listen_port, Some(28090));
+        assert!(!config.rpc_enabled);
+        assert_eq!(config.rpc_bind, "127.0.0.1:28081".parse().unwrap());
+
+        assert!(ConfigBuilder::new().threads(0).db(memory_db()).build().is_err());
+    }
+
+    #[test]
+    fn build_resolves_and_opens_nothing() {
+        let config = ConfigBuilder::new().build().unwrap();
+        assert!(config.peers.is_none());
+        assert!(config.db.is_none());
+        assert!(!config.to_toml().lines().any(|line| line.starts_with("peers =")));
+
+        let db = memory_db();
+        let config = ConfigBuilder::new().db(db.clone()).build().unwrap();
+        assert!(Arc::ptr_eq(&config.open_db(), &db));
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let config = ConfigBuilder::new()
+            .network(Network::Testnet)
+            .peers(vec!["1.2.3.4:28080".parse().unwrap(), "[::2]:28080".parse().unwrap()])
+            .threads(4)
+            .listen_port(28090)
+            .hide_my_port(true)
+            .in_peers(3)
+            .log_level(parse_log_level("warn,xmr_p2p=debug").unwrap())
+            .rpc_enabled(false)
+            .invoke_timeout(Duration::from_millis(1500))
+            .max_clock_skew(Duration::from_secs(30))
+            .ipv6(false)
+            .db(memory_db())
+            .build()
+            .unwrap();
+
+        let path = ::std::env::temp_dir().join(format!("dxmr-config-{}.toml", ::std::process::id()));
+        fs::write(&path, config.to_toml()).unwrap();
+        let reloaded = ConfigBuilder::from_file(&path)
+            .unwrap()
+            .db(memory_db())
+            .build()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(reloaded.network, config.network);
+        assert_eq!(reloaded.peers, config.peers);
+        assert_eq!(reloaded.threads, config.threads);
+        assert_eq!(reloaded.listen_port, config.listen_port);
+        assert_eq!(reloaded.hide_my_port, config.hide_my_port);
+        assert_eq!(reloaded.out_peers, config.out_peers);
+        assert_eq!(reloaded.in_peers, config.in_peers);
+        assert_eq!(reloaded.log_level, config.log_level);
+        assert_eq!(reloaded.rpc_enabled, config.rpc_enabled);
+        assert_eq!(reloaded.rpc_bind, config.rpc_bind);
+        assert_eq!(reloaded.invoke_timeout, config.invoke_timeout);
+        assert_eq!(reloaded.max_packet_size, config.max_packet_size);
+        assert_eq!(reloaded.max_clock_skew, config.max_clock_skew);
+        assert_eq!(reloaded.use_ipv6, config.use_ipv6);
+        assert_eq!(reloaded.to_toml(), config.to_toml());
+    }
+
+    #[test]
+    fn network_option() {
+        let app = || {
+            App::new("dxmr")
+                .arg(Arg::with_name("network").long("network").takes_value(true))
+                .arg(Arg::with_name("testnet").long("testnet"))
+        };
+
+        let matches = app().get_matches_from(vec!["dxmr", "--network", "Testnet"]);
+        assert_eq!(parse_builder(&matches).unwrap().network, Some(Network::Testnet));
+
+        let matches = app().get_matches_from(vec!["dxmr", "--network", "moon"]);
+        assert_eq!(parse_builder(&matches).err().unwrap().to_string(),
+                   "unknown network \"moon\", expected mainnet or testnet");
+
+        assert_eq!(ConfigBuilder::from_toml("network = \"testnet\"").unwrap().network,
+                   Some(Network::Testnet));
+        assert!(ConfigBuilder::from_toml("network = \"moon\"").is_err());
+    }
+
+    #[test]
+    fn invalid_toml() {
+        assert!(ConfigBuilder::from_toml("threads = \"four\"").is_err());
+        assert!(ConfigBuilder::from_toml("unknown = 1").is_err());
+        assert!(ConfigBuilder::from_toml("threads").is_err());
+        assert!(ConfigBuilder::from_toml("# comment\n\nthreads = 2").is_ok());
+
+        let err = ConfigBuilder::from_toml("listen_port = 70000").err().unwrap();
+        assert_eq!(err.to_string(), "70000 is out of range for listen_port");
+        assert!(ConfigBuilder::from_toml("out_peers = 4294967296").is_err());
+        assert!(ConfigBuilder::from_toml("threads = -1").is_err());
+        assert!(ConfigBuilder::from_toml("max_packet_size = \"2TB\"").is_err());
+        assert!(ConfigBuilder::from_toml("max_packet_size = true").is_err());
+    }
+
+    #[test]
+    fn toml_sizes() {
+        let size = |toml: &str| ConfigBuilder::from_toml(toml).unwrap().max_packet_size;
+        assert_eq!(size("max_packet_size = 4096"), Some(4096));
+        assert_eq!(size("max_packet_size = \"4096\""), Some(4096));
+        assert_eq!(size("max_packet_size = \"2MB\""), Some(2 * 1024 * 1024));
+    }
+
+    #[test]
+    fn address_families() {
+        let app = App::new("dxmr")
+            .arg(Arg::with_name("noipv4").long("no-ipv4"))
+            .arg(Arg::with_name("noipv6").long("no-ipv6"));
+
+        let matches = app.clone().get_matches_from(vec!["dxmr", "--no-ipv4"]);
+        let config = parse_builder(&matches).unwrap().db(memory_db()).build().unwrap();
+        assert!(!config.use_ipv4);
+        assert!(config.use_ipv6);
+
+        let matches = app.get_matches_from(vec!["dxmr", "--no-ipv4", "--no-ipv6"]);
+        let err = parse_builder(&matches).unwrap().db(memory_db()).build().err().unwrap();
+        assert!(format!("{}", err).contains("can't both be disabled"));
+    }
+
+    #[test]
+    fn parse_levels() {
+        let levels = [("error", LevelFilter::Error),
+                      ("warn", LevelFilter::Warn),
+                      ("info", LevelFilter::Info),
+                      ("debug", LevelFilter::Debug),
+                      ("trace", LevelFilter::Trace)];
+
+        for &(s, level) in levels.iter() {
+            assert_eq!(parse_log_level(s).unwrap(),
+                       LogLevel {
+                           level,
+                           modules: vec![],
+                       });
+        }
+    }
+
+    #[test]
+    fn parse_module_overrides() {
+        assert_eq!(parse_log_level("warn,xmr_p2p=debug").unwrap(),
+                   LogLevel {
+                       level: LevelFilter::Warn,
+                       modules: vec![("xmr_p2p".to_owned(), LevelFilter::Debug)],
+                   });
+    }
+
+    #[test]
+    fn parse_unknown_level() {
+        let err = parse_log_level("verbose").unwrap_err();
+        assert!(format!("{}", err).contains("unknown log level \"verbose\""));
+
+        assert!(parse_log_level("xmr_p2p=loud").is_err());
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+
+        let err = parse_duration("10xyz").unwrap_err();
+        assert!(format!("{}", err).contains("unknown duration unit \"xyz\""));
+        assert!(parse_duration("s").is_err());
+
+        let err = parse_duration("999999999999999999h").unwrap_err();
+        assert!(format!("{}", err).contains("duration \"999999999999999999h\" is too large"));
+        assert!(parse_duration("999999999999999999m").is_err());
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("100B").unwrap(), 100);
+        assert_eq!(parse_size("500KB").unwrap(), 500 * 1024);
+        assert_eq!(parse_size("2MB").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("1gb").unwrap(), 1024 * 1024 * 1024);
+
+        let err = parse_size("10xyz").unwrap_err();
+        assert!(format!("{}", err).contains("unknown size unit \"xyz\""));
+        assert!(parse_size("99999999999999999999GB").is_err());
+    }
+
+    #[test]
+    fn duplicated_peers_collapse() {
+        let a: SocketAddr = "1.2.3.4:18080".parse().unwrap();
+        let b: SocketAddr = "5.6.7.8:18080".parse().unwrap();
+
+        assert_eq!(normalize_peers(vec![a, b, a, a], 18080), vec![a, b]);
+    }
+
+    #[test]
+    fn peers_file_merges_with_connect() {
+        let path = std::env::temp_dir().join(format!("dxmr-peers-{}", std::process::id()));
+        fs::write(&path, "# peers\n1.2.3.4:18080\n5.6.7.8:18080\n").unwrap();
+
+        let app = App::new("dxmr")
+            .arg(Arg::with_name("connect").long("connect").takes_value(true).multiple(true)
+                     .number_of_values(1))
+            .arg(Arg::with_name("peersfile").long("peers-file").takes_value(true));
+        let matches = app.get_matches_from(vec!["dxmr",
+                                                "--connect",
+                                                "9.9.9.9:18080",
+                                                "--connect",
+                                                "1.2.3.4:18080",
+                                                "--peers-file",
+                                                path.to_str().unwrap()]);
+        let config = parse_builder(&matches).unwrap().db(memory_db()).build().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.peers,
+                   Some(vec!["9.9.9.9:18080".parse().unwrap(),
+                             "1.2.3.4:18080".parse().unwrap(),
+                             "5.6.7.8:18080".parse().unwrap()]));
+    }
+
+    #[test]
+    fn local_listen_address_is_excluded() {
+        let peers = vec!["127.0.0.1:18080".parse().unwrap(),
+                         "0.0.0.0:18080".parse().unwrap(),
+                         "[::1]:18080".parse().unwrap(),
+                         "127.0.0.1:18081".parse().unwrap(),
+                         "1.2.3.4:18080".parse().unwrap()];
+
+        assert_eq!(normalize_peers(peers, 18080),
+                   vec!["127.0.0.1:18081".parse().unwrap(),
+                        "1.2.3.4:18080".parse().unwrap()]);
+    }
+
+    #[test]
+    fn colliding_ports_are_rejected() {
+        let err = ConfigBuilder::new()
+            .listen_port(28081)
+            .rpc_bind("127.0.0.1:28081".parse().unwrap())
+            .db(memory_db())
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(),
+                   "listen port (--listenport) and RPC port (--rpc-bind) are both set to 28081");
 
-    let listen_port = value_t!(matches.value_of("listenport"), u16).ok();
+        let err = ConfigBuilder::new()
+            .network(Network::Testnet)
+            .rpc_bind("0.0.0.0:28080".parse().unwrap())
+            .db(memory_db())
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("--rpc-bind"));
+    }
 
-    let hide_my_port = matches.is_present("hidemyport");
+    #[test]
+    fn distinct_ports_pass() {
+        assert!(ConfigBuilder::new()
+                    .listen_port(28080)
+                    .rpc_bind("127.0.0.1:28081".parse().unwrap())
+                    .db(memory_db())
+                    .build()
+                    .is_ok());
 
-    let out_peers = value_t!(matches.value_of("outpeers"), u32).unwrap_or(10);
-    let in_peers = value_t!(matches.value_of("inpeers"), u32).unwrap_or(10);
+        // ports that aren't bound can't collide.
+        assert!(ConfigBuilder::new()
+                    .listen_port(28081)
+                    .rpc_bind("127.0.0.1:28081".parse().unwrap())
+                    .rpc_enabled(false)
+                    .db(memory_db())
+                    .build()
+                    .is_ok());
+        assert!(check_port_collisions(&[("a", 1), ("b", 2), ("c", 3)]).is_ok());
+        assert!(check_port_collisions(&[("a", 1), ("b", 2), ("c", 1)]).is_err());
+    }
 
-    let db = utils::open_db();
+    #[test]
+    fn default_rpc_bind() {
+        assert_eq!(parse_rpc_bind(None, Network::Mainnet).unwrap(),
+                   "127.0.0.1:18081".parse().unwrap());
+        assert_eq!(parse_rpc_bind(None, Network::Testnet).unwrap(),
+                   "127.0.0.1:28081".parse().unwrap());
+    }
 
-    Ok(Config {
-           network,
-           peers,
-           threads,
-           listen_port,
-           hide_my_port,
-           out_peers,
-           in_peers,
-           db,
-       })
+    #[test]
+    fn explicit_rpc_bind() {
+        assert_eq!(parse_rpc_bind(Some("0.0.0.0:8080"), Network::Mainnet).unwrap(),
+                   "0.0.0.0:8080".parse().unwrap());
+        assert!(parse_rpc_bind(Some("localhost"), Network::Mainnet).is_err());
+    }
 }
@@ -0,0 +1,44 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Configuration, peers and setup of the `dxmr` node.
+
+extern crate app_dirs;
+#[macro_use]
+extern crate clap;
+
+extern crate env_logger;
+#[macro_use]
+extern crate log;
+
+extern crate failure;
+
+extern crate xmr_chain as chain;
+extern crate xmr_db as db;
+extern crate xmr_network as network;
+extern crate xmr_p2p as p2p;
+extern crate xmr_storage as storage;
+
+pub mod config;
+pub mod peers;
+pub mod utils;
+
+use app_dirs::AppInfo;
+
+pub const APP_INFO: AppInfo = AppInfo {
+    name: "dxmr",
+    author: "Jean Pierre Dudey",
+};
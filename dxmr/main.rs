@@ -14,68 +14,87 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-extern crate app_dirs;
 #[macro_use]
 extern crate clap;
 
-extern crate env_logger;
+#[macro_use]
 extern crate log;
 
 extern crate failure;
 
 extern crate rand;
 
-extern crate xmr_chain as chain;
-extern crate xmr_db as db;
-extern crate xmr_network as network;
+extern crate xmr;
 extern crate xmr_p2p as p2p;
-extern crate xmr_storage as storage;
 extern crate xmr_sync as sync;
 
-mod config;
-mod peers;
-mod utils;
+use std::process;
+use std::time::Duration;
 
 use failure::Error;
-use app_dirs::AppInfo;
 
-pub const APP_INFO: AppInfo = AppInfo {
-    name: "dxmr",
-    author: "Jean Pierre Dudey",
-};
+use xmr::{config, utils};
 
 fn main() {
-    env_logger::init();
-
     let matches = clap_app!(dxmr =>
         (version: "0.1.0")
         (author: "Jean Pierre Dudey <jeandudey@hotmail.com>")
         (about: "Monero client")
+        (@arg config: --config +takes_value "Read the configuration from a TOML file, command line options take precedence")
+        (@arg printconfig: --("print-config") "Print the effective configuration and exit")
         (@arg threads: --threads +takes_value "Number of threads")
-        (@arg testnet: --testnet "Use the test network")
-        (@arg connect: --connect +takes_value "Connect only to the given peer")
+        (@arg network: --network +takes_value conflicts_with[testnet] "Network to use, mainnet or testnet (default mainnet)")
+        (@arg testnet: --testnet "Use the test network, same as --network testnet")
+        (@arg connect: --connect +takes_value +multiple number_of_values(1) "Connect only to the given peers")
+        (@arg peersfile: --("peers-file") +takes_value "Connect only to the peers in the given file, one ip:port per line")
         (@arg listenport: --listenport +takes_value )
         (@arg hidemyport: --hidemyport)
         (@arg outpeers: --outpeers +takes_value "Maximum of outbound peers")
         (@arg inpeers: --inpeers +takes_value "Maximum of outbound peers")
+        (@arg rpcbind: --("rpc-bind") +takes_value "Address of the RPC server (default 127.0.0.1:18081, 28081 on testnet)")
+        (@arg norpc: --("no-rpc") "Disable the RPC server")
+        (@arg invoketimeout: --("invoke-timeout") +takes_value "Time to wait for a peer's response, e.g. 30s or 2m")
+        (@arg maxpacketsize: --("max-packet-size") +takes_value "Maximum size of a received packet, e.g. 500KB or 100MB")
+        (@arg maxclockskew: --("max-clock-skew") +takes_value "Maximum difference between a peer's clock and ours, e.g. 10m")
+        (@arg noipv4: --("no-ipv4") "Don't listen on or connect to IPv4 addresses")
+        (@arg noipv6: --("no-ipv6") "Don't listen on or connect to IPv6 addresses")
+        (@arg loglevel: --("log-level") +takes_value "Log level: error, warn, info, debug or trace, with optional per-module overrides (e.g. info,xmr_p2p=debug)")
     )
             .get_matches();
 
-    // TODO: no unwrap
-    let cfg = config::parse(&matches).unwrap();
+    let cfg = match config::parse(&matches) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
 
-    if let Err(e) = start(cfg) {
-        println!("{}", e);
+    if matches.is_present("printconfig") {
+        print!("{}", cfg.to_toml());
         return;
     }
+
+    utils::init_logger(&cfg);
+
+    if let Err(e) = start(cfg) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
 
 fn start(cfg: config::Config) -> Result<(), Error> {
-    utils::init_db(&cfg);
+    let db = cfg.open_db();
+    utils::init_db(&db, cfg.network);
+
+    if cfg.rpc_enabled {
+        // TODO: spawn the RPC server.
+        warn!("the RPC server isn't implemented yet, not binding {}", cfg.rpc_bind);
+    }
 
     let mut el = p2p::event_loop();
 
-    let local_node = sync::create_local_node(cfg.db.clone(), cfg.network);
+    let local_node = sync::create_local_node(db.clone(), cfg.network);
     let local_sync_node = sync::create_local_sync_node(local_node.clone());
 
     let mut rng = rand::OsRng::new().expect("couldn't open OS random");
@@ -83,15 +102,27 @@ fn start(cfg: config::Config) -> Result<(), Error> {
     let config = p2p::Config {
         threads: cfg.threads,
         network: cfg.network,
-        peers: cfg.peers,
+        peers: cfg.peers_or_seeds(),
         listen_port: cfg.listen_port,
         hide_my_port: cfg.hide_my_port,
         out_peers: cfg.out_peers,
         in_peers: cfg.in_peers,
         peer_id: p2p::types::PeerId::random(&mut rng),
+        invoke_timeout: cfg.invoke_timeout,
+        max_packet_size: cfg.max_packet_size,
+        max_clock_skew: cfg.max_clock_skew,
+        socket_options: p2p::config::SocketOptions::default(),
+        max_connections_per_ip: p2p::config::DEFAULT_MAX_CONNECTIONS_PER_IP,
+        max_handshakes_per_ip: p2p::config::DEFAULT_MAX_HANDSHAKES_PER_IP,
+        use_ipv4: cfg.use_ipv4,
+        use_ipv6: cfg.use_ipv6,
+        priority_peers: Vec::new(),
+        priority_redial_interval:
+            Duration::from_secs(p2p::config::DEFAULT_PRIORITY_REDIAL_INTERVAL),
+        priority_peers_use_out_slots: false,
     };
 
-    let p2p = p2p::P2P::new(config, el.handle(), cfg.db.clone(), local_sync_node);
+    let p2p = p2p::P2P::new(config, el.handle(), db, local_sync_node);
 
     p2p.run().expect("couldn't start p2p");
 
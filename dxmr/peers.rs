@@ -14,35 +14,126 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::fs;
+use std::io;
 use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+
+use failure::{Error, err_msg};
 use crate::network::Network;
 
+/// The network's hard coded seed nodes.
 pub fn default_peers(network: Network) -> Vec<SocketAddr> {
-    let addrs: &[&'static str] = match network {
-        Network::Testnet => {
-            &["212.83.175.67:28080",
-              "5.9.100.248:28080",
-              "163.172.182.165:28080",
-              "195.154.123.123:28080",
-              "212.83.172.165:28080"]
-        }
-        Network::Mainnet => {
-            &["107.152.130.98:18080",
-              "212.83.175.67:18080",
-              "5.9.100.248:18080",
-              "163.172.182.165:18080",
-              "161.67.132.39:18080",
-              "198.74.231.92:18080",
-              "195.154.123.123:28080",
-              "212.83.172.165:28080"]
+    network
+        .seed_nodes()
+        .iter()
+        .map(|addr| addr.parse().expect("seed nodes are valid addresses"))
+        .collect()
+}
+
+/// Resolves the network's seed nodes, falling back to the hard coded
+/// peers if none of them resolves.
+pub fn resolve_seeds(network: Network) -> Vec<SocketAddr> {
+    resolve_seeds_with(network, |host, port| {
+        (host, port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect())
+    })
+}
+
+/// Same as `resolve_seeds`, but with a custom DNS resolver.
+pub fn resolve_seeds_with<F>(network: Network, resolve: F) -> Vec<SocketAddr>
+    where F: Fn(&str, u16) -> io::Result<Vec<SocketAddr>>
+{
+    let port = network.listen_port() as u16;
+
+    let mut addrs = Vec::new();
+    for host in network.seed_hostnames() {
+        match resolve(host, port) {
+            Ok(resolved) => addrs.extend(resolved),
+            Err(e) => warn!("couldn't resolve seed node {}: {}", host, e),
         }
-    };
+    }
+
+    if addrs.is_empty() {
+        info!("no seed node resolved, using the hard coded peers.");
+        return default_peers(network);
+    }
 
-    // TODO: use expect instead of unwrap.
     addrs
-        .iter()
-        .map(|addr| (*addr).to_socket_addrs().unwrap().next().unwrap())
-        .collect()
 }
 
-// TODO: Add support for seed nodes
+/// Reads a peers file, see `parse_peers`.
+pub fn read_peers_file<P: AsRef<Path>>(path: P) -> Result<Vec<SocketAddr>, Error> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .map_err(|e| err_msg(format!("couldn't read {}: {}", path.display(), e)))?;
+    parse_peers(&contents)
+        .map_err(|e| err_msg(format!("{}: {}", path.display(), e)))
+}
+
+/// Parses one `ip:port` peer per line, blank lines and `#` comments are
+/// skipped.
+pub fn parse_peers(contents: &str) -> Result<Vec<SocketAddr>, Error> {
+    let mut peers = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let peer = line.parse().map_err(|_| {
+            err_msg(format!("line {}: invalid peer address \"{}\"", number + 1, line))
+        })?;
+        peers.push(peer);
+    }
+
+    Ok(peers)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_failure_falls_back_to_default_peers() {
+        let peers = resolve_seeds_with(Network::Mainnet, |_, _| {
+            Err(io::Error::new(io::ErrorKind::Other, "no dns"))
+        });
+
+        assert!(!peers.is_empty());
+        assert_eq!(peers, default_peers(Network::Mainnet));
+    }
+
+    #[test]
+    fn resolved_seeds_use_listen_port() {
+        let peers = resolve_seeds_with(Network::Mainnet,
+                                       |_, port| Ok(vec![SocketAddr::from(([1, 2, 3, 4], port))]));
+
+        assert_eq!(peers.len(), Network::Mainnet.seed_hostnames().len());
+        assert!(peers.iter().all(|addr| addr.port() == 18080));
+    }
+
+    #[test]
+    fn peers_file() {
+        let peers = parse_peers("1.2.3.4:18080\n[2001:db8::1]:18080\n5.6.7.8:28080").unwrap();
+        assert_eq!(peers,
+                   vec!["1.2.3.4:18080".parse().unwrap(),
+                        "[2001:db8::1]:18080".parse().unwrap(),
+                        "5.6.7.8:28080".parse().unwrap()]);
+    }
+
+    #[test]
+    fn peers_file_comments_and_blanks() {
+        let peers = parse_peers("# curated peers\n\n  1.2.3.4:18080  \n\t\n# 5.6.7.8:18080\n")
+            .unwrap();
+        assert_eq!(peers, vec!["1.2.3.4:18080".parse().unwrap()]);
+        assert!(parse_peers("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn peers_file_malformed_line() {
+        let err = parse_peers("1.2.3.4:18080\n\n1.2.3.4\n5.6.7.8:18080").unwrap_err();
+        assert_eq!(format!("{}", err), "line 3: invalid peer address \"1.2.3.4\"");
+    }
+}
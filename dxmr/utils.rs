@@ -17,9 +17,11 @@
 use std::sync::Arc;
 
 use app_dirs::{AppDataType, app_dir};
+use env_logger::Builder;
 
 use crate::chain::IndexedBlock;
 use crate::db::BlockChainDatabase;
+use crate::network::Network;
 use crate::storage::SharedStore;
 
 use crate::config::Config;
@@ -35,10 +37,27 @@ pub fn open_db() -> SharedStore {
     Arc::new(db)
 }
 
-pub fn init_db(cfg: &Config) {
-    let genesis_block: IndexedBlock = cfg.network.genesis_block().into();
+/// Initializes the logger, `RUST_LOG` is used when no log level is configured.
+pub fn init_logger(cfg: &Config) {
+    let mut builder = match cfg.log_level {
+        Some(ref log_level) => {
+            let mut builder = Builder::new();
+            builder.filter(None, log_level.level);
+            for &(ref module, level) in log_level.modules.iter() {
+                builder.filter(Some(module), level);
+            }
+            builder
+        }
+        None => Builder::from_default_env(),
+    };
+
+    builder.init();
+}
+
+pub fn init_db(db: &SharedStore, network: Network) {
+    let genesis_block: IndexedBlock = network.genesis_block().into();
 
-    match cfg.db.block_id(0) {
+    match db.block_id(0) {
         Some(ref id) => {
             if id != genesis_block.id() {
                 panic!("trying to open database with incompatible genesis block")
@@ -46,13 +65,8 @@ pub fn init_db(cfg: &Config) {
         }
         None => {
             let id = genesis_block.id().clone();
-            cfg.db
-                .insert(genesis_block)
-                .expect("couldn't insert genesis block");
-
-            cfg.db
-                .canonize(&id)
-                .expect("couldn't canonize genesis block");
+            db.insert(genesis_block).expect("couldn't insert genesis block");
+            db.canonize(&id).expect("couldn't canonize genesis block");
         }
     }
 }
@@ -15,7 +15,7 @@ use utils::fmt_byte_slice;
 /// Public Key length in bytes.
 pub const PUBLIC_KEY_LENGTH: usize = 32;
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Eq, PartialEq, Hash)]
 pub struct PublicKey(pub [u8; PUBLIC_KEY_LENGTH]);
 
 impl PublicKey {
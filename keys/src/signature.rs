@@ -7,6 +7,7 @@
 // except according to those terms.
 
 use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
 
 use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream};
 
@@ -62,6 +63,20 @@ impl Clone for Signature {
     }
 }
 
+impl PartialEq for Signature {
+    fn eq(&self, other: &Signature) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for Signature {}
+
+impl Hash for Signature {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0[..].hash(state)
+    }
+}
+
 impl Debug for Signature {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
         fmt_byte_slice(&self.0, fmt)
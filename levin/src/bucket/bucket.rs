@@ -17,10 +17,11 @@ use bytes::{Bytes, BytesMut, IntoBuf};
 use portable_storage::{self, Section};
 
 use bucket::bucket_head::{BucketHead, LEVIN_SIGNATURE, LEVIN_PROTOCOL_VER_1, LEVIN_OK,
-                          LEVIN_PACKET_REQUEST, LEVIN_PACKET_RESPONSE, BUCKET_HEAD_LENGTH};
+                          LEVIN_PACKET_REQUEST, LEVIN_PACKET_RESPONSE, BUCKET_HEAD_LENGTH,
+                          LEVIN_DEFAULT_MAX_PACKET_SIZE};
 
 use command::Id;
-use error::Result;
+use error::{Error, Result};
 
 /// A levin bucket, this is the packet of information
 /// that carries commands in the levin protocol.
@@ -118,9 +119,21 @@ impl Bucket {
     /// Creates a future that will read a bucket from the provided stream.
     pub fn receive_future<A>(a: A) -> Receive<A>
         where A: AsyncRead
+    {
+        Bucket::receive_future_with_limit(a, LEVIN_DEFAULT_MAX_PACKET_SIZE)
+    }
+
+    /// Creates a future that will read a bucket from the provided stream,
+    /// buckets bigger than `max_packet_size` result in
+    /// `Error::PacketTooBig` without their body being read.
+    pub fn receive_future_with_limit<A>(a: A, max_packet_size: u64) -> Receive<A>
+        where A: AsyncRead
     {
         let buf = vec![0u8; BUCKET_HEAD_LENGTH];
-        Receive { state: ReceiveState::ReadBucket { reader: read_exact(a, buf) } }
+        Receive {
+            state: ReceiveState::ReadBucket { reader: read_exact(a, buf) },
+            max_packet_size,
+        }
     }
 
     /// Convert the body of this bucket into a portable storage section.
@@ -149,6 +162,7 @@ impl Bucket {
 #[derive(Debug)]
 pub struct Receive<A: AsyncRead> {
     state: ReceiveState<A>,
+    max_packet_size: u64,
 }
 
 #[derive(Debug)]
@@ -183,6 +197,15 @@ impl<A> Future for Receive<A>
 
                     trace!("receive poll - bucket received: {:?}", bucket_head);
 
+                    if bucket_head.cb > self.max_packet_size {
+                        let e = Error::PacketTooBig {
+                            command: bucket_head.command,
+                            size: bucket_head.cb,
+                            expect_response: bucket_head.have_to_return_data,
+                        };
+                        return Ok((stream, Err(e)).into());
+                    }
+
                     let buf = vec![0u8; bucket_head.cb as usize];
                     ReceiveState::ReadStorage {
                         bucket_head,
@@ -210,3 +233,38 @@ impl<A> Future for Receive<A>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn too_big_bucket_body_is_not_read() {
+        let mut big = Bucket::invokation(1001, Section::new());
+        big.body = BytesMut::from(vec![0xaa; 4097]);
+        big.head.cb = big.body.len() as u64;
+        let stream = Cursor::new(big.to_bytes());
+
+        let (stream, result) = Bucket::receive_future_with_limit(stream, 1000)
+            .wait()
+            .unwrap();
+        match result {
+            Err(Error::PacketTooBig { command: 1001, size: 4097, expect_response: true }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(stream.position(), BUCKET_HEAD_LENGTH as u64);
+
+        let mut big = Bucket::notification(1002, Section::new());
+        big.head.cb = 1001;
+        let stream = Cursor::new(big.to_bytes());
+        let (_, result) = Bucket::receive_future_with_limit(stream, 1000)
+            .wait()
+            .unwrap();
+        match result {
+            Err(Error::PacketTooBig { command: 1002, expect_response: false, .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
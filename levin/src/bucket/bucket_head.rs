@@ -30,6 +30,9 @@ pub const BUCKET_HEAD_LENGTH: usize = 33;
 /// Ok return code.
 pub const LEVIN_OK: i32 = 0;
 
+/// Return code of a bucket with an invalid format.
+pub const LEVIN_ERROR_FORMAT: i32 = -7;
+
 /// Header of a levin bucket.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct BucketHead {
@@ -17,4 +17,4 @@ mod bucket_head;
 pub use self::bucket::{Bucket, Receive};
 pub use self::bucket_head::{BucketHead, LEVIN_SIGNATURE, LEVIN_PACKET_REQUEST,
                             LEVIN_PACKET_RESPONSE, LEVIN_PROTOCOL_VER_1, BUCKET_HEAD_LENGTH,
-                            LEVIN_OK, LEVIN_DEFAULT_MAX_PACKET_SIZE};
+                            LEVIN_OK, LEVIN_ERROR_FORMAT, LEVIN_DEFAULT_MAX_PACKET_SIZE};
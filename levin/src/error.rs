@@ -22,6 +22,18 @@ pub enum Error {
     /// The command is invalid.
     #[fail(display = "the bucket command id ({}) is invalid", _0)]
     InvalidCommandId(u32),
+
+    /// The bucket of a command is bigger than the accepted size, its body
+    /// wasn't read.
+    #[fail(display = "the bucket of command #{} is too big ({} bytes)", command, size)]
+    PacketTooBig {
+        /// The command of the bucket.
+        command: u32,
+        /// The size of the bucket body.
+        size: u64,
+        /// Does the sender expect a response?
+        expect_response: bool,
+    },
 }
 
 impl From<BucketHeadError> for Error {
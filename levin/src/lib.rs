@@ -34,6 +34,7 @@
 extern crate futures;
 extern crate tokio_core;
 extern crate tokio_io;
+extern crate net2;
 
 extern crate crossbeam;
 extern crate parking_lot;
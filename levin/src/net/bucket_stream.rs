@@ -7,27 +7,42 @@
 // except according to those terms.
 
 use std::io;
-use std::mem::replace;
 
 use futures::{Future, Poll, Async};
 use futures::stream::Stream;
 
 use tokio_io::AsyncRead;
 
-use bucket::{Bucket, Receive};
-use error::Result;
+use bucket::{Bucket, Receive, LEVIN_DEFAULT_MAX_PACKET_SIZE};
+use error::{Error, Result};
 
 /// Creates the bucket stream.
 pub fn bucket_stream<A>(a: A) -> BucketStream<A>
     where A: AsyncRead
 {
-    BucketStream { future: Bucket::receive_future(a) }
+    bucket_stream_with_limit(a, LEVIN_DEFAULT_MAX_PACKET_SIZE)
+}
+
+/// Creates a bucket stream that stops at the first bucket bigger than
+/// `max_packet_size`.
+pub fn bucket_stream_with_limit<A>(a: A, max_packet_size: u64) -> BucketStream<A>
+    where A: AsyncRead
+{
+    BucketStream {
+        future: Some(Bucket::receive_future_with_limit(a, max_packet_size)),
+        max_packet_size,
+    }
 }
 
 /// A stream of buckets.
+///
+/// The body of a too big bucket is left unread, so nothing is read after
+/// `Error::PacketTooBig` and the stream never yields again, the connection
+/// is expected to be closed.
 #[derive(Debug)]
 pub struct BucketStream<A: AsyncRead> {
-    future: Receive<A>,
+    future: Option<Receive<A>>,
+    max_packet_size: u64,
 }
 
 impl<A> Stream for BucketStream<A>
@@ -37,9 +52,15 @@ impl<A> Stream for BucketStream<A>
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, io::Error> {
-        let (stream, result) = try_ready!(self.future.poll());
+        let (stream, result) = match self.future {
+            Some(ref mut future) => try_ready!(future.poll()),
+            None => return Ok(Async::NotReady),
+        };
 
-        replace(&mut self.future, Bucket::receive_future(stream));
+        self.future = match result {
+            Err(Error::PacketTooBig { .. }) => None,
+            _ => Some(Bucket::receive_future_with_limit(stream, self.max_packet_size)),
+        };
 
         Ok(Async::Ready(Some(result)))
     }
@@ -9,6 +9,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use bucket::LEVIN_DEFAULT_MAX_PACKET_SIZE;
 use command::{Id, Command};
 use net::handlers::{NotificationHandler, InvokationHandler, RemoteHandler};
 
@@ -20,17 +21,21 @@ pub type IoHandlerRef = Arc<IoHandler>;
 #[derive(Clone)]
 pub struct IoHandler {
     handlers: HashMap<Id, RemoteHandler>,
+    max_packet_size: u64,
 }
 
 impl IoHandler {
     /// Creates an empty `IoHandler`.
     pub fn new() -> IoHandler {
-        IoHandler { handlers: HashMap::new() }
+        IoHandler::with_capacity(0)
     }
 
     /// Creates an `IoHandler` with the given capacity.
     pub fn with_capacity(cap: usize) -> IoHandler {
-        IoHandler { handlers: HashMap::with_capacity(cap) }
+        IoHandler {
+            handlers: HashMap::with_capacity(cap),
+            max_packet_size: LEVIN_DEFAULT_MAX_PACKET_SIZE,
+        }
     }
 
     /// Add a notification to this handler.
@@ -59,6 +64,17 @@ impl IoHandler {
         trace!("Adding invokation #{}", C::ID);
     }
 
+    /// Sets the maximum size of the received buckets, bigger buckets are
+    /// answered with `LEVIN_ERROR_FORMAT`.
+    pub fn set_max_packet_size(&mut self, max_packet_size: u64) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    /// The maximum size of the received buckets.
+    pub fn max_packet_size(&self) -> u64 {
+        self.max_packet_size
+    }
+
     /// Get a handler.
     pub(crate) fn get(&self, id: Id) -> Option<RemoteHandler> {
         self.handlers.get(&id).cloned()
@@ -12,12 +12,14 @@ mod bucket_sink;
 mod bucket_stream;
 mod handlers;
 mod io;
+mod socket;
 mod tcp_server;
 mod tcp_client;
 
 pub use self::bucket_sink::{BucketSink, bucket_sink};
-pub use self::bucket_stream::{BucketStream, bucket_stream};
+pub use self::bucket_stream::{BucketStream, bucket_stream, bucket_stream_with_limit};
 pub use self::handlers::{NotificationHandler, InvokationHandler, RemoteHandler};
 pub use self::io::{IoHandler, IoHandlerRef};
+pub use self::socket::{DEFAULT_LISTEN_BACKLOG, SocketOptions};
 pub use self::tcp_server::{TcpServer, ConnectionHandler, ConnectionHandlerRef};
 pub use self::tcp_client::{connect, Commands};
@@ -0,0 +1,109 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::io;
+use std::net::SocketAddr;
+
+use net2::TcpBuilder;
+
+use tokio_core::net::{TcpListener, TcpStream};
+use tokio_core::reactor::Handle;
+
+/// Default size of the pending connections queue of a listener.
+pub const DEFAULT_LISTEN_BACKLOG: i32 = 1024;
+
+/// TCP options applied to the listener and to every connection.
+///
+/// The defaults are the ones used by `tokio_core::net::TcpListener::bind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Size of the pending connections queue of the listener.
+    pub backlog: i32,
+    /// Set `SO_REUSEADDR` on the listener.
+    pub reuse_address: bool,
+    /// Set `TCP_NODELAY` on the accepted and outbound connections.
+    pub nodelay: bool,
+    /// Set `IPV6_V6ONLY` on IPv6 listeners, so they leave the IPv4 port
+    /// free for another listener.
+    pub only_v6: bool,
+}
+
+impl Default for SocketOptions {
+    fn default() -> SocketOptions {
+        SocketOptions {
+            backlog: DEFAULT_LISTEN_BACKLOG,
+            reuse_address: true,
+            nodelay: false,
+            only_v6: false,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Binds a listener to `addr` using these options.
+    pub fn bind(&self, addr: &SocketAddr, handle: &Handle) -> io::Result<TcpListener> {
+        let builder = match *addr {
+            SocketAddr::V4(..) => TcpBuilder::new_v4()?,
+            SocketAddr::V6(..) => {
+                let builder = TcpBuilder::new_v6()?;
+                builder.only_v6(self.only_v6)?;
+                builder
+            }
+        };
+
+        builder.reuse_address(self.reuse_address)?;
+        builder.bind(addr)?;
+        let listener = builder.listen(self.backlog)?;
+
+        TcpListener::from_listener(listener, addr, handle)
+    }
+
+    /// Applies the per-connection options to `stream`.
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpStream as StdTcpStream;
+
+    use futures::{Future, Stream};
+    use tokio_core::reactor::Core;
+
+    fn accept(options: SocketOptions) -> TcpStream {
+        let mut core = Core::new().unwrap();
+        let listener = options.bind(&"127.0.0.1:0".parse().unwrap(), &core.handle()).unwrap();
+        let _client = StdTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let accepted = listener.incoming().into_future().map_err(|(e, _)| e);
+        let (stream, _) = core.run(accepted).unwrap().0.unwrap();
+        options.apply(&stream).unwrap();
+        stream
+    }
+
+    #[test]
+    fn nodelay_is_applied_to_accepted_connections() {
+        let options = SocketOptions { nodelay: true, ..SocketOptions::default() };
+        assert!(accept(options).nodelay().unwrap());
+        assert!(!accept(SocketOptions::default()).nodelay().unwrap());
+    }
+
+    #[test]
+    fn only_v6_leaves_the_ipv4_port_free() {
+        let core = Core::new().unwrap();
+        let options = SocketOptions { only_v6: true, ..SocketOptions::default() };
+        let v6 = options.bind(&"[::]:0".parse().unwrap(), &core.handle()).unwrap();
+
+        let port = v6.local_addr().unwrap().port();
+        let v4 = SocketAddr::from(([0, 0, 0, 0], port));
+        assert!(options.bind(&v4, &core.handle()).is_ok());
+    }
+}
@@ -23,27 +23,34 @@ use parking_lot::RwLock;
 
 use portable_storage::Section;
 
-use bucket::Bucket;
+use bucket::{Bucket, LEVIN_ERROR_FORMAT};
 use command::{Command, Id};
+use error::Error;
 use net::io::IoHandlerRef;
+use net::socket::SocketOptions;
 use net::handlers::RemoteHandler;
-use net::bucket_stream::bucket_stream;
+use net::bucket_stream::bucket_stream_with_limit;
 use net::bucket_sink::bucket_sink;
 
 /// Connects to a levin server.
 pub fn connect(addr: &SocketAddr,
                handle: &Handle,
+               options: SocketOptions,
                io_handler: IoHandlerRef,
                commands: Commands)
                -> Box<Future<Item = (), Error = io::Error> + Send + Sync + 'static> {
     let addr = addr.clone();
     Box::new(TcpStream::connect(&addr, handle).and_then(move |stream| {
+        if let Err(e) = options.apply(&stream) {
+            warn!("couldn't set socket options for {}: {}", addr, e);
+        }
+
         let io_handler = io_handler.clone();
         let commands = commands.clone();
 
         let (read_half, write_half) = stream.split();
 
-        let buckets = bucket_stream(read_half);
+        let buckets = bucket_stream_with_limit(read_half, io_handler.max_packet_size());
 
         let commands_ = commands.clone();
         let receiver = buckets.for_each(move |bucket| {
@@ -52,6 +59,16 @@ pub fn connect(addr: &SocketAddr,
 
             let bucket = match bucket {
                 Ok(b) => b,
+                Err(Error::PacketTooBig { command, size, expect_response }) => {
+                    warn!("received too big bucket #{} ({} bytes), closing the connection",
+                          command,
+                          size);
+                    if expect_response {
+                        commands.error_response(command, LEVIN_ERROR_FORMAT);
+                    }
+                    commands.close();
+                    return future::ok::<(), io::Error>(());
+                }
                 Err(e) => {
                     warn!("received invalid bucket: {}", e);
                     return future::ok::<(), io::Error>(());
@@ -112,9 +129,12 @@ pub fn connect(addr: &SocketAddr,
         let bucket_sender = bucket_sink(write_half);
 
         let sender = commands.forward(bucket_sender)
-            .map(|(_, sender)| sender.inner().unwrap().shutdown());
+            .map(|(_, sender)| {
+                     let _ = sender.inner().unwrap().shutdown();
+                 });
 
-        receiver.join(sender).map(|_| ())
+        // the connection is over when either side is done, or fails.
+        receiver.select(sender).map(|_| ()).map_err(|(e, _)| e)
     }))
 }
 
@@ -138,6 +158,7 @@ pub struct Commands {
     handler_queue: Arc<MsQueue<(Id, Arc<InvokationResponseHandler>)>>,
     task: Arc<RwLock<Option<Task>>>,
     shutdown: Arc<RwLock<bool>>,
+    closing: Arc<RwLock<bool>>,
 }
 
 impl Commands {
@@ -148,6 +169,7 @@ impl Commands {
             handler_queue: Arc::new(MsQueue::new()),
             task: Arc::new(RwLock::new(None)),
             shutdown: Arc::new(RwLock::new(false)),
+            closing: Arc::new(RwLock::new(false)),
         }
     }
 
@@ -204,6 +226,22 @@ impl Commands {
     /// Stop streaming commands.
     pub fn shutdown(&self) {
         *self.shutdown.write() = true;
+        if let Some(ref task) = *self.task.read() {
+            task.notify()
+        }
+    }
+
+    /// Stop streaming commands once the queued ones are sent.
+    pub fn close(&self) {
+        *self.closing.write() = true;
+        if let Some(ref task) = *self.task.read() {
+            task.notify()
+        }
+    }
+
+    /// Has the command stream been shut down?
+    pub fn is_shutdown(&self) -> bool {
+        *self.shutdown.read()
     }
 }
 
@@ -221,7 +259,7 @@ impl Stream for Commands {
                 Ok(Async::Ready(Some(bucket)))
             }
         } else {
-            if shutdown {
+            if shutdown || *self.closing.read() {
                 Ok(Async::Ready(None))
             } else {
                 *self.task.write() = Some(task::current());
@@ -230,3 +268,20 @@ impl Stream for Commands {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_commands_send_the_queued_buckets() {
+        let commands = Commands::new();
+        commands.error_response(1001, LEVIN_ERROR_FORMAT);
+        commands.close();
+
+        let buckets: Vec<Bucket> = commands.wait().collect::<io::Result<_>>().unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].head.command, 1001);
+        assert_eq!(buckets[0].head.return_code, LEVIN_ERROR_FORMAT);
+    }
+}
@@ -17,9 +17,12 @@ use tokio_core::net::TcpListener;
 use tokio_core::reactor::Handle;
 use tokio_io::{AsyncRead, AsyncWrite};
 
+use bucket::LEVIN_ERROR_FORMAT;
+use error::Error;
 use net::io::IoHandlerRef;
+use net::socket::SocketOptions;
 use net::handlers::RemoteHandler;
-use net::bucket_stream::bucket_stream;
+use net::bucket_stream::bucket_stream_with_limit;
 use net::bucket_sink::bucket_sink;
 use net::tcp_client::Commands;
 
@@ -27,6 +30,7 @@ use net::tcp_client::Commands;
 #[allow(missing_debug_implementations)]
 pub struct TcpServer {
     listener: TcpListener,
+    options: SocketOptions,
     io_handler: IoHandlerRef,
     connection_handler: ConnectionHandlerRef,
 }
@@ -35,32 +39,44 @@ impl TcpServer {
     /// Creates a new server.
     pub fn bind(addr: &SocketAddr,
                 handle: &Handle,
+                options: SocketOptions,
                 io_handler: IoHandlerRef,
                 connection_handler: ConnectionHandlerRef)
                 -> io::Result<TcpServer> {
         Ok(TcpServer {
-               listener: TcpListener::bind(addr, handle)?,
+               listener: options.bind(addr, handle)?,
+               options,
                io_handler,
                connection_handler,
            })
     }
 
+    /// The address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
     /// Creates a future that will run the server.
     pub fn run(self) -> Box<Future<Item = (), Error = io::Error> + Send + Sync + 'static> {
         let io_handler = self.io_handler;
         let connection_handler = self.connection_handler;
+        let options = self.options;
         Box::new(self.listener
             .incoming()
             .for_each(move |(stream, addr)| {
                 let io_handler = io_handler.clone();
                 let connection_handler = connection_handler.clone();
 
+                if let Err(e) = options.apply(&stream) {
+                    warn!("couldn't set socket options for {}: {}", addr, e);
+                }
+
                 let commands = Commands::new();
                 connection_handler.on_connect(addr.clone(), commands.clone());
 
                 let (read_half, write_half) = stream.split();
 
-                let buckets = bucket_stream(read_half);
+                let buckets = bucket_stream_with_limit(read_half, io_handler.max_packet_size());
 
                 let commands_ = commands.clone();
                 let receiver = buckets.for_each(move |bucket| {
@@ -69,6 +85,16 @@ impl TcpServer {
 
                     let bucket = match bucket {
                         Ok(b) => b,
+                        Err(Error::PacketTooBig { command, size, expect_response }) => {
+                            warn!("received too big bucket #{} ({} bytes), closing the connection",
+                                  command,
+                                  size);
+                            if expect_response {
+                                commands.error_response(command, LEVIN_ERROR_FORMAT);
+                            }
+                            commands.close();
+                            return future::ok::<(), io::Error>(());
+                        }
                         Err(e) => {
                             warn!("received invalid bucket: {}", e);
                             return future::ok::<(), io::Error>(());
@@ -129,9 +155,22 @@ impl TcpServer {
                 let bucket_sender = bucket_sink(write_half);
 
                 let sender = commands.forward(bucket_sender)
-                    .map(|(_, sender)| sender.inner().unwrap().shutdown());
-
-                receiver.join(sender).map(|_| ())
+                    .map(|(_, sender)| {
+                             let _ = sender.inner().unwrap().shutdown();
+                         });
+
+                // the connection is over when either side is done, or fails,
+                // an IO error on one connection doesn't stop the server.
+                let connection_handler_ = connection_handler.clone();
+                receiver
+                    .select(sender)
+                    .then(move |result| {
+                        if let Err((e, _)) = result {
+                            warn!("connection with {} failed: {}", addr, e);
+                        }
+                        connection_handler_.on_disconnect(addr);
+                        Ok(())
+                    })
             }))
     }
 }
@@ -140,6 +179,9 @@ impl TcpServer {
 pub trait ConnectionHandler: Send + Sync + 'static {
     /// A new connection is made
     fn on_connect(&self, addr: SocketAddr, commands: Commands);
+
+    /// The connection is closed, by either side or by an IO error.
+    fn on_disconnect(&self, addr: SocketAddr);
 }
 
 /// A reference to a `ConnectionHandler`.
@@ -22,7 +22,12 @@ extern crate xmr_chain as chain;
 extern crate xmr_primitives as primitives;
 extern crate xmr_pow_verification as pow_verification;
 
-use chain::transaction::Transaction;
+use std::error::Error;
+use std::fmt;
+use std::slice;
+use std::str::FromStr;
+
+use chain::transaction::{Transaction, TxOut};
 use chain::{Block, BlockHeader};
 use uuid::Uuid;
 use primitives::H256;
@@ -52,6 +57,17 @@ pub const TESTNET_GENESIS_TX: &'static [u8] =
       0x9d, 0x0b, 0xc8, 0x90, 0xd1];
 pub const TESTNET_GENESIS_NONCE: u32 = 10001;
 
+/// Base of the levin command ids of the P2P commands (handshake, timed
+/// sync, ping...), `P2P_COMMANDS_POOL_BASE` in Monero.
+pub const P2P_COMMANDS_POOL_BASE: u32 = 1000;
+
+/// Base of the levin command ids of the CryptoNote protocol commands (new
+/// block, request chain...), `BC_COMMANDS_POOL_BASE` in Monero.
+pub const BC_COMMANDS_POOL_BASE: u32 = 2000;
+
+/// Version of the levin protocol carried in every bucket head.
+pub const LEVIN_PROTOCOL_VERSION: u32 = 1;
+
 /// The Monero network we are in
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Network {
@@ -60,17 +76,84 @@ pub enum Network {
     Mainnet,
     /// The main Monero testnet, the place where you can dream you are rich.
     Testnet,
+    /// A private network, e.g. for regression testing.
+    Custom(&'static NetworkParams),
 }
 
 impl Network {
+    /// Creates a custom network with the given parameters, fails if the
+    /// hard fork table is empty or unsorted.
+    ///
+    /// The parameters are leaked so they live for the rest of the program
+    /// and `Network` can still be copied around. Every call leaks a new
+    /// copy, create a custom network once at startup and copy it instead of
+    /// calling this again.
+    pub fn custom(params: NetworkParams) -> Result<Network, InvalidNetworkParams> {
+        if params.hard_forks.is_empty() {
+            return Err(InvalidNetworkParams::NoHardForks);
+        }
+
+        let sorted = params.hard_forks
+            .windows(2)
+            .all(|pair| pair[0].height < pair[1].height && pair[0].version < pair[1].version);
+        if !sorted {
+            return Err(InvalidNetworkParams::UnsortedHardForks);
+        }
+
+        Ok(Network::Custom(Box::leak(Box::new(params))))
+    }
+
+    /// Is this the main network?
+    pub fn is_mainnet(&self) -> bool {
+        *self == Network::Mainnet
+    }
+
+    /// Is this the main testnet?
+    pub fn is_testnet(&self) -> bool {
+        *self == Network::Testnet
+    }
+
+    /// Is this the stagenet? There's no stagenet support yet, so this is
+    /// always false.
+    pub fn is_stagenet(&self) -> bool {
+        false
+    }
+
+    /// Do coins on this network have real world value? Only true on
+    /// mainnet.
+    pub fn allows_real_value(&self) -> bool {
+        self.is_mainnet()
+    }
+
     /// Returns the network's ID.
     pub fn id(&self) -> Uuid {
-        let id = match *self {
+        Uuid::from_bytes(&self.id_bytes()).expect("invalid network id")
+    }
+
+    /// The network with the given ID, `None` for unknown IDs. Custom
+    /// networks aren't known.
+    pub fn from_id(id: &Uuid) -> Option<Network> {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(id.as_bytes());
+        Network::from_id_bytes(&bytes)
+    }
+
+    /// Same as `from_id`, with the ID as raw bytes.
+    pub fn from_id_bytes(id: &[u8; 16]) -> Option<Network> {
+        match *id {
+            MAINNET_NETWORK_ID => Some(Network::Mainnet),
+            TESTNET_NETWORK_ID => Some(Network::Testnet),
+            _ => None,
+        }
+    }
+
+    /// Returns the network's ID as raw bytes.
+    pub fn id_bytes(&self) -> [u8; 16] {
+        match *self {
             Network::Mainnet => MAINNET_NETWORK_ID,
             Network::Testnet => TESTNET_NETWORK_ID,
-        };
-
-        Uuid::from_bytes(&id).expect("invalid network id")
+            Network::Custom(params) => params.id,
+        }
     }
 
     /// Returns this peer's listening port.
@@ -78,6 +161,118 @@ impl Network {
         match *self {
             Network::Mainnet => 18080,
             Network::Testnet => 28080,
+            Network::Custom(params) => params.listen_port,
+        }
+    }
+
+    /// Returns the default port of the RPC server, the one after the
+    /// listening port.
+    pub fn rpc_port(&self) -> u32 {
+        match *self {
+            Network::Mainnet => 18081,
+            Network::Testnet => 28081,
+            Network::Custom(params) => params.listen_port + 1,
+        }
+    }
+
+    /// The DNS names of the seed nodes, they're resolved on the network's
+    /// listening port.
+    pub fn seed_hostnames(&self) -> &'static [&'static str] {
+        match *self {
+            Network::Mainnet => {
+                &["seeds.moneroseeds.se",
+                  "seeds.moneroseeds.ae.org",
+                  "seeds.moneroseeds.ch",
+                  "seeds.moneroseeds.li"]
+            }
+            Network::Testnet | Network::Custom(_) => &[],
+        }
+    }
+
+    /// The hard coded seed nodes as `ip:port`, used to bootstrap when none
+    /// of the `seed_hostnames` resolves. Custom networks have none.
+    pub fn seed_nodes(&self) -> &'static [&'static str] {
+        match *self {
+            Network::Mainnet => {
+                &["107.152.130.98:18080",
+                  "212.83.175.67:18080",
+                  "5.9.100.248:18080",
+                  "163.172.182.165:18080",
+                  "161.67.132.39:18080",
+                  "198.74.231.92:18080",
+                  "195.154.123.123:18080",
+                  "212.83.172.165:18080"]
+            }
+            Network::Testnet => {
+                &["212.83.175.67:28080",
+                  "5.9.100.248:28080",
+                  "163.172.182.165:28080",
+                  "195.154.123.123:28080",
+                  "212.83.172.165:28080"]
+            }
+            Network::Custom(_) => &[],
+        }
+    }
+
+    /// Number of most recent blocks left out of the difficulty window.
+    ///
+    /// Testnet and custom networks use the mainnet value.
+    pub fn difficulty_lag(&self) -> u64 {
+        match *self {
+            Network::Mainnet | Network::Testnet | Network::Custom(_) => 15,
+        }
+    }
+
+    /// Number of blocks until a coinbase output can be spent.
+    ///
+    /// Testnet and custom networks use the mainnet value.
+    pub fn coinbase_unlock_window(&self) -> u64 {
+        match *self {
+            Network::Mainnet | Network::Testnet | Network::Custom(_) => 60,
+        }
+    }
+
+    /// Base of the P2P command ids, the same on every network.
+    pub fn p2p_command_base(&self) -> u32 {
+        P2P_COMMANDS_POOL_BASE
+    }
+
+    /// Base of the CryptoNote protocol command ids, the same on every
+    /// network.
+    pub fn cn_command_base(&self) -> u32 {
+        BC_COMMANDS_POOL_BASE
+    }
+
+    /// Version of the levin protocol, the same on every network.
+    pub fn levin_protocol_version(&self) -> u32 {
+        LEVIN_PROTOCOL_VERSION
+    }
+
+    /// Base58 tag of the standard public addresses.
+    ///
+    /// Custom networks use the testnet prefixes, as their coins have no
+    /// real value.
+    pub fn address_prefix(&self) -> u64 {
+        match *self {
+            Network::Mainnet => 18,
+            Network::Testnet | Network::Custom(_) => 53,
+        }
+    }
+
+    /// Base58 tag of the integrated addresses, those with an embedded
+    /// payment ID.
+    pub fn integrated_address_prefix(&self) -> u64 {
+        match *self {
+            Network::Mainnet => 19,
+            Network::Testnet | Network::Custom(_) => 54,
+        }
+    }
+
+    /// Base58 tag of the subaddresses.
+    pub fn subaddress_prefix(&self) -> u64 {
+        match *self {
+            Network::Mainnet => 42,
+            Network::Testnet | Network::Custom(_) => 63,
         }
     }
 
@@ -165,6 +360,7 @@ impl Network {
                       time: 1512211236,
                   }]
             }
+            Network::Custom(params) => params.hard_forks,
         };
 
         HardForks::from(parameters)
@@ -174,18 +370,40 @@ impl Network {
         let tx = match *self {
             Network::Mainnet => Transaction::from_bytes(MAINNET_GENESIS_TX),
             Network::Testnet => Transaction::from_bytes(TESTNET_GENESIS_TX),
+            Network::Custom(params) => Transaction::from_bytes(params.genesis_tx),
         };
 
         tx.expect("couldn't parse transaction from hard coded blob")
     }
 
+    /// The amount of the genesis coinbase's single output, in atomic units.
+    pub fn genesis_miner_reward(&self) -> u64 {
+        self.genesis_miner_output().amount
+    }
+
+    /// The one-time public key of the genesis coinbase's single output.
+    pub fn genesis_miner_output_key(&self) -> H256 {
+        self.genesis_miner_output()
+            .stealth_public_key()
+            .expect("the genesis output is a to-key output")
+    }
+
+    fn genesis_miner_output(&self) -> TxOut {
+        let mut vout = self.genesis_transaction().prefix.vout;
+        assert_eq!(vout.len(), 1, "the genesis transaction has a single output");
+        vout.remove(0)
+    }
+
     pub fn genesis_nonce(&self) -> u32 {
         match *self {
             Network::Mainnet => MAINNET_GENESIS_NONCE,
             Network::Testnet => TESTNET_GENESIS_NONCE,
+            Network::Custom(params) => params.genesis_nonce,
         }
     }
 
+    /// The first block of the chain: version 1.0, timestamp 0, no parent
+    /// and the genesis transaction as coinbase.
     pub fn genesis_block(&self) -> Block {
         let bl = Block {
             header: BlockHeader {
@@ -209,6 +427,83 @@ impl Network {
     }
 }
 
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Custom(_) => "custom",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Network {
+    type Err = ParseNetworkError;
+
+    /// Parses "mainnet" or "testnet", in any case. Custom networks can't be
+    /// parsed.
+    fn from_str(s: &str) -> Result<Network, ParseNetworkError> {
+        match s.to_lowercase().as_str() {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            _ => Err(ParseNetworkError(s.to_owned())),
+        }
+    }
+}
+
+/// An unknown network name, the error of `Network::from_str`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseNetworkError(pub String);
+
+impl fmt::Display for ParseNetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "unknown network \"{}\", expected mainnet or testnet",
+               self.0)
+    }
+}
+
+impl Error for ParseNetworkError {}
+
+/// Why `Network::custom` rejected its parameters.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InvalidNetworkParams {
+    /// The hard fork table is empty.
+    NoHardForks,
+    /// The hard forks aren't sorted by height and version.
+    UnsortedHardForks,
+}
+
+impl fmt::Display for InvalidNetworkParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidNetworkParams::NoHardForks => write!(f, "the network has no hard forks"),
+            InvalidNetworkParams::UnsortedHardForks => {
+                write!(f, "the hard forks aren't sorted by height and version")
+            }
+        }
+    }
+}
+
+impl Error for InvalidNetworkParams {}
+
+/// The parameters of a custom network.
+#[derive(Debug, Eq, PartialEq)]
+pub struct NetworkParams {
+    /// The network ID.
+    pub id: [u8; 16],
+    /// The default listening port.
+    pub listen_port: u32,
+    /// The genesis transaction blob.
+    pub genesis_tx: &'static [u8],
+    /// The genesis block nonce.
+    pub genesis_nonce: u32,
+    /// The hard forks, at least one, sorted by height and version.
+    pub hard_forks: &'static [HardForkParameters],
+}
+
 #[derive(Debug)]
 pub struct HardForks {
     pub parameters: &'static [HardForkParameters],
@@ -220,14 +515,64 @@ impl HardForks {
         self.parameters[back].version
     }
 
+    /// The version of the last hard fork at or below `height`, heights
+    /// before the first fork get the first version.
+    ///
+    /// Forks activate by height alone, their `time` isn't looked at: on
+    /// testnet v5's time is months after v4's while its height is only
+    /// 1441 blocks later.
     pub fn ideal_version_for_height(&self, height: u64) -> u8 {
-        for hard_fork in self.parameters {
-            if height >= hard_fork.height {
-                return hard_fork.version;
-            }
+        self.parameters
+            .iter()
+            .rev()
+            .find(|hard_fork| height >= hard_fork.height)
+            .unwrap_or(&self.parameters[0])
+            .version
+    }
+
+    /// Is `version` active at `height`?
+    ///
+    /// The fork must be scheduled at or below `height`, and at least
+    /// `threshold` percent of `window_versions`, the versions signaled by
+    /// the most recent blocks, must be `version` or later. Unknown
+    /// versions are never active.
+    pub fn is_active(&self, version: u8, height: u64, window_versions: &[u8]) -> bool {
+        let hard_fork = match self.parameters.iter().find(|hf| hf.version == version) {
+            Some(hard_fork) => hard_fork,
+            None => return false,
+        };
+
+        if height < hard_fork.height {
+            return false;
+        }
+
+        if hard_fork.threshold == 0 {
+            return true;
         }
 
-        unreachable!();
+        let votes = window_versions.iter().filter(|v| **v >= version).count();
+        let required = (window_versions.len() * hard_fork.threshold as usize + 99) / 100;
+        !window_versions.is_empty() && votes >= required
+    }
+
+    /// Iterates over the hard forks, sorted by height.
+    pub fn iter(&self) -> slice::Iter<'static, HardForkParameters> {
+        self.parameters.iter()
+    }
+
+    /// The `(version, height)` pairs of the hard forks, sorted by height.
+    pub fn version_heights(&self) -> Vec<(u8, u64)> {
+        self.iter()
+            .map(|hard_fork| (hard_fork.version, hard_fork.height))
+            .collect()
+    }
+
+    /// The first hard fork scheduled after `height`, `None` if there are
+    /// no more hard forks.
+    pub fn next_fork_after(&self, height: u64) -> Option<&'static HardForkParameters> {
+        self.parameters
+            .iter()
+            .find(|hard_fork| hard_fork.height > height)
     }
 }
 
@@ -238,16 +583,17 @@ impl From<&'static [HardForkParameters]> for HardForks {
 }
 
 /// The information about a hard fork.
-#[derive(Debug)]
+#[derive(Debug, Eq, PartialEq)]
 pub struct HardForkParameters {
     /// The version.
     pub version: u8,
     /// The block height.
     pub height: u64,
-    // XXX: what does this mean on monero.
-    /// Threshold
+    /// Percentage of the blocks in the voting window that must signal this
+    /// version, or a later one, before the fork activates. 0 activates
+    /// the fork on its height alone.
     pub threshold: u8,
-    /// Time since epoch.
+    /// Time since epoch, informative only, forks activate by height.
     pub time: u64,
 }
 
@@ -265,6 +611,11 @@ pub mod tests {
                                                  0x57, 0x27, 0xa5, 0x68, 0x94, 0xba, 0x0f, 0xb2,
                                                  0x46, 0xad, 0xaa, 0xbb, 0x1f, 0x46, 0x32, 0xe3]);
 
+    const TESTNET_GENESIS_BLOCK_ID: H256 = H256([0x48, 0xca, 0x7c, 0xd3, 0xc8, 0xde, 0x5b, 0x6a,
+                                                 0x4d, 0x53, 0xd2, 0x86, 0x1f, 0xbd, 0xae, 0xdc,
+                                                 0xa1, 0x41, 0x55, 0x35, 0x59, 0xf9, 0xbe, 0x95,
+                                                 0x20, 0x06, 0x80, 0x53, 0xcd, 0xa8, 0x43, 0x0b]);
+
     const MAINNET_GENESIS_BLOCK_BLOB: &'static [u8] =
         &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
           0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -282,20 +633,311 @@ pub mod tests {
         Network::Testnet.id();
     }
 
+    #[test]
+    fn id_bytes_match_id() {
+        for network in [Network::Mainnet, Network::Testnet].iter() {
+            assert_eq!(network.id().as_bytes(), &network.id_bytes());
+        }
+
+        assert_eq!(Network::Mainnet.id_bytes(), MAINNET_NETWORK_ID);
+        assert_eq!(Network::Testnet.id_bytes(), TESTNET_NETWORK_ID);
+    }
+
+    #[test]
+    fn network_kind() {
+        static HARD_FORKS: &'static [HardForkParameters] = &[HardForkParameters {
+                                                                 version: 1,
+                                                                 height: 1,
+                                                                 threshold: 0,
+                                                                 time: 0,
+                                                             }];
+        let custom = Network::custom(NetworkParams {
+                                         id: [0x42; 16],
+                                         listen_port: 38080,
+                                         genesis_tx: MAINNET_GENESIS_TX,
+                                         genesis_nonce: MAINNET_GENESIS_NONCE,
+                                         hard_forks: HARD_FORKS,
+                                     })
+                .unwrap();
+
+        assert!(Network::Mainnet.is_mainnet());
+        assert!(!Network::Mainnet.is_testnet());
+        assert!(!Network::Mainnet.is_stagenet());
+        assert!(Network::Mainnet.allows_real_value());
+
+        assert!(!Network::Testnet.is_mainnet());
+        assert!(Network::Testnet.is_testnet());
+        assert!(!Network::Testnet.is_stagenet());
+        assert!(!Network::Testnet.allows_real_value());
+
+        assert!(!custom.is_mainnet());
+        assert!(!custom.is_testnet());
+        assert!(!custom.is_stagenet());
+        assert!(!custom.allows_real_value());
+    }
+
+    #[test]
+    fn seed_hostnames() {
+        assert!(!Network::Mainnet.seed_hostnames().is_empty());
+        assert!(Network::Testnet.seed_hostnames().is_empty());
+    }
+
+    #[test]
+    fn network_from_id() {
+        for network in &[Network::Mainnet, Network::Testnet] {
+            assert_eq!(Network::from_id(&network.id()), Some(*network));
+            assert_eq!(Network::from_id_bytes(&network.id_bytes()), Some(*network));
+        }
+
+        assert_eq!(Network::from_id_bytes(&[0x42; 16]), None);
+        assert_eq!(Network::from_id(&Uuid::from_bytes(&[0x42; 16]).unwrap()), None);
+    }
+
+    #[test]
+    fn rpc_port() {
+        assert_eq!(Network::Mainnet.rpc_port(), 18081);
+        assert_eq!(Network::Testnet.rpc_port(), 28081);
+    }
+
+    #[test]
+    fn seed_nodes() {
+        use std::net::SocketAddr;
+
+        for network in &[Network::Mainnet, Network::Testnet] {
+            assert!(!network.seed_nodes().is_empty());
+            assert!(network
+                        .seed_nodes()
+                        .iter()
+                        .all(|node| node.parse::<SocketAddr>().is_ok()));
+        }
+        assert!(Network::Mainnet
+                    .seed_nodes()
+                    .iter()
+                    .all(|node| node.ends_with(":18080")));
+        assert!(Network::Testnet
+                    .seed_nodes()
+                    .iter()
+                    .all(|node| node.ends_with(":28080")));
+    }
+
+    #[test]
+    fn ideal_version_for_height() {
+        let hard_forks = Network::Mainnet.hard_forks();
+        assert_eq!(hard_forks.ideal_version_for_height(0), 1);
+        assert_eq!(hard_forks.ideal_version_for_height(1009826), 1);
+        assert_eq!(hard_forks.ideal_version_for_height(1009827), 2);
+        assert_eq!(hard_forks.ideal_version_for_height(1500000), 6);
+    }
+
+    #[test]
+    fn testnet_version_for_height() {
+        let hard_forks = Network::Testnet.hard_forks();
+        assert_eq!(hard_forks.ideal_version_for_height(800499), 2);
+        assert_eq!(hard_forks.ideal_version_for_height(801218), 3);
+        assert_eq!(hard_forks.ideal_version_for_height(801219), 4);
+        assert_eq!(hard_forks.ideal_version_for_height(802659), 4);
+        assert_eq!(hard_forks.ideal_version_for_height(802660), 5);
+        assert_eq!(hard_forks.ideal_version_for_height(971400), 6);
+
+        // the heights are sorted even though the times aren't spaced alike.
+        let heights = hard_forks.version_heights();
+        assert!(heights.windows(2).all(|pair| pair[0].1 < pair[1].1));
+    }
+
+    #[test]
+    fn voted_hard_fork() {
+        static HARD_FORKS: &'static [HardForkParameters] = &[HardForkParameters {
+                                                                 version: 1,
+                                                                 height: 1,
+                                                                 threshold: 0,
+                                                                 time: 0,
+                                                             },
+                                                             HardForkParameters {
+                                                                 version: 2,
+                                                                 height: 100,
+                                                                 threshold: 80,
+                                                                 time: 0,
+                                                             }];
+        let hard_forks = HardForks::from(HARD_FORKS);
+
+        let mut window = [1u8; 10];
+        assert!(hard_forks.is_active(1, 1, &[]));
+        assert!(!hard_forks.is_active(2, 100, &window));
+
+        for vote in window.iter_mut().take(7) {
+            *vote = 2;
+        }
+        assert!(!hard_forks.is_active(2, 100, &window));
+
+        window[7] = 3;
+        assert!(hard_forks.is_active(2, 100, &window));
+        assert!(!hard_forks.is_active(2, 99, &window));
+        assert!(!hard_forks.is_active(3, 100, &window));
+    }
+
+    #[test]
+    fn consensus_windows() {
+        assert_eq!(Network::Mainnet.difficulty_lag(), 15);
+        assert_eq!(Network::Mainnet.coinbase_unlock_window(), 60);
+        assert_eq!(Network::Testnet.difficulty_lag(), 15);
+        assert_eq!(Network::Testnet.coinbase_unlock_window(), 60);
+    }
+
+    #[test]
+    fn protocol_constants() {
+        assert_eq!(P2P_COMMANDS_POOL_BASE, 1000);
+        assert_eq!(BC_COMMANDS_POOL_BASE, 2000);
+        for network in [Network::Mainnet, Network::Testnet].iter() {
+            assert_eq!(network.p2p_command_base(), P2P_COMMANDS_POOL_BASE);
+            assert_eq!(network.cn_command_base(), BC_COMMANDS_POOL_BASE);
+            assert_eq!(network.levin_protocol_version(), 1);
+        }
+    }
+
+    #[test]
+    fn address_prefixes() {
+        assert_eq!(Network::Mainnet.address_prefix(), 18);
+        assert_eq!(Network::Mainnet.integrated_address_prefix(), 19);
+        assert_eq!(Network::Mainnet.subaddress_prefix(), 42);
+        assert_eq!(Network::Testnet.address_prefix(), 53);
+        assert_eq!(Network::Testnet.integrated_address_prefix(), 54);
+        assert_eq!(Network::Testnet.subaddress_prefix(), 63);
+    }
+
     #[test]
     fn is_valid_genesis_transaction() {
         Network::Mainnet.genesis_transaction();
         Network::Testnet.genesis_transaction();
     }
 
+    #[test]
+    fn genesis_miner_output() {
+        assert_eq!(Network::Mainnet.genesis_miner_reward(), 17592186044415);
+        assert_eq!(Network::Mainnet.genesis_miner_output_key(),
+                   H256::from_bytes(&[0x9b, 0x2e, 0x4c, 0x02, 0x81, 0xc0, 0xb0, 0x2e, 0x7c, 0x53,
+                                      0x29, 0x1a, 0x94, 0xd1, 0xd0, 0xcb, 0xff, 0x88, 0x83, 0xf8,
+                                      0x02, 0x4f, 0x51, 0x42, 0xee, 0x49, 0x4f, 0xfb, 0xbd, 0x08,
+                                      0x80, 0x71]));
+
+        assert_eq!(Network::Testnet.genesis_miner_reward(), 17592186044415);
+    }
+
     #[test]
     fn is_valid_genesis_block() {
         let mainnet_block = Network::Mainnet.genesis_block();
         let mainnet_block_blob = to_binary(&mainnet_block);
         assert_eq!(mainnet_block_blob, MAINNET_GENESIS_BLOCK_BLOB);
+        assert_eq!(mainnet_block.serialize_blob(), MAINNET_GENESIS_BLOCK_BLOB);
+        assert_eq!(mainnet_block.blob_size(), MAINNET_GENESIS_BLOCK_BLOB.len());
         assert_eq!(mainnet_block.id(), MAINNET_GENESIS_BLOCK_ID);
 
-        let _tesnet_block = Network::Testnet.genesis_block();
-        // TODO: check for testnet genesis block id validity.
+        let testnet_block = Network::Testnet.genesis_block();
+        assert_eq!(testnet_block.header.nonce, TESTNET_GENESIS_NONCE);
+        assert_eq!(testnet_block.miner_tx, mainnet_block.miner_tx);
+        assert_eq!(testnet_block.id(), TESTNET_GENESIS_BLOCK_ID);
+    }
+
+    #[test]
+    fn custom_network() {
+        const ID: [u8; 16] = [0x42; 16];
+        static HARD_FORKS: &'static [HardForkParameters] = &[HardForkParameters {
+                                                                 version: 9,
+                                                                 height: 1,
+                                                                 threshold: 0,
+                                                                 time: 0,
+                                                             }];
+
+        let network = Network::custom(NetworkParams {
+                                          id: ID,
+                                          listen_port: 38080,
+                                          genesis_tx: MAINNET_GENESIS_TX,
+                                          genesis_nonce: 42,
+                                          hard_forks: HARD_FORKS,
+                                      })
+                .unwrap();
+
+        assert_eq!(network.id(), Uuid::from_bytes(&ID).unwrap());
+        assert_eq!(network.id_bytes(), ID);
+        assert_eq!(network.listen_port(), 38080);
+        assert_eq!(network.rpc_port(), 38081);
+        assert_eq!(network.hard_forks().ideal_version(), 9);
+        assert_eq!(network.genesis_nonce(), 42);
+        assert_eq!(network.genesis_block().header.nonce, 42);
+        assert_eq!(network, network.clone());
+        assert!(network != Network::Mainnet);
+    }
+
+    #[test]
+    fn invalid_custom_networks() {
+        static UNSORTED: &'static [HardForkParameters] = &[HardForkParameters {
+                                                               version: 2,
+                                                               height: 10,
+                                                               threshold: 0,
+                                                               time: 0,
+                                                           },
+                                                           HardForkParameters {
+                                                               version: 1,
+                                                               height: 1,
+                                                               threshold: 0,
+                                                               time: 0,
+                                                           }];
+        let params = |hard_forks| {
+            NetworkParams {
+                id: [0x42; 16],
+                listen_port: 38080,
+                genesis_tx: MAINNET_GENESIS_TX,
+                genesis_nonce: 42,
+                hard_forks,
+            }
+        };
+
+        assert_eq!(Network::custom(params(&[])), Err(InvalidNetworkParams::NoHardForks));
+        assert_eq!(Network::custom(params(UNSORTED)),
+                   Err(InvalidNetworkParams::UnsortedHardForks));
+    }
+
+    #[test]
+    fn next_fork_after() {
+        let hard_forks = Network::Mainnet.hard_forks();
+
+        assert_eq!(hard_forks.next_fork_after(0).unwrap().version, 1);
+        assert_eq!(hard_forks.next_fork_after(1009826).unwrap().version, 2);
+        assert_eq!(hard_forks.next_fork_after(1009827).unwrap().version, 3);
+        assert_eq!(hard_forks.next_fork_after(1399999).unwrap().version, 6);
+        assert!(hard_forks.next_fork_after(1400000).is_none());
+        assert!(hard_forks.next_fork_after(u64::max_value()).is_none());
+    }
+
+    #[test]
+    fn mainnet_version_heights() {
+        let hard_forks = Network::Mainnet.hard_forks();
+        let version_heights = hard_forks.version_heights();
+
+        assert_eq!(version_heights,
+                   vec![(1, 1),
+                        (2, 1009827),
+                        (3, 1141317),
+                        (4, 1220516),
+                        (5, 1288616),
+                        (6, 1400000)]);
+        assert_eq!(hard_forks.iter().count(), 6);
+        assert!(hard_forks
+                    .iter()
+                    .zip(hard_forks.iter().skip(1))
+                    .all(|(a, b)| a.height < b.height && a.version < b.version));
+    }
+
+    #[test]
+    fn network_names() {
+        assert_eq!("mainnet".parse(), Ok(Network::Mainnet));
+        assert_eq!("TestNet".parse(), Ok(Network::Testnet));
+        assert_eq!("stagenet".parse::<Network>(),
+                   Err(ParseNetworkError("stagenet".to_owned())));
+        assert_eq!("custom".parse::<Network>(),
+                   Err(ParseNetworkError("custom".to_owned())));
+
+        for network in &[Network::Mainnet, Network::Testnet] {
+            assert_eq!(network.to_string().parse(), Ok(*network));
+        }
     }
 }
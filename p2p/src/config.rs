@@ -15,10 +15,42 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::net::SocketAddr;
+use std::time::Duration;
+
 use network::Network;
 
 use types::PeerId;
 
+pub use levin::bucket::LEVIN_DEFAULT_MAX_PACKET_SIZE as DEFAULT_MAX_PACKET_SIZE;
+pub use levin::net::SocketOptions;
+pub use utils::HANDSHAKE_RATE_WINDOW;
+
+/// Default invoke timeout in seconds.
+pub const DEFAULT_INVOKE_TIMEOUT: u64 = 2 * 60;
+
+/// Maximum random delay in milliseconds before dialing a peer.
+pub const MAX_DIAL_JITTER: u64 = 2000;
+
+/// Default maximum difference in seconds between a peer's clock and ours.
+pub const DEFAULT_MAX_CLOCK_SKEW: u64 = 10 * 60;
+
+/// Default maximum of inbound connections from a single IP address.
+pub const DEFAULT_MAX_CONNECTIONS_PER_IP: u32 = 1;
+
+/// Default maximum of handshakes from a single IP address per
+/// `HANDSHAKE_RATE_WINDOW`.
+pub const DEFAULT_MAX_HANDSHAKES_PER_IP: u32 = 10;
+
+/// Number of blocks we can be behind our peers and still be synced.
+pub const SYNCED_HEIGHT_TOLERANCE: u64 = 5;
+
+/// Default interval in seconds between the redials of a disconnected
+/// priority peer.
+pub const DEFAULT_PRIORITY_REDIAL_INTERVAL: u64 = 5;
+
+/// Maximum number of blocks sent in a single `ResponseGetObjects`.
+pub const MAX_OBJECT_REQUEST_COUNT: usize = 500;
+
 /// P2P configuration.
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -38,4 +70,32 @@ pub struct Config {
     pub in_peers: u32,
     /// The peer ID.
     pub peer_id: PeerId,
+    /// Time to wait for the response of an invoked command before
+    /// dropping the connection.
+    pub invoke_timeout: Duration,
+    /// Maximum size in bytes of the received levin buckets.
+    pub max_packet_size: u64,
+    /// Maximum difference between the peer advertised `local_time` and
+    /// ours, peers beyond it are rejected on handshake.
+    pub max_clock_skew: Duration,
+    /// Listener backlog, `SO_REUSEADDR` and `TCP_NODELAY` settings.
+    pub socket_options: SocketOptions,
+    /// Maximum of inbound connections from a single IP address, the
+    /// excess connections are refused.
+    pub max_connections_per_ip: u32,
+    /// Maximum of handshakes from a single IP address per
+    /// `HANDSHAKE_RATE_WINDOW`, the excess handshakes are rejected.
+    pub max_handshakes_per_ip: u32,
+    /// Listen on and dial IPv4 addresses.
+    pub use_ipv4: bool,
+    /// Listen on and dial IPv6 addresses.
+    pub use_ipv6: bool,
+    /// Peers kept connected at all times, they're redialed every
+    /// `priority_redial_interval` without backing off.
+    pub priority_peers: Vec<SocketAddr>,
+    /// Time between the redials of a disconnected priority peer.
+    pub priority_redial_interval: Duration,
+    /// Do the priority peers take `out_peers` dial slots? Otherwise they're
+    /// dialed on top of them.
+    pub priority_peers_use_out_slots: bool,
 }
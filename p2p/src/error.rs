@@ -0,0 +1,105 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt;
+
+use uuid::Uuid;
+
+use network::Network;
+
+use levin::Id;
+
+/// An error passed to the response handler of an invocation.
+#[derive(Debug, Clone, Fail)]
+pub enum InvokeError {
+    /// The peer didn't respond in time.
+    #[fail(display = "command #{} timed out", _0)]
+    Timeout(Id),
+    /// The peer sent a response we couldn't decode.
+    #[fail(display = "invalid response to command #{}", _0)]
+    InvalidResponse(Id),
+    /// There's no open connection to the peer.
+    #[fail(display = "not connected")]
+    NotConnected,
+    /// The connection was closed before the response arrived.
+    #[fail(display = "connection closed during command #{}", _0)]
+    ConnectionClosed(Id),
+}
+
+/// The reason a handshake was rejected.
+#[derive(Debug, Clone, PartialEq, Fail)]
+pub enum HandshakeError {
+    /// The peer belongs to another network.
+    #[fail(display = "wrong network id {}", _0)]
+    WrongNetwork(Uuid),
+    /// The peer belongs to another known network.
+    #[fail(display = "peer is on {}", _0)]
+    OtherNetwork(Network),
+    /// We are connected to ourselves.
+    #[fail(display = "same peer id")]
+    SamePeerId,
+    /// The peer's clock differs too much from ours.
+    #[fail(display = "clock skew of {}", _0)]
+    ClockSkew(ClockSkew),
+    /// The peer's top version is older than the hard fork at its height,
+    /// the advertised and the expected version.
+    #[fail(display = "obsolete top version {}, expected {}", _0, _1)]
+    ObsoleteVersion(u8, u8),
+}
+
+/// The difference between the clock of a peer and ours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    /// The difference in seconds.
+    pub seconds: u64,
+    /// Is the peer's clock ahead of ours?
+    pub ahead: bool,
+}
+
+impl ClockSkew {
+    /// The skew of a peer reporting `peer_time` when our time is
+    /// `local_time`, both in seconds since the unix epoch.
+    pub fn between(peer_time: u64, local_time: u64) -> ClockSkew {
+        ClockSkew {
+            seconds: peer_time.max(local_time) - peer_time.min(local_time),
+            ahead: peer_time > local_time,
+        }
+    }
+}
+
+impl fmt::Display for ClockSkew {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let direction = if self.ahead { "ahead" } else { "behind" };
+        write!(fmt, "{} seconds {}", self.seconds, direction)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_skew_of_extreme_times() {
+        let max = u64::max_value();
+        assert_eq!(ClockSkew::between(max, 0), ClockSkew { seconds: max, ahead: true });
+        assert_eq!(ClockSkew::between(0, max), ClockSkew { seconds: max, ahead: false });
+        assert_eq!(ClockSkew::between(1 << 63, 0), ClockSkew { seconds: 1 << 63, ahead: true });
+        assert_eq!(ClockSkew::between(max, 1 << 63),
+                   ClockSkew { seconds: (1 << 63) - 1, ahead: true });
+        assert_eq!(ClockSkew::between(7, 7), ClockSkew { seconds: 0, ahead: false });
+        assert_eq!(ClockSkew::between(5, 65).to_string(), "60 seconds behind");
+    }
+}
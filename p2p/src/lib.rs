@@ -30,8 +30,11 @@ extern crate serde_derive;
 
 extern crate failure;
 #[macro_use]
+extern crate failure_derive;
+#[macro_use]
 extern crate log;
 
+extern crate xmr_chain as chain;
 extern crate xmr_levin as levin;
 extern crate xmr_network as network;
 extern crate xmr_portable_storage as portable_storage;
@@ -39,6 +42,9 @@ extern crate xmr_portable_storage_utils as portable_storage_utils;
 extern crate xmr_primitives as primitives;
 extern crate xmr_storage as storage;
 
+mod error;
+
+pub mod event;
 pub mod event_loop;
 pub mod types;
 pub mod p2p;
@@ -48,5 +54,7 @@ pub mod config;
 pub mod utils;
 
 pub use p2p::P2P;
+pub use event::P2pEvent;
 pub use event_loop::{event_loop, forever};
 pub use config::Config;
+pub use error::{ClockSkew, HandshakeError, InvokeError};
@@ -89,6 +89,13 @@ impl ConnectionCounter {
         }
     }
 
+    /// Forgets all the connections and resets the counters.
+    pub fn clear(&self) {
+        self.connection_type.write().clear();
+        self.current_inbound_connections.store(0, Ordering::Release);
+        self.current_outbound_connections.store(0, Ordering::Release);
+    }
+
     /// Returns number of inbound connections needed to reach the maximum
     pub fn inbound_connections_needed(&self) -> u32 {
         let ic = self.inbound_connections();
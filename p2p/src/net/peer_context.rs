@@ -51,6 +51,18 @@ impl PeerContext {
         };
     }
 
+    /// The support flags negotiated with the peer, 0 until they're
+    /// received.
+    pub fn support_flags(&self) -> u32 {
+        self.context.support_flags(&self.addr)
+    }
+
+    /// Does the peer support the optional feature `flag`? See the
+    /// `P2P_SUPPORT_FLAG_*` constants.
+    pub fn supports(&self, flag: u32) -> bool {
+        self.support_flags() & flag == flag
+    }
+
     pub fn close(&self) {
         Context::close(self.context.clone(), &self.addr);
     }
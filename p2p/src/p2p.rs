@@ -14,34 +14,51 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::net::SocketAddr;
+use std::net::{SocketAddr, SocketAddrV4};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::collections::HashMap;
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use failure::Error;
+use failure::{Error, err_msg};
 
 use futures::Future;
-use futures_cpupool::CpuPool;
-use tokio_core::reactor::{Handle, Remote};
+use futures::future::{self, Either};
+use futures::sync::oneshot;
+use futures_cpupool::{CpuFuture, CpuPool};
+use tokio_core::reactor::{Handle, Remote, Timeout};
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
-use storage::SharedStore;
+use rand::{self, Rng};
 
+use chain::Block;
+use chain::transaction::Transaction;
+
+use storage::{BestBlock, SharedStore};
+
+use network::Network;
+
+use levin::Command;
 use levin::net::{IoHandler, IoHandlerRef, TcpServer, Commands,
                  ConnectionHandler as ConnectionHandlerTrait, ConnectionHandlerRef,
                  connect as levin_connect};
 
+use serde::de::DeserializeOwned;
+
 use portable_storage::{Section, from_section, to_section};
+use portable_storage_utils::stl::{StlLinkedList, StlVector};
 
-use config::Config;
+use config::{Config, MAX_DIAL_JITTER, MAX_OBJECT_REQUEST_COUNT, SYNCED_HEIGHT_TOLERANCE};
+use error::{ClockSkew, HandshakeError, InvokeError};
+use event::P2pEvent;
 
 use net::{ConnectionCounter, ConnectionType, PeerContext};
 use protocol::{LocalSyncNodeRef, OutboundSync, InboundSyncConnectionRef};
 
-use types::{BasicNodeData, PeerlistEntry};
-use types::cn::CoreSyncData;
+use types::{BasicNodeData, PeerId, PeerlistEntry};
+use types::cn::{BlockCompleteEntry, CoreSyncData};
 use types::cmd::{Handshake, HandshakeRequest, HandshakeResponse, Ping, PingResponse,
                  RequestSupportFlags, SupportFlagsResponse, TimedSync, TimedSyncRequest,
                  TimedSyncResponse};
@@ -49,7 +66,8 @@ use types::cn::cmd::{NewBlock, NewFluffyBlock, NewTransactions, RequestChain,
                      RequestFluffyMissingTx, RequestGetObjects, ResponseChainEntry,
                      ResponseGetObjects};
 
-use utils::Peerlist;
+use utils::{is_valid_peer_address, Backoff, IpLimiter, Peerlist, RelayedTxs,
+            MAX_PEERS_IN_HANDSHAKE, GRAY_PEERLIST_LIMIT};
 
 pub struct Context {
     remote: Remote,
@@ -59,10 +77,28 @@ pub struct Context {
     store: SharedStore,
     pub(crate) command_streams: RwLock<HashMap<SocketAddr, Commands>>,
     peerlist: RwLock<Peerlist>,
+    gray_peerlist: RwLock<Peerlist>,
     local_sync_node: LocalSyncNodeRef,
     inbound_sync_connections: RwLock<HashMap<SocketAddr, InboundSyncConnectionRef>>,
+    listening: AtomicBool,
+    shut_down: AtomicBool,
+    event_sink: Mutex<Option<SyncSender<P2pEvent>>>,
+    dial_queue: Mutex<VecDeque<SocketAddr>>,
+    dials_in_flight: AtomicUsize,
+    /// Priority peers dialed on top of the dial slots.
+    priority_dials: Mutex<HashSet<SocketAddr>>,
+    backoff: Mutex<Backoff>,
+    peer_heights: RwLock<HashMap<SocketAddr, u64>>,
+    peer_support_flags: RwLock<HashMap<SocketAddr, u32>>,
+    pending_invokes: Mutex<HashMap<SocketAddr, HashMap<usize, PendingInvoke>>>,
+    next_invoke: AtomicUsize,
+    ip_limiter: Mutex<IpLimiter>,
+    relayed_txs: Mutex<RelayedTxs>,
 }
 
+/// Fails an invocation that's still waiting for its response.
+type PendingInvoke = Box<dyn Fn() + Send>;
+
 impl Context {
     pub fn new(remote: Remote,
                pool: CpuPool,
@@ -75,6 +111,8 @@ impl Context {
         let max_peers = config.in_peers + config.out_peers;
         let command_streams = RwLock::new(HashMap::with_capacity(max_peers as _));
         let inbound_sync_connections = RwLock::new(HashMap::with_capacity(max_peers as _));
+        let ip_limiter = IpLimiter::new(config.max_connections_per_ip,
+                                        config.max_handshakes_per_ip);
 
         Context {
             remote,
@@ -84,54 +122,339 @@ impl Context {
             store,
             command_streams,
             peerlist: RwLock::new(Peerlist::new()),
+            gray_peerlist: RwLock::new(Peerlist::new()),
             local_sync_node,
             inbound_sync_connections,
+            listening: AtomicBool::new(false),
+            shut_down: AtomicBool::new(false),
+            event_sink: Mutex::new(None),
+            dial_queue: Mutex::new(VecDeque::new()),
+            dials_in_flight: AtomicUsize::new(0),
+            priority_dials: Mutex::new(HashSet::new()),
+            backoff: Mutex::new(Backoff::new()),
+            peer_heights: RwLock::new(HashMap::with_capacity(max_peers as _)),
+            peer_support_flags: RwLock::new(HashMap::with_capacity(max_peers as _)),
+            pending_invokes: Mutex::new(HashMap::with_capacity(max_peers as _)),
+            next_invoke: AtomicUsize::new(0),
+            ip_limiter: Mutex::new(ip_limiter),
+            relayed_txs: Mutex::new(RelayedTxs::new()),
         }
     }
 
     pub fn close(context: Arc<Context>, addr: &SocketAddr) {
         if let Some(command_stream) = context.command_streams.write().remove(addr) {
             context.inbound_sync_connections.write().remove(addr);
+            context.peer_heights.write().remove(addr);
+            context.peer_support_flags.write().remove(addr);
             command_stream.shutdown();
+            if let Some(ConnectionType::Inbound) = context.connection_counter.connection_type(addr) {
+                context.ip_limiter.lock().note_disconnect(&addr.ip());
+            }
             context.connection_counter.note_close_connection(addr);
+            context.emit(P2pEvent::Disconnected(addr.clone()));
+        }
+
+        let pending = context.pending_invokes.lock().remove(addr);
+        for (_, fail) in pending.into_iter().flat_map(|pending| pending) {
+            fail();
+        }
+    }
+
+    /// Sets the channel receiving the connection events.
+    pub fn set_event_sink(&self, sink: SyncSender<P2pEvent>) {
+        *self.event_sink.lock() = Some(sink);
+    }
+
+    /// Records the chain height advertised by a peer.
+    fn note_peer_height(&self, addr: &SocketAddr, height: u64) {
+        self.peer_heights.write().insert(addr.clone(), height);
+    }
+
+    /// Records the support flags sent by a peer.
+    fn note_support_flags(&self, addr: &SocketAddr, support_flags: u32) {
+        self.peer_support_flags.write().insert(addr.clone(), support_flags);
+    }
+
+    /// The support flags sent by a peer, 0 until it sends them.
+    pub fn support_flags(&self, addr: &SocketAddr) -> u32 {
+        self.peer_support_flags
+            .read()
+            .get(addr)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Do we have at least one peer that completed the handshake?
+    pub fn is_connected(&self) -> bool {
+        !self.peer_heights.read().is_empty()
+    }
+
+    /// Is `local_height` within `SYNCED_HEIGHT_TOLERANCE` blocks of the
+    /// highest chain advertised by our peers? Without peers we can't tell,
+    /// so we aren't synced.
+    pub fn is_synced(&self, local_height: u64) -> bool {
+        match self.peer_heights.read().values().max() {
+            Some(&peer_height) => {
+                local_height.saturating_add(SYNCED_HEIGHT_TOLERANCE) >= peer_height
+            }
+            None => false,
+        }
+    }
+
+    /// Sends an event to the event sink, if any, without blocking. The event
+    /// is dropped if the channel is full.
+    fn emit(&self, event: P2pEvent) {
+        let mut event_sink = self.event_sink.lock();
+        let disconnected = match *event_sink {
+            Some(ref sink) => {
+                match sink.try_send(event) {
+                    Ok(()) => false,
+                    Err(TrySendError::Full(event)) => {
+                        debug!("event sink is full, dropping {:?}", event);
+                        false
+                    }
+                    Err(TrySendError::Disconnected(_)) => true,
+                }
+            }
+            None => false,
+        };
+
+        if disconnected {
+            *event_sink = None;
         }
     }
 
+    /// Closes all the open connections.
+    ///
+    /// Calling this more than once is harmless, the subsequent calls
+    /// won't find any connection to close.
+    pub fn shutdown(context: Arc<Context>) {
+        context.shut_down.store(true, Ordering::Release);
+        let mut command_streams = context.command_streams.write();
+
+        info!("shutting down {} connections.", command_streams.len());
+
+        for (addr, command_stream) in command_streams.drain() {
+            command_stream.shutdown();
+            context.connection_counter.note_close_connection(&addr);
+            context.emit(P2pEvent::Disconnected(addr));
+        }
+
+        context.inbound_sync_connections.write().clear();
+        context.peer_heights.write().clear();
+        context.peer_support_flags.write().clear();
+        context.connection_counter.clear();
+        context.ip_limiter.lock().clear();
+        context.priority_dials.lock().clear();
+    }
+
+    /// Spawns a levin server on each of the `listen_addrs`, unless
+    /// `hide_my_port` is set.
     pub fn spawn_server(context: Arc<Context>, io_handler: IoHandlerRef) {
-        let addr = context
-            .config
+        if context.config.hide_my_port {
+            return;
+        }
+
+        context.listening.store(true, Ordering::Release);
+
+        // with both families enabled the IPv6 listener leaves the IPv4
+        // port to its own listener.
+        let mut socket_options = context.config.socket_options;
+        socket_options.only_v6 = context.config.use_ipv4;
+
+        for addr in context.listen_addrs() {
+            let context = context.clone();
+            let io_handler = io_handler.clone();
+            context
+                .remote
+                .clone()
+                .spawn(move |handle| {
+                    let connection_handler = ConnectionHandler::new(context.clone());
+                    let server = match TcpServer::bind(&addr,
+                                                       handle,
+                                                       socket_options,
+                                                       io_handler,
+                                                       connection_handler) {
+                        Ok(server) => server,
+                        Err(e) => {
+                            warn!("couldn't listen on {}: {}", addr, e);
+                            return Either::A(future::ok(()));
+                        }
+                    };
+
+                    let future = server
+                        .run()
+                        .map_err(|e| {
+                                     warn!("server io error: {}", e);
+                                     ()
+                                 });
+
+                    Either::B(context.pool.clone().spawn(future))
+                })
+        }
+    }
+
+    /// The addresses the levin server listens on, the wildcard address of
+    /// each enabled address family.
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        let port = self.config
             .listen_port
-            .map(|port| format!("127.0.0.1:{}", port))
-            .unwrap_or(format!("127.0.0.1:{}", context.config.network.listen_port()))
-            .parse()
-            .unwrap();
+            .unwrap_or(self.config.network.listen_port() as u16);
+
+        let mut addrs = Vec::with_capacity(2);
+        if self.config.use_ipv4 {
+            addrs.push(SocketAddr::from(([0, 0, 0, 0], port)));
+        }
+        if self.config.use_ipv6 {
+            addrs.push(SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 0], port)));
+        }
+        addrs
+    }
+
+    /// Is the address family of `addr` enabled?
+    pub fn allows_address(&self, addr: &SocketAddr) -> bool {
+        match *addr {
+            SocketAddr::V4(_) => self.config.use_ipv4,
+            SocketAddr::V6(_) => self.config.use_ipv6,
+        }
+    }
+
+    /// Queues `peers` in random order and starts dialing them, with at
+    /// most `out_peers` dials in flight.
+    pub fn dial(context: Arc<Context>, peers: &[SocketAddr], io_handler: IoHandlerRef) {
+        let mut peers: Vec<SocketAddr> = peers
+            .iter()
+            .filter(|addr| if context.allows_address(addr) {
+                        true
+                    } else {
+                        debug!("not dialing {}, its address family is disabled", addr);
+                        false
+                    })
+            .cloned()
+            .collect();
+        rand::thread_rng().shuffle(&mut peers);
+
+        context.dial_queue.lock().extend(peers);
 
+        for _ in 0..context.config.out_peers {
+            Context::dial_next(context.clone(), io_handler.clone());
+        }
+    }
+
+    /// Dials the next queued peer after a random delay, if there's a free
+    /// dial slot. Peers that failed recently wait for their backoff too.
+    fn dial_next(context: Arc<Context>, io_handler: IoHandlerRef) {
+        let addr = {
+            let mut dial_queue = context.dial_queue.lock();
+            let in_flight = context.dials_in_flight.load(Ordering::Acquire);
+            if in_flight >= context.config.out_peers as usize {
+                return;
+            }
+
+            match dial_queue.pop_front() {
+                Some(addr) => {
+                    context.dials_in_flight.fetch_add(1, Ordering::AcqRel);
+                    addr
+                }
+                None => return,
+            }
+        };
+
+        let jitter = rand::thread_rng().gen_range(0, MAX_DIAL_JITTER + 1);
+        let mut delay = Duration::from_millis(jitter);
+        if let Some(next_dial) = context.backoff.lock().next_dial(&addr) {
+            let now = Instant::now();
+            if next_dial > now {
+                delay += next_dial - now;
+            }
+        }
         context
             .remote
             .clone()
             .spawn(move |handle| {
-                // TODO: spawn this future on the threadpool.
-                let connection_handler = ConnectionHandler::new(context.clone());
-                let future = TcpServer::bind(&addr, handle, io_handler, connection_handler)
-                    .unwrap()
-                    .run()
-                    .map_err(|e| {
-                                 warn!("server io error: {}", e);
-                                 ()
-                             });
+                Timeout::new(delay, handle)
+                    .expect("reactor to be alive")
+                    .then(move |_| {
+                        info!("connecting to {}", addr);
+                        Context::connect(context, &addr, io_handler);
+                        Ok(())
+                    })
+            })
+    }
 
-                context.pool.clone().spawn(future)
+    /// Frees a dial slot once a handshake is done and dials the next queued
+    /// peer. Priority peers dialed on top of the slots don't free one.
+    fn on_dial_done(context: Arc<Context>, addr: &SocketAddr, io_handler: IoHandlerRef) {
+        if context.priority_dials.lock().remove(addr) {
+            return;
+        }
+
+        {
+            let _dial_queue = context.dial_queue.lock();
+            if context.dials_in_flight.load(Ordering::Acquire) == 0 {
+                return;
+            }
+
+            context.dials_in_flight.fetch_sub(1, Ordering::AcqRel);
+        }
+
+        Context::dial_next(context, io_handler);
+    }
+
+    /// Dials the priority peers now and then every `priority_redial_interval`
+    /// until shutdown.
+    pub fn keep_priority_peers(context: Arc<Context>, io_handler: IoHandlerRef) {
+        if context.config.priority_peers.is_empty() || context.shut_down.load(Ordering::Acquire) {
+            return;
+        }
+
+        Context::dial_priority_peers(context.clone(), io_handler.clone());
+
+        let interval = context.config.priority_redial_interval;
+        context
+            .remote
+            .clone()
+            .spawn(move |handle| {
+                Timeout::new(interval, handle)
+                    .expect("reactor to be alive")
+                    .then(move |_| {
+                        Context::keep_priority_peers(context, io_handler);
+                        Ok(())
+                    })
             })
     }
 
+    /// Dials the priority peers that aren't connected, without waiting for
+    /// their backoff.
+    fn dial_priority_peers(context: Arc<Context>, io_handler: IoHandlerRef) {
+        for addr in context.config.priority_peers.iter() {
+            if !context.allows_address(addr) ||
+               context.command_streams.read().contains_key(addr) {
+                continue;
+            }
+
+            if context.config.priority_peers_use_out_slots {
+                let _dial_queue = context.dial_queue.lock();
+                let in_flight = context.dials_in_flight.load(Ordering::Acquire);
+                if in_flight >= context.config.out_peers as usize {
+                    continue;
+                }
+                context.dials_in_flight.fetch_add(1, Ordering::AcqRel);
+            } else if !context.priority_dials.lock().insert(addr.clone()) {
+                continue;
+            }
+
+            info!("connecting to priority peer {}", addr);
+            Context::connect(context.clone(), addr, io_handler.clone());
+        }
+    }
+
     pub fn connect(context: Arc<Context>, addr: &SocketAddr, io_handler: IoHandlerRef) {
         let addr = addr.clone();
         context
             .remote
             .clone()
             .spawn(move |handle| {
-                // TODO: on threadpool
-
                 let commands = Commands::new();
 
                 let request = to_section(&HandshakeRequest {
@@ -141,52 +464,215 @@ impl Context {
                                           })
                         .unwrap();
 
-                commands.invoke::<Handshake, _>(request, {
+                Context::invoke::<Handshake, _>(context.clone(), &addr, &commands, request, {
                     let context = context.clone();
                     let addr = addr.clone();
-                    move |response: Section| {
-                        // TODO: handle errors
-                        let response: HandshakeResponse = from_section(response).unwrap();
+                    let io_handler = io_handler.clone();
+                    move |response: Result<Section, InvokeError>| {
+                        Context::on_dial_done(context.clone(), &addr, io_handler.clone());
+                        Context::on_handshake_response(&context, &addr, response);
+                    }
+                });
 
-                        if response.node_data.peer_id == context.config.peer_id {
-                            warn!("same peer id from address {}, disconnecting", addr);
-                            Context::close(context.clone(), &addr);
-                        }
+                Context::open_outbound(context, addr, handle, io_handler, commands)
+            })
+    }
+
+    /// Handles the response to a handshake sent to `addr`. Peers that fail
+    /// the handshake are closed and backed off.
+    fn on_handshake_response(context: &Arc<Context>,
+                             addr: &SocketAddr,
+                             response: Result<Section, InvokeError>) {
+        let addr = addr.clone();
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("handshake with {} failed: {}", addr, e);
+                context.backoff.lock().note_failure(addr, Instant::now());
+                return;
+            }
+        };
 
-                        let peer_context = PeerContext::new(context.clone(), addr.clone());
-                        let outbound_sync = Arc::new(OutboundSync::new(peer_context));
+        let response: HandshakeResponse = match from_section(response) {
+            Ok(response) => response,
+            Err(_) => {
+                let e = InvokeError::InvalidResponse(Handshake::ID);
+                warn!("handshake with {} failed: {}", addr, e);
+                context.backoff.lock().note_failure(addr, Instant::now());
+                Context::close(context.clone(), &addr);
+                return;
+            }
+        };
 
-                        let sync_data = response.payload_data;
+        let checked = Context::check_node_data(context, &response.node_data)
+            .and_then(|_| Context::check_sync_data(context, &response.payload_data));
+        if let Err(e) = checked {
+            warn!("rejecting handshake from {}: {}", addr, e);
+            context.backoff.lock().note_failure(addr, Instant::now());
+            Context::close(context.clone(), &addr);
+            return;
+        }
 
-                        let inbound_sync_connection =
-                            context
-                                .local_sync_node
-                                .new_sync_connection(&sync_data, outbound_sync);
+        context.backoff.lock().note_success(&addr);
+        context.note_peer_height(&addr, response.payload_data.current_height);
+        Context::on_peerlist(context, &addr, &response.local_peerlist);
+        context.emit(P2pEvent::HandshakeComplete(addr.clone(),
+                                                 response.node_data.peer_id));
 
-                        context
-                            .inbound_sync_connections
-                            .write()
-                            .insert(addr.clone(), inbound_sync_connection);
-                    }
-                });
+        let peer_context = PeerContext::new(context.clone(), addr.clone());
+        let outbound_sync = Arc::new(OutboundSync::new(peer_context));
 
-                context
-                    .command_streams
-                    .write()
-                    .insert(addr.clone(), commands.clone());
-                context
-                    .connection_counter
-                    .note_new_outbound_connection(addr.clone());
-                // XXX: peerlist?
-
-                let future = levin_connect(&addr, handle, io_handler, commands)
-                    .map_err(|e| {
-                        warn!("connect io error: {}", e);
-                        ()
-                    });
-
-                context.pool.clone().spawn(future)
-            })
+        let sync_data = response.payload_data;
+
+        let inbound_sync_connection =
+            context
+                .local_sync_node
+                .new_sync_connection(&sync_data, outbound_sync);
+
+        context
+            .inbound_sync_connections
+            .write()
+            .insert(addr.clone(), inbound_sync_connection);
+    }
+
+    /// Registers an outbound connection and starts streaming its commands.
+    fn open_outbound(context: Arc<Context>,
+                     addr: SocketAddr,
+                     handle: &Handle,
+                     io_handler: IoHandlerRef,
+                     commands: Commands)
+                     -> CpuFuture<(), ()> {
+        context
+            .command_streams
+            .write()
+            .insert(addr.clone(), commands.clone());
+        context
+            .connection_counter
+            .note_new_outbound_connection(addr.clone());
+        context.emit(P2pEvent::Connected(addr.clone()));
+        // XXX: peerlist?
+
+        let pool = context.pool.clone();
+        let future = levin_connect(&addr,
+                                   handle,
+                                   context.config.socket_options,
+                                   io_handler,
+                                   commands)
+            .then(move |result| {
+                if let Err(e) = result {
+                    warn!("connection with {} failed: {}", addr, e);
+                }
+                Context::close(context, &addr);
+                Ok(())
+            });
+
+        pool.spawn(future)
+    }
+
+    /// Checks the node data received on a handshake, in both directions.
+    pub fn check_node_data(context: &Context,
+                           node_data: &BasicNodeData)
+                           -> Result<(), HandshakeError> {
+        let network_id = node_data.network_id.0;
+        if network_id.as_bytes() != &context.config.network.id_bytes() {
+            return Err(match Network::from_id(&network_id) {
+                           Some(network) => HandshakeError::OtherNetwork(network),
+                           None => HandshakeError::WrongNetwork(network_id),
+                       });
+        }
+
+        if node_data.peer_id == context.config.peer_id {
+            return Err(HandshakeError::SamePeerId);
+        }
+
+        let skew = ClockSkew::between(node_data.local_time, Context::local_time());
+        if skew.seconds > context.config.max_clock_skew.as_secs() {
+            return Err(HandshakeError::ClockSkew(skew));
+        } else if skew.seconds != 0 {
+            debug!("peer {:?} clock skew of {}", node_data.peer_id, skew);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the peer's top version against the hard fork schedule at the
+    /// peer's height.
+    pub fn check_sync_data(context: &Context,
+                           sync_data: &CoreSyncData)
+                           -> Result<(), HandshakeError> {
+        if sync_data.current_height == 0 {
+            return Ok(());
+        }
+
+        let expected = context
+            .config
+            .network
+            .hard_forks()
+            .ideal_version_for_height(sync_data.current_height - 1);
+        if sync_data.top_version < expected {
+            return Err(HandshakeError::ObsoleteVersion(sync_data.top_version, expected));
+        }
+
+        Ok(())
+    }
+
+    /// Adds the peers sent by `addr` to the gray peerlist, returns the
+    /// number of new entries.
+    ///
+    /// Only the first `MAX_PEERS_IN_HANDSHAKE` entries of oversized
+    /// peerlists are read, entries with reserved or local addresses are
+    /// skipped.
+    pub fn on_peerlist(context: &Context,
+                       addr: &SocketAddr,
+                       peerlist: &StlLinkedList<PeerlistEntry>)
+                       -> usize {
+        if peerlist.len() > MAX_PEERS_IN_HANDSHAKE {
+            warn!("peer {} sent {} peers, only reading the first {}",
+                  addr,
+                  peerlist.len(),
+                  MAX_PEERS_IN_HANDSHAKE);
+        }
+
+        let mut gray_peerlist = context.gray_peerlist.write();
+        let mut added = 0;
+        for entry in peerlist.0.iter().take(MAX_PEERS_IN_HANDSHAKE) {
+            let peer_addr = SocketAddrV4::from(&entry.adr);
+            if !is_valid_peer_address(&peer_addr) {
+                trace!("peer {} sent the invalid address {}", addr, peer_addr);
+                continue;
+            }
+
+            let peer_addr = SocketAddr::V4(peer_addr);
+            if gray_peerlist.len() >= GRAY_PEERLIST_LIMIT {
+                break;
+            }
+
+            if !gray_peerlist.contains(&peer_addr) {
+                gray_peerlist.insert(peer_addr, entry.clone());
+                added += 1;
+            }
+        }
+
+        added
+    }
+
+    /// Pings a connected peer over its command stream, `callback` receives
+    /// the decoded response.
+    pub fn ping<F>(context: Arc<Context>, addr: &SocketAddr, callback: F)
+        where F: Fn(Result<PingResponse, InvokeError>) + Send + Sync + 'static
+    {
+        let commands = context.command_streams.read().get(addr).cloned();
+        let commands = match commands {
+            Some(commands) => commands,
+            None => return callback(Err(InvokeError::NotConnected)),
+        };
+
+        Context::invoke::<Ping, _>(context, addr, &commands, Section::new(), move |response| {
+            let response = response.and_then(|response| {
+                from_section(response).map_err(|_| InvokeError::InvalidResponse(Ping::ID))
+            });
+            callback(response)
+        });
     }
 
     pub fn try_ping(context: Arc<Context>, addr: &SocketAddr) {
@@ -195,15 +681,21 @@ impl Context {
             .remote
             .clone()
             .spawn(move |handle| {
-                // TODO: on threadpool
-
                 let commands = Commands::new();
                 let io_handler = IoHandler::new().to_ref();
 
-                commands.invoke::<Ping, _>(Section::new(), {
+                Context::invoke::<Ping, _>(context.clone(), &addr, &commands, Section::new(), {
                     let context = context.clone();
                     let addr = addr.clone();
-                    move |response: Section| {
+                    move |response: Result<Section, InvokeError>| {
+                        let response = match response {
+                            Ok(response) => response,
+                            Err(e) => {
+                                warn!("ping to {} failed: {}", addr, e);
+                                return;
+                            }
+                        };
+
                         let response: Result<PingResponse, _> = from_section(response);
                         if let Ok(response) = response {
                             if response.is_ok() {
@@ -238,22 +730,7 @@ impl Context {
                     }
                 });
 
-                context
-                    .command_streams
-                    .write()
-                    .insert(addr.clone(), commands.clone());
-                context
-                    .connection_counter
-                    .note_new_outbound_connection(addr.clone());
-                // XXX: peerlist?
-
-                let future = levin_connect(&addr, handle, io_handler, commands)
-                    .map_err(|e| {
-                        warn!("connect io error: {}", e);
-                        ()
-                    });
-
-                context.pool.clone().spawn(future)
+                Context::open_outbound(context, addr, handle, io_handler, commands)
             })
     }
 
@@ -261,9 +738,17 @@ impl Context {
                         addr: SocketAddr,
                         request: HandshakeRequest)
                         -> Option<HandshakeResponse> {
-        let network_id = request.node_data.network_id.0;
-        if network_id != context.config.network.id() {
-            info!("wrong network agent connected! id {}", network_id);
+        if !context.ip_limiter.lock().try_handshake(addr.ip(), Instant::now()) {
+            info!("rejecting handshake from {}: too many handshakes", addr);
+            Context::close(context.clone(), &addr);
+
+            return None;
+        }
+
+        let checked = Context::check_node_data(&context, &request.node_data)
+            .and_then(|_| Context::check_sync_data(&context, &request.payload_data));
+        if let Err(e) = checked {
+            info!("rejecting handshake from {}: {}", addr, e);
             Context::close(context.clone(), &addr);
 
             return None;
@@ -281,6 +766,9 @@ impl Context {
             _ => { /* it's fine */ }
         }
 
+        context.emit(P2pEvent::HandshakeComplete(addr.clone(), request.node_data.peer_id));
+        context.note_peer_height(&addr, request.payload_data.current_height);
+
         let peer_context = PeerContext::new(context.clone(), addr.clone());
         let out_sync = Arc::new(OutboundSync::new(peer_context));
 
@@ -301,19 +789,28 @@ impl Context {
             .cloned()
             .unwrap();
 
-        if context.config.peer_id != request.node_data.peer_id && request.node_data.my_port != 0 {
+        if request.node_data.my_port != 0 {
             Context::try_ping(context.clone(), &addr);
         }
 
-        command_stream.invoke::<RequestSupportFlags, _>(Section::new(), {
+        let on_support_flags = {
             let context = context.clone();
             let in_sync = in_sync.clone();
             let addr = addr.clone();
 
-            move |response: Section| {
+            move |response: Result<Section, InvokeError>| {
+                let response = match response {
+                    Ok(response) => response,
+                    Err(e) => {
+                        warn!("support flags request to {} failed: {}", addr, e);
+                        return;
+                    }
+                };
+
                 let response: Result<SupportFlagsResponse, _> = from_section(response);
                 match response {
                     Ok(response) => {
+                        context.note_support_flags(&addr, response.support_flags);
                         in_sync.on_support_flags(response.support_flags);
                     }
                     Err(e) => {
@@ -324,7 +821,13 @@ impl Context {
                     }
                 }
             }
-        });
+        };
+
+        Context::invoke::<RequestSupportFlags, _>(context.clone(),
+                                                  &addr,
+                                                  &command_stream,
+                                                  Section::new(),
+                                                  on_support_flags);
 
         Some(HandshakeResponse {
                  node_data: Context::basic_node_data(context.clone()),
@@ -333,6 +836,104 @@ impl Context {
              })
     }
 
+    /// Invokes the command `C` on a peer.
+    ///
+    /// If the peer doesn't respond within `Config::invoke_timeout` the
+    /// handler is called with `InvokeError::Timeout` and the connection
+    /// is closed.
+    pub fn invoke<C, F>(context: Arc<Context>,
+                        addr: &SocketAddr,
+                        commands: &Commands,
+                        request: Section,
+                        handler: F)
+        where C: Command,
+              F: Fn(Result<Section, InvokeError>) + Send + Sync + 'static
+    {
+        let handler = Arc::new(handler);
+        let responded = Arc::new(AtomicBool::new(false));
+        let id = C::ID;
+        let addr = addr.clone();
+
+        // dropped on a response or when the connection closes, which
+        // stops the timer.
+        let (cancel_timer, timer_cancelled) = oneshot::channel::<()>();
+        let cancel_timer = Arc::new(Mutex::new(Some(cancel_timer)));
+
+        // fails the invocation if the connection closes before a response.
+        let invoke = context.next_invoke.fetch_add(1, Ordering::Relaxed);
+        context
+            .pending_invokes
+            .lock()
+            .entry(addr.clone())
+            .or_insert_with(HashMap::new)
+            .insert(invoke,
+                    Box::new({
+                                 let handler = handler.clone();
+                                 let responded = responded.clone();
+                                 let cancel_timer = cancel_timer.clone();
+                                 move || if !responded.swap(true, Ordering::AcqRel) {
+                                     cancel_timer.lock().take();
+                                     handler(Err(InvokeError::ConnectionClosed(id)));
+                                 }
+                             }));
+
+        commands.invoke::<C, _>(request, {
+            let context = context.clone();
+            let handler = handler.clone();
+            let responded = responded.clone();
+            move |response: Section| {
+                if !responded.swap(true, Ordering::AcqRel) {
+                    cancel_timer.lock().take();
+                    context.forget_invoke(&addr, invoke);
+                    handler(Ok(response));
+                }
+            }
+        });
+
+        let timeout = context.config.invoke_timeout;
+        context
+            .remote
+            .clone()
+            .spawn(move |handle| {
+                Timeout::new(timeout, handle)
+                    .expect("couldn't create the invoke timer")
+                    .select2(timer_cancelled)
+                    .then(move |result| {
+                        let expired = match result {
+                            Ok(Either::A(_)) | Err(Either::A(_)) => true,
+                            Ok(Either::B(_)) | Err(Either::B(_)) => false,
+                        };
+
+                        if expired && !responded.swap(true, Ordering::AcqRel) {
+                            warn!("peer {} didn't respond to command #{}, disconnecting",
+                                  addr,
+                                  id);
+                            context.forget_invoke(&addr, invoke);
+                            handler(Err(InvokeError::Timeout(id)));
+                            Context::close(context, &addr);
+                        }
+
+                        Ok(())
+                    })
+            })
+    }
+
+    /// Drops the pending entry of an invocation that got its response or
+    /// timed out.
+    fn forget_invoke(&self, addr: &SocketAddr, invoke: usize) {
+        let mut pending_invokes = self.pending_invokes.lock();
+        let is_empty = match pending_invokes.get_mut(addr) {
+            Some(pending) => {
+                pending.remove(&invoke);
+                pending.is_empty()
+            }
+            None => false,
+        };
+        if is_empty {
+            pending_invokes.remove(addr);
+        }
+    }
+
     pub fn on_ping(context: Arc<Context>) -> PingResponse {
         PingResponse::new(context.config.peer_id)
     }
@@ -342,58 +943,280 @@ impl Context {
     }
 
     pub fn on_timed_sync(context: Arc<Context>,
-                         _addr: SocketAddr,
-                         _request: TimedSyncRequest)
-                         -> TimedSyncResponse {
-        // TODO: handle request.payload_data
+                         addr: SocketAddr,
+                         request: TimedSyncRequest)
+                         -> Option<TimedSyncResponse> {
+        if let Err(e) = Context::check_sync_data(&context, &request.payload_data) {
+            info!("dropping {}: {}", addr, e);
+            Context::close(context.clone(), &addr);
 
-        TimedSyncResponse {
-            local_time: Context::local_time(),
-            payload_data: Context::core_sync_data(context.clone()),
-            local_peerlist: context.peerlist.read().stl_peerlist(),
+            return None;
         }
-    }
 
-    fn io_handler(context: Arc<Context>) -> IoHandlerRef {
-        let mut io_handler = IoHandler::with_capacity(12);
+        if context.peer_heights.read().contains_key(&addr) {
+            context.note_peer_height(&addr, request.payload_data.current_height);
+        }
 
-        io_handler.add_invokation::<Handshake, _>({
-          let context = context.clone();
-          move |addr: SocketAddr,
-                request: Section|
-                -> Result<Option<Section>, i32> {
-              from_section(request)
-                    .map_err(|_| -1)
-                    .map(|request: HandshakeRequest| {
-                        Context::on_handshake(context.clone(), addr, request)
-                            .map(|res| to_section(&res).unwrap())
-                    })
+        Some(TimedSyncResponse {
+                 local_time: Context::local_time(),
+                 payload_data: Context::core_sync_data(context.clone()),
+                 local_peerlist: context.peerlist.read().stl_peerlist(),
+             })
+    }
+
+    /// Answers a `RequestGetObjects` with the requested blocks we have,
+    /// unknown blocks and transactions are reported in `missed_ids`. The
+    /// response is capped at `MAX_OBJECT_REQUEST_COUNT` blocks and at the
+    /// maximum packet size, the peer asks again for the rest.
+    ///
+    /// The store doesn't keep transaction bodies, so only the blocks
+    /// complete without them, the ones with no transaction besides the
+    /// miner's, are served. The others are reported in `missed_ids` and
+    /// the peer gets them from someone else.
+    pub fn on_request_get_objects(context: &Context,
+                                  request: &RequestGetObjects)
+                                  -> ResponseGetObjects {
+        let mut blocks = Vec::new();
+        let mut missed_ids = Vec::new();
+        let mut size = 0u64;
+
+        for id in request.blocks.iter() {
+            if blocks.len() == MAX_OBJECT_REQUEST_COUNT {
+                break;
             }
-        });
 
-        io_handler.add_invokation::<Ping, _>({
-            let context = context.clone();
-             move |_: SocketAddr, _: Section|
-                   -> Result<Option<Section>, i32> {
-                 let res = Context::on_ping(context.clone());
-                 Ok(Some(to_section(&res).unwrap()))
-             }
-        });
+            let block = match context.store.indexed_block(id.clone().into()) {
+                Some(block) => block,
+                None => {
+                    missed_ids.push(id.clone());
+                    continue;
+                }
+            };
+            if !block.raw.tx_hashes.is_empty() {
+                missed_ids.push(id.clone());
+                continue;
+            }
 
-        io_handler.add_invokation::<RequestSupportFlags, _>({
-            move |_: SocketAddr, _: Section| -> Result<Option<Section>, i32> {
-                let res = Context::on_request_support_flags();
-                Ok(Some(to_section(&res).unwrap()))
+            let blob = block.raw.serialize_blob();
+            size += blob.len() as u64;
+            if !blocks.is_empty() && size > context.config.max_packet_size {
+                break;
             }
-        });
 
-        io_handler.add_invokation::<TimedSync, _>({
+            blocks.push(BlockCompleteEntry {
+                            block: blob.to_vec().into(),
+                            txs: Vec::new(),
+                        });
+        }
+
+        missed_ids.extend(request.txs.iter().cloned());
+
+        ResponseGetObjects {
+            txs: Vec::new(),
+            blocks,
+            missed_ids,
+            current_blockchain_height: context.store.height(),
+        }
+    }
+
+    /// Answers a `RequestFluffyMissingTx` with a fluffy block carrying the
+    /// requested transactions.
+    ///
+    /// There's no transaction pool yet and the store doesn't keep
+    /// transaction bodies, so the transactions are taken from the recently
+    /// relayed ones. The request isn't answered when the block is unknown
+    /// or some of them weren't relayed, the peer asks someone else.
+    pub fn on_request_fluffy_missing_tx(context: &Context,
+                                        request: &RequestFluffyMissingTx)
+                                        -> Result<NewFluffyBlock, Error> {
+        let block = context
+            .store
+            .indexed_block(request.block_hash.clone().into())
+            .ok_or_else(|| err_msg(format!("unknown block {:?}", request.block_hash)))?;
+
+        let relayed_txs = context.relayed_txs.lock();
+        let mut txs = Vec::with_capacity(request.missing_tx_indices.0.len());
+        for &index in request.missing_tx_indices.0.iter() {
+            let tx_hash = block
+                .raw
+                .tx_hashes
+                .get(index as usize)
+                .ok_or_else(|| err_msg(format!("transaction index {} out of range", index)))?;
+            let tx = relayed_txs
+                .get(tx_hash)
+                .ok_or_else(|| err_msg(format!("transaction {:?} wasn't relayed", tx_hash)))?;
+            txs.push(tx.clone());
+        }
+
+        Ok(NewFluffyBlock {
+               b: BlockCompleteEntry {
+                   block: block.raw.serialize_blob().to_vec().into(),
+                   txs,
+               },
+               current_blockchain_height: context.store.height(),
+           })
+    }
+
+    /// Remembers the relayed transactions to answer `RequestFluffyMissingTx`,
+    /// fails when one of them can't be decoded.
+    pub fn on_new_transactions(context: &Context, request: &NewTransactions) -> Result<(), Error> {
+        for tx in request.txs.iter() {
+            let id = Transaction::from_bytes(&tx.0)
+                .map_err(|e| err_msg(format!("invalid relayed transaction: {}", e)))?
+                .id();
+            context.relayed_txs.lock().insert(id, tx.clone());
+        }
+
+        Ok(())
+    }
+
+    /// The request for the transactions of a fluffy block that weren't
+    /// relayed along with it nor recently, `None` when the block came
+    /// complete.
+    pub fn on_new_fluffy_block(context: &Context,
+                               request: &NewFluffyBlock)
+                               -> Result<Option<RequestFluffyMissingTx>, Error> {
+        let block = Block::from_bytes(&request.b.block.0)
+            .map_err(|e| err_msg(format!("invalid fluffy block: {}", e)))?;
+
+        let mut relayed = HashSet::with_capacity(request.b.txs.len());
+        for tx in request.b.txs.iter() {
+            let id = Transaction::from_bytes(&tx.0)
+                .map_err(|e| err_msg(format!("invalid fluffy block transaction: {}", e)))?
+                .id();
+            context.relayed_txs.lock().insert(id.clone(), tx.clone());
+            relayed.insert(id);
+        }
+
+        let missing_tx_indices = {
+            let relayed_txs = context.relayed_txs.lock();
+            block
+                .tx_hashes
+                .iter()
+                .enumerate()
+                .filter(|&(_, tx_hash)| !relayed.contains(tx_hash) && !relayed_txs.contains(tx_hash))
+                .map(|(index, _)| index as u64)
+                .collect::<Vec<_>>()
+        };
+
+        if missing_tx_indices.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(RequestFluffyMissingTx {
+                    block_hash: block.id(),
+                    current_blockchain_length: context.store.height(),
+                    missing_tx_indices: StlVector(missing_tx_indices),
+                }))
+    }
+
+    /// Closes the connection of `addr` after it sent something invalid, and
+    /// backs it off.
+    fn drop_misbehaving(context: &Arc<Context>, addr: &SocketAddr, e: Error) {
+        warn!("dropping {}: {}", addr, e);
+        context.backoff.lock().note_failure(*addr, Instant::now());
+        Context::close(context.clone(), addr);
+    }
+
+    /// Decodes a notification from `addr` and hands it to `handler` on the
+    /// thread pool, the reactor only does the IO.
+    fn spawn_notification<T, F>(context: Arc<Context>,
+                                addr: SocketAddr,
+                                request: Section,
+                                handler: F)
+        where T: DeserializeOwned + Send + 'static,
+              F: FnOnce(&Arc<Context>, SocketAddr, &T) + Send + 'static
+    {
+        let pool_context = context.clone();
+        context
+            .pool
+            .spawn_fn(move || {
+                let request: T = match from_section(request) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        debug!("invalid notification from {}: {}", addr, e);
+                        return Ok(());
+                    }
+                };
+
+                handler(&pool_context, addr, &request);
+
+                Ok::<(), ()>(())
+            })
+            .forget();
+    }
+
+    /// Decodes a notification from `addr` on the thread pool and hands it to
+    /// the inbound sync connection of the peer.
+    fn spawn_sync_notification<T, F>(context: Arc<Context>,
+                                     addr: SocketAddr,
+                                     request: Section,
+                                     handler: F)
+        where T: DeserializeOwned + Send + 'static,
+              F: FnOnce(InboundSyncConnectionRef, &T) + Send + 'static
+    {
+        Context::spawn_notification(context, addr, request, move |context, addr, request: &T| {
+            Context::on_sync_notification(context, &addr, |c| handler(c, request))
+        })
+    }
+
+    /// Hands a notification from `addr` to the inbound sync connection of
+    /// the peer.
+    fn on_sync_notification<F>(context: &Context, addr: &SocketAddr, handler: F)
+        where F: FnOnce(InboundSyncConnectionRef)
+    {
+        let connection = context
+            .inbound_sync_connections
+            .read()
+            .get(addr)
+            .cloned();
+        match connection {
+            Some(connection) => handler(connection),
+            None => debug!("notification from unknown peer {}", addr),
+        }
+    }
+
+    fn io_handler(context: Arc<Context>) -> IoHandlerRef {
+        let mut io_handler = IoHandler::with_capacity(12);
+        io_handler.set_max_packet_size(context.config.max_packet_size);
+
+        io_handler.add_invokation::<Handshake, _>({
+          let context = context.clone();
+          move |addr: SocketAddr,
+                request: Section|
+                -> Result<Option<Section>, i32> {
+              from_section(request)
+                    .map_err(|_| -1)
+                    .map(|request: HandshakeRequest| {
+                        Context::on_handshake(context.clone(), addr, request)
+                            .map(|res| to_section(&res).unwrap())
+                    })
+            }
+        });
+
+        io_handler.add_invokation::<Ping, _>({
+            let context = context.clone();
+             move |_: SocketAddr, _: Section|
+                   -> Result<Option<Section>, i32> {
+                 let res = Context::on_ping(context.clone());
+                 Ok(Some(to_section(&res).unwrap()))
+             }
+        });
+
+        io_handler.add_invokation::<RequestSupportFlags, _>({
+            move |_: SocketAddr, _: Section| -> Result<Option<Section>, i32> {
+                let res = Context::on_request_support_flags();
+                Ok(Some(to_section(&res).unwrap()))
+            }
+        });
+
+        io_handler.add_invokation::<TimedSync, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| -> Result<Option<Section>, i32> {
                 from_section(request)
                     .map(|request: TimedSyncRequest| {
-                        let res = Context::on_timed_sync(context.clone(), addr, request);
-                        Some(to_section(&res).unwrap())
+                        Context::on_timed_sync(context.clone(), addr, request)
+                            .map(|res| to_section(&res).unwrap())
                     })
                     .map_err(|_| -1)
             }
@@ -402,119 +1225,104 @@ impl Context {
         io_handler.add_notification::<NewBlock, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .cloned()
-                        .unwrap()
-                        .on_new_block(&req);
-                }
+                Context::spawn_sync_notification(context.clone(), addr, request, |c, req: &NewBlock| {
+                    c.on_new_block(req)
+                });
             }
         });
 
         io_handler.add_notification::<NewFluffyBlock, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .cloned()
-                        .unwrap()
-                        .on_new_fluffy_block(&req);
-                }
+                let on_block = |context: &Arc<Context>, addr, req: &NewFluffyBlock| {
+                    // the block is handed to the sync node once complete,
+                    // the peer answers the request with a new fluffy block.
+                    match Context::on_new_fluffy_block(context, req) {
+                        Ok(Some(missing)) => {
+                            PeerContext::new(context.clone(), addr)
+                                .notify::<RequestFluffyMissingTx>(to_section(&missing).unwrap());
+                        }
+                        Ok(None) => {
+                            Context::on_sync_notification(context, &addr, |c| {
+                                c.on_new_fluffy_block(req)
+                            })
+                        }
+                        Err(e) => Context::drop_misbehaving(context, &addr, e),
+                    }
+                };
+                Context::spawn_notification(context.clone(), addr, request, on_block);
             }
         });
 
         io_handler.add_notification::<NewTransactions, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .unwrap()
-                        .on_new_transactions(&req);
-                }
+                let on_txs = |context: &Arc<Context>, addr, req: &NewTransactions| {
+                    match Context::on_new_transactions(context, req) {
+                        Ok(()) => {
+                            Context::on_sync_notification(context, &addr, |c| {
+                                c.on_new_transactions(req)
+                            })
+                        }
+                        Err(e) => Context::drop_misbehaving(context, &addr, e),
+                    }
+                };
+                Context::spawn_notification(context.clone(), addr, request, on_txs);
             }
         });
 
         io_handler.add_notification::<RequestChain, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .cloned()
-                        .unwrap()
-                        .on_request_chain(&req);
-                }
+                Context::spawn_sync_notification(context.clone(), addr, request, |c, req: &RequestChain| {
+                    c.on_request_chain(req)
+                });
             }
         });
 
         io_handler.add_notification::<RequestFluffyMissingTx, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .cloned()
-                        .unwrap()
-                        .on_request_fluffy_missing_tx(&req);
-                }
+                let on_request = |context: &Arc<Context>, addr, req: &RequestFluffyMissingTx| {
+                    match Context::on_request_fluffy_missing_tx(context, req) {
+                        Ok(response) => {
+                            PeerContext::new(context.clone(), addr)
+                                .notify::<NewFluffyBlock>(to_section(&response).unwrap());
+                        }
+                        Err(e) => debug!("not answering {}: {}", addr, e),
+                    }
+                };
+                Context::spawn_notification(context.clone(), addr, request, on_request);
             }
         });
 
         io_handler.add_notification::<RequestGetObjects, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .cloned()
-                        .unwrap()
-                        .on_request_get_objects(&req);
-                }
+                let on_request = |context: &Arc<Context>, addr, req: &RequestGetObjects| {
+                    let response = Context::on_request_get_objects(context, req);
+                    PeerContext::new(context.clone(), addr)
+                        .notify::<ResponseGetObjects>(to_section(&response).unwrap());
+                };
+                Context::spawn_notification(context.clone(), addr, request, on_request);
             }
         });
 
         io_handler.add_notification::<ResponseChainEntry, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .cloned()
-                        .unwrap()
-                        .on_response_chain_entry(&req);
-                }
+                Context::spawn_sync_notification(context.clone(), addr, request, |c, req: &ResponseChainEntry| {
+                    c.on_response_chain_entry(req)
+                });
             }
         });
 
         io_handler.add_notification::<ResponseGetObjects, _>({
             let context = context.clone();
             move |addr: SocketAddr, request: Section| {
-                if let Ok(req) = from_section(request) {
-                    context
-                        .inbound_sync_connections
-                        .read()
-                        .get(&addr)
-                        .cloned()
-                        .unwrap()
-                        .on_response_get_objects(&req);
-                }
+                Context::spawn_sync_notification(context.clone(), addr, request, |c, req: &ResponseGetObjects| {
+                    c.on_response_get_objects(req)
+                });
             }
         });
 
@@ -522,32 +1330,48 @@ impl Context {
     }
 
     pub fn basic_node_data(context: Arc<Context>) -> BasicNodeData {
-        let my_port = if context.config.hide_my_port {
-            0
-        } else {
-            context
-                .config
-                .listen_port
-                .map(|p| p as u32)
-                .unwrap_or(context.config.network.listen_port() as u32)
-        };
+        let listen_port = context
+            .config
+            .listen_port
+            .map(|p| p as u32)
+            .unwrap_or(context.config.network.listen_port());
+
+        Context::node_data_for(context.config.network,
+                               context.config.peer_id,
+                               context.config.hide_my_port,
+                               listen_port)
+    }
+
+    pub fn core_sync_data(context: Arc<Context>) -> CoreSyncData {
+        Context::sync_data_for(context.config.network, context.store.best_block())
+    }
+
+    /// The node data sent in handshakes, `my_port` is 0 when the port is
+    /// hidden.
+    pub fn node_data_for(network: Network,
+                         peer_id: PeerId,
+                         hide_my_port: bool,
+                         listen_port: u32)
+                         -> BasicNodeData {
+        let my_port = if hide_my_port { 0 } else { listen_port };
 
         BasicNodeData {
-            network_id: context.config.network.id().into(),
+            network_id: network.id().into(),
             local_time: Context::local_time(),
             my_port,
-            peer_id: context.config.peer_id,
+            peer_id,
         }
     }
 
-    pub fn core_sync_data(context: Arc<Context>) -> CoreSyncData {
-        let best_block = context.store.best_block();
+    /// The sync data sent in handshakes and timed syncs for the chain
+    /// ending in `best_block`.
+    pub fn sync_data_for(network: Network, best_block: BestBlock) -> CoreSyncData {
         CoreSyncData {
             // TODO: cumulative difficulty?,
             cumulative_difficulty: 0,
             current_height: best_block.height,
             top_id: best_block.id,
-            top_version: context.config.network.hard_forks().ideal_version(),
+            top_version: network.hard_forks().ideal_version(),
         }
     }
 
@@ -583,18 +1407,43 @@ impl P2P {
     pub fn run(&self) -> Result<(), Error> {
         let io_handler = Context::io_handler(self.context.clone());
 
-        if !self.config.hide_my_port {
+        if self.config.hide_my_port {
+            info!("hiding our port, only outbound connections will be made.");
+        } else {
             info!("spawning the levin server.");
             Context::spawn_server(self.context.clone(), io_handler.clone())
         }
 
-        for addr in self.config.peers.iter() {
-            info!("connecting to {}", addr);
-            Context::connect(self.context.clone(), addr, io_handler.clone())
-        }
+        Context::keep_priority_peers(self.context.clone(), io_handler.clone());
+        Context::dial(self.context.clone(), &self.config.peers, io_handler);
 
         Ok(())
     }
+
+    /// Is the levin server spawned?
+    pub fn is_listening(&self) -> bool {
+        self.context.listening.load(Ordering::Acquire)
+    }
+
+    /// Sets the channel receiving the connection events.
+    pub fn set_event_sink(&self, sink: SyncSender<P2pEvent>) {
+        self.context.set_event_sink(sink)
+    }
+
+    /// Are we connected to the network?
+    pub fn is_connected(&self) -> bool {
+        self.context.is_connected()
+    }
+
+    /// Is `local_height` close enough to our peers' chain height?
+    pub fn is_synced(&self, local_height: u64) -> bool {
+        self.context.is_synced(local_height)
+    }
+
+    /// Stops the node, closing all the connections.
+    pub fn shutdown(&self) {
+        Context::shutdown(self.context.clone())
+    }
 }
 
 pub struct ConnectionHandler {
@@ -609,6 +1458,12 @@ impl ConnectionHandler {
 
 impl ConnectionHandlerTrait for ConnectionHandler {
     fn on_connect(&self, addr: SocketAddr, commands: Commands) {
+        if !self.context.ip_limiter.lock().try_connect(addr.ip()) {
+            info!("refusing inbound connection from {}: too many connections", addr);
+            commands.shutdown();
+            return;
+        }
+
         info!("new inbound connection from {}", addr);
         self.context
             .command_streams
@@ -617,5 +1472,1039 @@ impl ConnectionHandlerTrait for ConnectionHandler {
         self.context
             .connection_counter
             .note_new_inbound_connection(addr.clone());
+        self.context.emit(P2pEvent::Connected(addr));
+    }
+
+    fn on_disconnect(&self, addr: SocketAddr) {
+        info!("inbound connection from {} closed", addr);
+        Context::close(self.context.clone(), &addr);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::LinkedList;
+    use std::sync::mpsc::{self, sync_channel};
+    use std::thread;
+
+    use std::time::Duration;
+
+    use parking_lot::Mutex;
+    use tokio_core::reactor::Core;
+    use uuid::Uuid;
+
+    use network::Network;
+    use primitives::H256;
+    use storage::{BestBlock, BlockChain, BlockProvider, BlockRef, CanonStore,
+                  IndexedBlockProvider, Store};
+
+    use chain::IndexedBlock;
+
+    use protocol::{LocalSyncNode, OutboundSyncConnectionRef, InboundSyncConnection,
+                   InboundSyncConnectionRef};
+    use config::{DEFAULT_MAX_CLOCK_SKEW, DEFAULT_MAX_CONNECTIONS_PER_IP,
+                 DEFAULT_MAX_HANDSHAKES_PER_IP, DEFAULT_MAX_PACKET_SIZE,
+                 DEFAULT_PRIORITY_REDIAL_INTERVAL, SocketOptions};
+    use portable_storage_utils::Blob;
+    use types::PeerId;
+    use types::cmd::P2P_SUPPORT_FLAG_FLUFFY_BLOCKS;
+
+    use super::*;
+
+    pub struct DummyStore {
+        pub best_block: BestBlock,
+        pub blocks: Vec<Block>,
+    }
+
+    impl BlockChain for DummyStore {
+        fn insert(&self, _block: IndexedBlock) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn canonize(&self, _id: &H256) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl BlockProvider for DummyStore {
+        fn block_id(&self, _height: u64) -> Option<H256> {
+            None
+        }
+    }
+
+    impl IndexedBlockProvider for DummyStore {
+        fn indexed_block(&self, block_ref: BlockRef) -> Option<IndexedBlock> {
+            match block_ref {
+                BlockRef::Id(id) => {
+                    self.blocks
+                        .iter()
+                        .find(|block| block.id() == id)
+                        .map(|block| block.clone().into())
+                }
+                BlockRef::Height(_) => None,
+            }
+        }
+    }
+
+    impl Store for DummyStore {
+        fn best_block(&self) -> BestBlock {
+            self.best_block.clone()
+        }
+
+        fn height(&self) -> u64 {
+            self.best_block.height + 1
+        }
+
+        fn short_chain_history(&self) -> LinkedList<H256> {
+            LinkedList::new()
+        }
+    }
+
+    impl CanonStore for DummyStore {
+        fn as_store(&self) -> &Store {
+            &*self
+        }
+    }
+
+    pub struct DummySyncNode;
+
+    impl LocalSyncNode for DummySyncNode {
+        fn new_sync_connection(&self,
+                               _sync_data: &CoreSyncData,
+                               _connection: OutboundSyncConnectionRef)
+                               -> InboundSyncConnectionRef {
+            Arc::new(DummySyncConnection)
+        }
+    }
+
+    pub struct DummySyncConnection;
+
+    impl InboundSyncConnection for DummySyncConnection {
+        fn on_new_block(&self, _arg: &NewBlock) {}
+        fn on_new_fluffy_block(&self, _arg: &NewFluffyBlock) {}
+        fn on_new_transactions(&self, _arg: &NewTransactions) {}
+        fn on_request_chain(&self, _arg: &RequestChain) {}
+        fn on_request_fluffy_missing_tx(&self, _arg: &RequestFluffyMissingTx) {}
+        fn on_request_get_objects(&self, _arg: &RequestGetObjects) {}
+        fn on_response_chain_entry(&self, _arg: &ResponseChainEntry) {}
+        fn on_response_get_objects(&self, _arg: &ResponseGetObjects) {}
+        fn on_support_flags(&self, _arg: u32) {}
+    }
+
+    pub fn test_config() -> Config {
+        Config {
+            threads: 1,
+            network: Network::Testnet,
+            peers: vec![],
+            listen_port: None,
+            hide_my_port: false,
+            out_peers: 10,
+            in_peers: 10,
+            peer_id: PeerId::from(0x1234),
+            invoke_timeout: Duration::from_secs(30),
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            max_clock_skew: Duration::from_secs(DEFAULT_MAX_CLOCK_SKEW),
+            socket_options: SocketOptions::default(),
+            max_connections_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+            max_handshakes_per_ip: DEFAULT_MAX_HANDSHAKES_PER_IP,
+            use_ipv4: true,
+            use_ipv6: true,
+            priority_peers: vec![],
+            priority_redial_interval: Duration::from_secs(DEFAULT_PRIORITY_REDIAL_INTERVAL),
+            priority_peers_use_out_slots: false,
+        }
+    }
+
+    pub fn test_p2p(core: &Core, config: Config) -> P2P {
+        let store = Arc::new(DummyStore {
+                                 best_block: BestBlock::default(),
+                                 blocks: Vec::new(),
+                             });
+        P2P::new(config, core.handle(), store, Box::new(DummySyncNode))
+    }
+
+    /// Serves `p2p` on an ephemeral port of the loopback interface and
+    /// returns its address.
+    fn spawn_test_server(core: &Core, p2p: &P2P) -> SocketAddr {
+        let context = p2p.context.clone();
+        let server = TcpServer::bind(&SocketAddr::from(([127, 0, 0, 1], 0)),
+                                     &core.handle(),
+                                     context.config.socket_options,
+                                     Context::io_handler(context.clone()),
+                                     ConnectionHandler::new(context.clone()))
+                .unwrap();
+        let addr = server.local_addr().unwrap();
+        core.handle().spawn(server.run().map_err(|e| panic!("server io error: {}", e)));
+        addr
+    }
+
+    /// Turns the reactor until `done` returns true, panicking if it takes
+    /// more than a few seconds.
+    fn run_until<F: FnMut() -> bool>(core: &mut Core, mut done: F) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            core.turn(Some(Duration::from_millis(10)));
+        }
+    }
+
+    #[test]
+    fn shutdown_closes_all_connections() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+
+        let inbound: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        let outbound: SocketAddr = "127.0.0.2:28080".parse().unwrap();
+
+        ConnectionHandler::new(p2p.context.clone()).on_connect(inbound, Commands::new());
+        p2p.context
+            .command_streams
+            .write()
+            .insert(outbound, Commands::new());
+        p2p.context
+            .connection_counter
+            .note_new_outbound_connection(outbound);
+
+        p2p.shutdown();
+
+        assert!(p2p.context.command_streams.read().is_empty());
+        assert_eq!(p2p.context.connection_counter.inbound_connections().0, 0);
+        assert_eq!(p2p.context.connection_counter.outbound_connections().0, 0);
+        assert!(p2p.context.connection_counter.connection_type(&inbound).is_none());
+
+        // a second shutdown is a no-op.
+        p2p.shutdown();
+        assert!(p2p.context.command_streams.read().is_empty());
+    }
+
+    #[test]
+    fn connections_are_capped_per_ip() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, Config { max_connections_per_ip: 2, ..test_config() });
+        let connection_handler = ConnectionHandler::new(p2p.context.clone());
+
+        let excess = Commands::new();
+        connection_handler.on_connect("1.2.3.4:1000".parse().unwrap(), Commands::new());
+        connection_handler.on_connect("1.2.3.4:1001".parse().unwrap(), Commands::new());
+        connection_handler.on_connect("1.2.3.4:1002".parse().unwrap(), excess.clone());
+        connection_handler.on_connect("5.6.7.8:1000".parse().unwrap(), Commands::new());
+
+        assert_eq!(p2p.context.connection_counter.inbound_connections().0, 3);
+        assert!(p2p.context
+                    .command_streams
+                    .read()
+                    .get(&"1.2.3.4:1002".parse().unwrap())
+                    .is_none());
+        assert!(excess.is_shutdown());
+
+        // a closed connection frees its slot.
+        Context::close(p2p.context.clone(), &"1.2.3.4:1000".parse().unwrap());
+        connection_handler.on_connect("1.2.3.4:1003".parse().unwrap(), Commands::new());
+        assert_eq!(p2p.context.connection_counter.inbound_connections().0, 3);
+    }
+
+    #[test]
+    fn serve_get_objects() {
+        let core = Core::new().unwrap();
+        let blocks: Vec<Block> = (0..3)
+            .map(|nonce| {
+                     let mut block = Network::Testnet.genesis_block();
+                     block.header.nonce = nonce;
+                     block
+                 })
+            .collect();
+        let store = Arc::new(DummyStore {
+                                 best_block: BestBlock::default(),
+                                 blocks: blocks.clone(),
+                             });
+        let p2p = P2P::new(test_config(), core.handle(), store, Box::new(DummySyncNode));
+
+        let unknown = H256::from([0x42; 32]);
+        let request = RequestGetObjects {
+            txs: vec![],
+            blocks: vec![blocks[0].id(), unknown.clone(), blocks[1].id(), blocks[2].id()],
+        };
+
+        let response = Context::on_request_get_objects(&p2p.context, &request);
+        assert_eq!(response.blocks.len(), 3);
+        for (entry, block) in response.blocks.iter().zip(blocks.iter()) {
+            assert_eq!(entry.block.0, block.serialize_blob().to_vec());
+        }
+        assert_eq!(response.missed_ids, vec![unknown]);
+    }
+
+    #[test]
+    fn blocks_without_their_transactions_are_not_served() {
+        let core = Core::new().unwrap();
+        let mut block = Network::Testnet.genesis_block();
+        block.tx_hashes = vec![H256::from([0x42; 32])];
+        let store = Arc::new(DummyStore {
+                                 best_block: BestBlock::default(),
+                                 blocks: vec![block.clone()],
+                             });
+        let p2p = P2P::new(test_config(), core.handle(), store, Box::new(DummySyncNode));
+
+        let request = RequestGetObjects {
+            txs: vec![],
+            blocks: vec![block.id()],
+        };
+
+        let response = Context::on_request_get_objects(&p2p.context, &request);
+        assert!(response.blocks.is_empty());
+        assert_eq!(response.missed_ids, vec![block.id()]);
+    }
+
+    #[test]
+    fn fluffy_block_missing_tx_is_requested() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+
+        let relayed = Network::Testnet.genesis_block().miner_tx;
+        let unknown = H256::from([0x42; 32]);
+        let mut block = Network::Testnet.genesis_block();
+        block.tx_hashes = vec![relayed.id(), unknown];
+
+        let mut request = NewFluffyBlock {
+            b: BlockCompleteEntry {
+                block: block.serialize_blob().to_vec().into(),
+                txs: vec![relayed.pruned_blob().to_vec().into()],
+            },
+            current_blockchain_height: 1,
+        };
+
+        let missing = Context::on_new_fluffy_block(&p2p.context, &request)
+            .unwrap()
+            .unwrap();
+        assert_eq!(missing.block_hash, block.id());
+        assert_eq!(missing.current_blockchain_length, p2p.context.store.height());
+        assert_eq!(missing.missing_tx_indices.0, vec![1]);
+
+        block.tx_hashes.pop();
+        request.b.block = block.serialize_blob().to_vec().into();
+        assert!(Context::on_new_fluffy_block(&p2p.context, &request)
+                    .unwrap()
+                    .is_none());
+
+        request.b.block = vec![0x01, 0x02].into();
+        assert!(Context::on_new_fluffy_block(&p2p.context, &request).is_err());
+    }
+
+    #[test]
+    fn recently_relayed_tx_is_not_requested() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+
+        let tx = Network::Mainnet.genesis_block().miner_tx;
+        let mut block = Network::Testnet.genesis_block();
+        block.tx_hashes = vec![tx.id()];
+        let request = NewFluffyBlock {
+            b: BlockCompleteEntry {
+                block: block.serialize_blob().to_vec().into(),
+                txs: vec![],
+            },
+            current_blockchain_height: 1,
+        };
+
+        assert!(Context::on_new_fluffy_block(&p2p.context, &request)
+                    .unwrap()
+                    .is_some());
+
+        let txs = NewTransactions { txs: vec![tx.pruned_blob().to_vec().into()] };
+        Context::on_new_transactions(&p2p.context, &txs).unwrap();
+        assert!(Context::on_new_fluffy_block(&p2p.context, &request)
+                    .unwrap()
+                    .is_none());
+
+        let invalid = NewTransactions { txs: vec![vec![0x01, 0x02].into()] };
+        assert!(Context::on_new_transactions(&p2p.context, &invalid).is_err());
+    }
+
+    #[test]
+    fn serve_fluffy_missing_tx() {
+        let core = Core::new().unwrap();
+        let tx = Network::Mainnet.genesis_block().miner_tx;
+        let mut block = Network::Testnet.genesis_block();
+        block.tx_hashes = vec![tx.id()];
+        let store = Arc::new(DummyStore {
+                                 best_block: BestBlock::default(),
+                                 blocks: vec![block.clone()],
+                             });
+        let p2p = P2P::new(test_config(), core.handle(), store, Box::new(DummySyncNode));
+
+        let mut request = RequestFluffyMissingTx {
+            block_hash: block.id(),
+            current_blockchain_length: 1,
+            missing_tx_indices: StlVector(vec![0]),
+        };
+
+        // the transaction wasn't relayed.
+        assert!(Context::on_request_fluffy_missing_tx(&p2p.context, &request).is_err());
+
+        let blob: Blob = tx.pruned_blob().to_vec().into();
+        let txs = NewTransactions { txs: vec![blob.clone()] };
+        Context::on_new_transactions(&p2p.context, &txs).unwrap();
+
+        let response = Context::on_request_fluffy_missing_tx(&p2p.context, &request).unwrap();
+        assert_eq!(response.b.block.0, block.serialize_blob().to_vec());
+        assert_eq!(response.b.txs, vec![blob]);
+
+        request.missing_tx_indices = StlVector(vec![1]);
+        assert!(Context::on_request_fluffy_missing_tx(&p2p.context, &request).is_err());
+
+        request.block_hash = H256::from([0x42; 32]);
+        request.missing_tx_indices = StlVector(vec![0]);
+        assert!(Context::on_request_fluffy_missing_tx(&p2p.context, &request).is_err());
+    }
+
+    #[test]
+    fn misbehaving_peer_is_dropped() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        p2p.context
+            .command_streams
+            .write()
+            .insert(addr, Commands::new());
+
+        Context::drop_misbehaving(&p2p.context, &addr, err_msg("invalid fluffy block"));
+
+        assert!(p2p.context.command_streams.read().get(&addr).is_none());
+        assert_eq!(p2p.context.backoff.lock().failures(&addr), 1);
+    }
+
+    #[test]
+    fn peer_support_flags() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let addr: SocketAddr = "1.2.3.4:18080".parse().unwrap();
+        let peer = PeerContext::new(p2p.context.clone(), addr);
+
+        ConnectionHandler::new(p2p.context.clone()).on_connect(addr, Commands::new());
+        assert_eq!(peer.support_flags(), 0);
+        assert!(!peer.supports(P2P_SUPPORT_FLAG_FLUFFY_BLOCKS));
+
+        p2p.context.note_support_flags(&addr, P2P_SUPPORT_FLAG_FLUFFY_BLOCKS);
+        assert_eq!(peer.support_flags(), P2P_SUPPORT_FLAG_FLUFFY_BLOCKS);
+        assert!(peer.supports(P2P_SUPPORT_FLAG_FLUFFY_BLOCKS));
+        assert!(!peer.supports(0x02));
+
+        peer.close();
+        assert!(!peer.supports(P2P_SUPPORT_FLAG_FLUFFY_BLOCKS));
+    }
+
+    #[test]
+    fn address_families() {
+        let core = Core::new().unwrap();
+        let v4: SocketAddr = "1.2.3.4:28080".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:28080".parse().unwrap();
+
+        let p2p = test_p2p(&core, Config { listen_port: Some(28090), ..test_config() });
+        assert_eq!(p2p.context.listen_addrs(),
+                   vec!["0.0.0.0:28090".parse().unwrap(), "[::]:28090".parse().unwrap()]);
+
+        let config = Config {
+            peers: vec![v4, v6],
+            hide_my_port: true,
+            listen_port: Some(28090),
+            use_ipv4: false,
+            ..test_config()
+        };
+        let p2p = test_p2p(&core, config);
+        assert_eq!(p2p.context.listen_addrs(), vec!["[::]:28090".parse().unwrap()]);
+        assert!(!p2p.context.allows_address(&v4));
+        assert!(p2p.context.allows_address(&v6));
+
+        p2p.run().unwrap();
+        assert_eq!(p2p.context.dials_in_flight.load(Ordering::Acquire), 1);
+        assert!(p2p.context.dial_queue.lock().is_empty());
+    }
+
+    #[test]
+    fn dials_are_capped_by_out_peers() {
+        let core = Core::new().unwrap();
+        let peers: Vec<SocketAddr> = (1..6)
+            .map(|i| format!("127.0.0.{}:28080", i).parse().unwrap())
+            .collect();
+        let config = Config {
+            peers: peers.clone(),
+            hide_my_port: true,
+            out_peers: 2,
+            ..test_config()
+        };
+        let p2p = test_p2p(&core, config);
+
+        p2p.run().unwrap();
+
+        assert_eq!(p2p.context.dials_in_flight.load(Ordering::Acquire), 2);
+        let queued: Vec<SocketAddr> = p2p.context.dial_queue.lock().iter().cloned().collect();
+        assert_eq!(queued.len(), 3);
+        for addr in queued.iter() {
+            assert!(peers.contains(addr));
+        }
+
+        let io_handler = Context::io_handler(p2p.context.clone());
+        Context::on_dial_done(p2p.context.clone(), &peers[0], io_handler);
+        assert_eq!(p2p.context.dials_in_flight.load(Ordering::Acquire), 2);
+        assert_eq!(p2p.context.dial_queue.lock().len(), 2);
+    }
+
+    #[test]
+    fn connected_and_synced() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let a: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        let b: SocketAddr = "127.0.0.2:28080".parse().unwrap();
+
+        assert!(!p2p.is_connected());
+        assert!(!p2p.is_synced(100));
+
+        ConnectionHandler::new(p2p.context.clone()).on_connect(a, Commands::new());
+        ConnectionHandler::new(p2p.context.clone()).on_connect(b, Commands::new());
+        // connections without handshake don't count.
+        assert!(!p2p.is_connected());
+
+        p2p.context.note_peer_height(&a, 100);
+        p2p.context.note_peer_height(&b, 100 + SYNCED_HEIGHT_TOLERANCE + 1);
+        assert!(p2p.is_connected());
+        assert!(!p2p.is_synced(100));
+        assert!(p2p.is_synced(101));
+
+        Context::close(p2p.context.clone(), &b);
+        assert!(p2p.is_synced(100));
+
+        p2p.shutdown();
+        assert!(!p2p.is_connected());
+    }
+
+    struct ThreadRecorder(Mutex<mpsc::Sender<thread::ThreadId>>);
+
+    impl InboundSyncConnection for ThreadRecorder {
+        fn on_new_block(&self, _arg: &NewBlock) {}
+        fn on_new_fluffy_block(&self, _arg: &NewFluffyBlock) {}
+        fn on_new_transactions(&self, _arg: &NewTransactions) {}
+        fn on_request_chain(&self, _arg: &RequestChain) {
+            self.0.lock().send(thread::current().id()).unwrap();
+        }
+        fn on_request_fluffy_missing_tx(&self, _arg: &RequestFluffyMissingTx) {}
+        fn on_request_get_objects(&self, _arg: &RequestGetObjects) {}
+        fn on_response_chain_entry(&self, _arg: &ResponseChainEntry) {}
+        fn on_response_get_objects(&self, _arg: &ResponseGetObjects) {}
+        fn on_support_flags(&self, _arg: u32) {}
+    }
+
+    #[test]
+    fn notifications_are_decoded_on_the_pool() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        p2p.context
+            .inbound_sync_connections
+            .write()
+            .insert(addr, Arc::new(ThreadRecorder(Mutex::new(sender))));
+
+        let request = RequestChain { block_ids: LinkedList::new().into() };
+        Context::spawn_sync_notification(p2p.context.clone(),
+                                         addr,
+                                         to_section(&request).unwrap(),
+                                         |c, req: &RequestChain| c.on_request_chain(req));
+
+        let handled_on = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_ne!(handled_on, thread::current().id());
+    }
+
+    #[test]
+    fn invoke_timeout_closes_connection() {
+        let mut core = Core::new().unwrap();
+        let config = Config { invoke_timeout: Duration::from_millis(10), ..test_config() };
+        let p2p = test_p2p(&core, config);
+
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        let commands = Commands::new();
+        p2p.context
+            .command_streams
+            .write()
+            .insert(addr, commands.clone());
+        p2p.context
+            .connection_counter
+            .note_new_outbound_connection(addr);
+
+        // nobody is listening on the other side, so the ping never gets
+        // a response.
+        let result = Arc::new(Mutex::new(None));
+        Context::invoke::<Ping, _>(p2p.context.clone(), &addr, &commands, Section::new(), {
+            let result = result.clone();
+            move |response: Result<Section, InvokeError>| {
+                *result.lock() = Some(response.map(|_| ()));
+            }
+        });
+
+        let wait = Timeout::new(Duration::from_millis(100), &core.handle()).unwrap();
+        core.run(wait).unwrap();
+
+        match *result.lock() {
+            Some(Err(InvokeError::Timeout(id))) => assert_eq!(id, Ping::ID),
+            ref other => panic!("unexpected invoke result: {:?}", other),
+        }
+        assert!(p2p.context.command_streams.read().get(&addr).is_none());
+        assert_eq!(p2p.context.connection_counter.outbound_connections().0, 0);
+    }
+
+    #[test]
+    fn invalid_handshake_response_closes_connection() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        p2p.context
+            .command_streams
+            .write()
+            .insert(addr, Commands::new());
+
+        // an empty section lacks the node and sync data.
+        Context::on_handshake_response(&p2p.context, &addr, Ok(Section::new()));
+
+        assert!(p2p.context.command_streams.read().get(&addr).is_none());
+        assert_eq!(p2p.context.backoff.lock().failures(&addr), 1);
+        assert!(p2p.context.inbound_sync_connections.read().is_empty());
+    }
+
+    #[test]
+    fn priority_peers_are_redialed() {
+        let mut core = Core::new().unwrap();
+
+        // the connections are accepted by the kernel, so they stay open
+        // until the handshake times out.
+        let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let config = Config {
+            priority_peers: vec![addr],
+            priority_redial_interval: Duration::from_millis(50),
+            ..test_config()
+        };
+        let p2p = test_p2p(&core, config);
+        let io_handler = Context::io_handler(p2p.context.clone());
+
+        Context::keep_priority_peers(p2p.context.clone(), io_handler);
+        core.turn(Some(Duration::from_millis(10)));
+        assert!(p2p.context.command_streams.read().contains_key(&addr));
+        // dialed on top of the dial slots.
+        assert_eq!(p2p.context.dials_in_flight.load(Ordering::Acquire), 0);
+
+        Context::close(p2p.context.clone(), &addr);
+        assert!(!p2p.context.command_streams.read().contains_key(&addr));
+
+        let wait = Timeout::new(Duration::from_millis(100), &core.handle()).unwrap();
+        core.run(wait).unwrap();
+        assert!(p2p.context.command_streams.read().contains_key(&addr));
+        assert_eq!(p2p.context.dials_in_flight.load(Ordering::Acquire), 0);
+
+        p2p.shutdown();
+    }
+
+    #[test]
+    fn ping_delivers_peer_id() {
+        let mut core = Core::new().unwrap();
+        let server = test_p2p(&core, Config { peer_id: PeerId::from(0x9999), ..test_config() });
+        let client = test_p2p(&core, test_config());
+
+        let addr = spawn_test_server(&core, &server);
+        let client_io_handler = Context::io_handler(client.context.clone());
+        let result = Arc::new(Mutex::new(None));
+        core.remote().spawn({
+            let context = client.context.clone();
+            let result = result.clone();
+            move |handle| {
+                let outbound = Context::open_outbound(context.clone(),
+                                                      addr,
+                                                      handle,
+                                                      client_io_handler,
+                                                      Commands::new());
+
+                Context::ping(context, &addr, move |response| {
+                    *result.lock() = Some(response.map(|response| response.peer_id));
+                });
+
+                outbound
+            }
+        });
+
+        run_until(&mut core, || result.lock().is_some());
+
+        match *result.lock() {
+            Some(Ok(peer_id)) => assert_eq!(peer_id, PeerId::from(0x9999)),
+            ref other => panic!("unexpected ping result: {:?}", other),
+        }
+
+        client.shutdown();
+        server.shutdown();
+    }
+
+    #[test]
+    fn connection_reset_fails_pending_invokes() {
+        let mut core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+
+        let connection_handler = ConnectionHandler::new(p2p.context.clone());
+        let commands = Commands::new();
+        connection_handler.on_connect(addr, commands.clone());
+        let references = Arc::strong_count(&p2p.context);
+
+        let result = Arc::new(Mutex::new(Vec::new()));
+        Context::invoke::<Ping, _>(p2p.context.clone(), &addr, &commands, Section::new(), {
+            let result = result.clone();
+            move |response: Result<Section, InvokeError>| {
+                result.lock().push(response.map(|_| ()));
+            }
+        });
+
+        // what the levin server reports when the peer resets the connection.
+        connection_handler.on_disconnect(addr);
+
+        assert!(p2p.context.command_streams.read().get(&addr).is_none());
+        assert_eq!(p2p.context.connection_counter.inbound_connections().0, 0);
+        assert!(p2p.context.pending_invokes.lock().is_empty());
+        let result = result.lock();
+        assert_eq!(result.len(), 1);
+        match result[0] {
+            Err(InvokeError::ConnectionClosed(id)) => assert_eq!(id, Ping::ID),
+            ref other => panic!("unexpected invoke result: {:?}", other),
+        }
+
+        // the timer is dropped instead of holding the context until the
+        // invoke timeout.
+        drop(commands);
+        run_until(&mut core, || Arc::strong_count(&p2p.context) == references);
+    }
+
+    #[test]
+    fn closed_inbound_socket_is_removed() {
+        let mut core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let (sink, events) = sync_channel(16);
+        p2p.set_event_sink(sink);
+        let addr = spawn_test_server(&core, &p2p);
+
+        let stream = ::std::net::TcpStream::connect(addr).unwrap();
+        let local = stream.local_addr().unwrap();
+        run_until(&mut core, || events.try_recv().ok() == Some(P2pEvent::Connected(local)));
+        assert_eq!(p2p.context.connection_counter.inbound_connections().0, 1);
+
+        drop(stream);
+        run_until(&mut core, || events.try_recv().ok() == Some(P2pEvent::Disconnected(local)));
+        assert_eq!(p2p.context.connection_counter.inbound_connections().0, 0);
+        assert!(p2p.context.command_streams.read().is_empty());
+
+        p2p.shutdown();
+    }
+
+    #[test]
+    fn ping_without_response_times_out() {
+        let mut core = Core::new().unwrap();
+        let config = Config { invoke_timeout: Duration::from_millis(10), ..test_config() };
+        let p2p = test_p2p(&core, config);
+
+        let result = Arc::new(Mutex::new(Vec::new()));
+        let on_response = {
+            let result = result.clone();
+            move |response: Result<PingResponse, InvokeError>| {
+                result.lock().push(response.map(|_| ()));
+            }
+        };
+
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        Context::ping(p2p.context.clone(), &addr, on_response.clone());
+
+        p2p.context
+            .command_streams
+            .write()
+            .insert(addr, Commands::new());
+        Context::ping(p2p.context.clone(), &addr, on_response);
+
+        let wait = Timeout::new(Duration::from_millis(100), &core.handle()).unwrap();
+        core.run(wait).unwrap();
+
+        match result.lock().as_slice() {
+            &[Err(InvokeError::NotConnected), Err(InvokeError::Timeout(id))] => {
+                assert_eq!(id, Ping::ID)
+            }
+            other => panic!("unexpected ping results: {:?}", other),
+        }
+        assert!(p2p.context.command_streams.read().get(&addr).is_none());
+    }
+
+    #[test]
+    fn handshake_checks_network_and_peer_id() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let context = p2p.context.clone();
+
+        let mut node_data = Context::basic_node_data(context.clone());
+        node_data.peer_id = PeerId::from(0x4321);
+        assert!(Context::check_node_data(&context, &node_data).is_ok());
+
+        let mut wrong_network = node_data.clone();
+        wrong_network.network_id = Network::Mainnet.id().into();
+        assert_eq!(Context::check_node_data(&context, &wrong_network),
+                   Err(HandshakeError::OtherNetwork(Network::Mainnet)));
+
+        let unknown_id = Uuid::from_bytes(&[0x42; 16]).unwrap();
+        wrong_network.network_id = unknown_id.into();
+        assert_eq!(Context::check_node_data(&context, &wrong_network),
+                   Err(HandshakeError::WrongNetwork(unknown_id)));
+
+        let mut same_peer_id = node_data.clone();
+        same_peer_id.peer_id = context.config.peer_id;
+        assert_eq!(Context::check_node_data(&context, &same_peer_id),
+                   Err(HandshakeError::SamePeerId));
+
+        let mut skewed = node_data.clone();
+        skewed.local_time += 60;
+        assert!(Context::check_node_data(&context, &skewed).is_ok());
+        skewed.local_time += 60 * 60;
+        match Context::check_node_data(&context, &skewed) {
+            Err(HandshakeError::ClockSkew(skew)) => assert!(skew.ahead && skew.seconds > 60 * 60),
+            other => panic!("expected a clock skew error, got {:?}", other),
+        }
+
+        // times that don't fit in an i64 are skewed rather than wrapped.
+        for &local_time in [u64::max_value(), 1 << 63].iter() {
+            skewed.local_time = local_time;
+            match Context::check_node_data(&context, &skewed) {
+                Err(HandshakeError::ClockSkew(skew)) => assert!(skew.ahead),
+                other => panic!("expected a clock skew error, got {:?}", other),
+            }
+        }
+
+        // inbound handshakes go through the same checks.
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        for node_data in [wrong_network.clone(), same_peer_id.clone()].iter() {
+            ConnectionHandler::new(context.clone()).on_connect(addr, Commands::new());
+            let request = HandshakeRequest {
+                node_data: node_data.clone(),
+                payload_data: Context::core_sync_data(context.clone()),
+            };
+
+            assert!(Context::on_handshake(context.clone(), addr, request).is_none());
+            assert!(context.command_streams.read().get(&addr).is_none());
+        }
+
+        // and so do the responses to outbound ones.
+        for node_data in [wrong_network, same_peer_id].iter() {
+            context.command_streams.write().insert(addr, Commands::new());
+            let response = HandshakeResponse {
+                node_data: node_data.clone(),
+                payload_data: Context::core_sync_data(context.clone()),
+                local_peerlist: context.peerlist.read().stl_peerlist(),
+            };
+
+            Context::on_handshake_response(&context, &addr, Ok(to_section(&response).unwrap()));
+            assert!(context.command_streams.read().get(&addr).is_none());
+            assert!(context.inbound_sync_connections.read().is_empty());
+        }
+        assert_eq!(context.backoff.lock().failures(&addr), 2);
+    }
+
+    #[test]
+    fn obsolete_top_version_is_dropped() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let context = p2p.context.clone();
+
+        let mut node_data = Context::basic_node_data(context.clone());
+        node_data.peer_id = PeerId::from(0x4321);
+
+        // testnet forked to v7 at 1057028.
+        let mut current = Context::core_sync_data(context.clone());
+        current.current_height = 1100000;
+        current.top_version = 7;
+        let mut obsolete = current.clone();
+        obsolete.top_version = 6;
+        assert!(Context::check_sync_data(&context, &current).is_ok());
+        assert_eq!(Context::check_sync_data(&context, &obsolete),
+                   Err(HandshakeError::ObsoleteVersion(6, 7)));
+
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        ConnectionHandler::new(context.clone()).on_connect(addr, Commands::new());
+        let request = HandshakeRequest {
+            node_data: node_data.clone(),
+            payload_data: obsolete.clone(),
+        };
+        assert!(Context::on_handshake(context.clone(), addr, request).is_none());
+        assert!(context.command_streams.read().get(&addr).is_none());
+
+        ConnectionHandler::new(context.clone()).on_connect(addr, Commands::new());
+        let request = HandshakeRequest {
+            node_data,
+            payload_data: current.clone(),
+        };
+        assert!(Context::on_handshake(context.clone(), addr, request).is_some());
+
+        let request = TimedSyncRequest { payload_data: current };
+        assert!(Context::on_timed_sync(context.clone(), addr, request).is_some());
+        assert!(context.command_streams.read().get(&addr).is_some());
+
+        let request = TimedSyncRequest { payload_data: obsolete };
+        assert!(Context::on_timed_sync(context.clone(), addr, request).is_none());
+        assert!(context.command_streams.read().get(&addr).is_none());
+    }
+
+    #[test]
+    fn hidden_port_is_neither_advertised_nor_bound() {
+        let core = Core::new().unwrap();
+        let config = Config {
+            hide_my_port: true,
+            peers: vec!["127.0.0.1:1".parse().unwrap()],
+            ..test_config()
+        };
+        let p2p = test_p2p(&core, config);
+
+        p2p.run().unwrap();
+        Context::spawn_server(p2p.context.clone(), IoHandler::new().to_ref());
+
+        assert!(!p2p.is_listening());
+        assert_eq!(Context::basic_node_data(p2p.context.clone()).my_port, 0);
+    }
+
+    #[test]
+    fn visible_port_is_advertised() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, Config { listen_port: Some(38080), ..test_config() });
+
+        assert_eq!(Context::basic_node_data(p2p.context.clone()).my_port, 38080);
+    }
+
+    #[test]
+    fn node_data_hides_port() {
+        let peer_id = PeerId::from(0x1234);
+
+        let node_data = Context::node_data_for(Network::Testnet, peer_id, true, 28080);
+        assert_eq!(node_data.my_port, 0);
+        assert_eq!(node_data.peer_id, peer_id);
+        assert_eq!(node_data.network_id, Network::Testnet.id().into());
+
+        let node_data = Context::node_data_for(Network::Testnet, peer_id, false, 28080);
+        assert_eq!(node_data.my_port, 28080);
+    }
+
+    #[test]
+    fn sync_data_reflects_best_block() {
+        let best_block = BestBlock {
+            height: 1234,
+            id: H256::from([0x42; 32]),
+        };
+
+        let sync_data = Context::sync_data_for(Network::Mainnet, best_block);
+        assert_eq!(sync_data.current_height, 1234);
+        assert_eq!(sync_data.top_id, H256::from([0x42; 32]));
+        assert_eq!(sync_data.top_version,
+                   Network::Mainnet.hard_forks().ideal_version());
+    }
+
+    #[test]
+    fn handshake_peerlist_fills_gray_peerlist() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let context = p2p.context.clone();
+
+        let entry = |addr: &str, id: u64| {
+            let addr: SocketAddrV4 = addr.parse().unwrap();
+            PeerlistEntry {
+                adr: addr.into(),
+                id: PeerId::from(id),
+                last_seen: 0,
+            }
+        };
+
+        let peerlist: LinkedList<PeerlistEntry> = vec![entry("1.1.1.1:18080", 1),
+                                                       entry("2.2.2.2:18080", 2),
+                                                       entry("3.3.3.3:18080", 3),
+                                                       entry("4.4.4.4:18080", 4),
+                                                       entry("5.5.5.5:18080", 5),
+                                                       entry("127.0.0.1:18080", 6)]
+                .into_iter()
+                .collect();
+
+        let addr: SocketAddr = "8.8.8.8:18080".parse().unwrap();
+        let added = Context::on_peerlist(&context, &addr, &peerlist.clone().into());
+        assert_eq!(added, 5);
+        assert_eq!(context.gray_peerlist.read().len(), 5);
+        assert!(!context
+                     .gray_peerlist
+                     .read()
+                     .contains(&"127.0.0.1:18080".parse().unwrap()));
+
+        // the same peers aren't added twice.
+        assert_eq!(Context::on_peerlist(&context, &addr, &peerlist.into()), 0);
+
+        // oversized peerlists are truncated to the cap.
+        let oversized: LinkedList<PeerlistEntry> = (0..MAX_PEERS_IN_HANDSHAKE as u64 + 10)
+            .map(|i| entry(&format!("9.9.{}.{}:18080", i / 256, i % 256), i))
+            .collect();
+        assert_eq!(Context::on_peerlist(&context, &addr, &oversized.into()),
+                   MAX_PEERS_IN_HANDSHAKE);
+        assert_eq!(context.gray_peerlist.read().len(), 5 + MAX_PEERS_IN_HANDSHAKE);
+        assert!(!context
+                     .gray_peerlist
+                     .read()
+                     .contains(&"9.9.1.0:18080".parse().unwrap()));
+    }
+
+    #[test]
+    fn io_handler_uses_max_packet_size() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, Config { max_packet_size: 4096, ..test_config() });
+
+        assert_eq!(Context::io_handler(p2p.context.clone()).max_packet_size(), 4096);
+    }
+
+    #[test]
+    fn connection_events() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+        let context = p2p.context.clone();
+
+        let (sink, events) = sync_channel(16);
+        p2p.set_event_sink(sink);
+
+        let addr: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        ConnectionHandler::new(context.clone()).on_connect(addr, Commands::new());
+
+        let mut node_data = Context::basic_node_data(context.clone());
+        node_data.peer_id = PeerId::from(0x4321);
+        let request = HandshakeRequest {
+            node_data,
+            payload_data: Context::core_sync_data(context.clone()),
+        };
+        assert!(Context::on_handshake(context.clone(), addr, request).is_some());
+
+        Context::close(context.clone(), &addr);
+        // closing twice emits a single event.
+        Context::close(context.clone(), &addr);
+
+        assert_eq!(events.try_iter().collect::<Vec<_>>(),
+                   vec![P2pEvent::Connected(addr),
+                        P2pEvent::HandshakeComplete(addr, PeerId::from(0x4321)),
+                        P2pEvent::Disconnected(addr)]);
+    }
+
+    #[test]
+    fn full_event_sink_does_not_block() {
+        let core = Core::new().unwrap();
+        let p2p = test_p2p(&core, test_config());
+
+        let (sink, events) = sync_channel(1);
+        p2p.set_event_sink(sink);
+
+        let a: SocketAddr = "127.0.0.1:28080".parse().unwrap();
+        let b: SocketAddr = "127.0.0.2:28080".parse().unwrap();
+        ConnectionHandler::new(p2p.context.clone()).on_connect(a, Commands::new());
+        ConnectionHandler::new(p2p.context.clone()).on_connect(b, Commands::new());
+
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), vec![P2pEvent::Connected(a)]);
     }
 }
@@ -1,6 +1,6 @@
 //! CryptoNote types.
 
-pub const CN_COMMAND_BASE_ID: u32 = 2000;
+pub use network::BC_COMMANDS_POOL_BASE as CN_COMMAND_BASE_ID;
 
 pub mod cmd;
 
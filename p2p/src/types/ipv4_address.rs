@@ -44,6 +44,12 @@ impl<'a> From<&'a net::SocketAddrV4> for Ipv4Address {
     }
 }
 
+impl<'a> From<&'a Ipv4Address> for net::SocketAddrV4 {
+    fn from(addr: &'a Ipv4Address) -> net::SocketAddrV4 {
+        net::SocketAddrV4::new(addr.ip.into(), addr.port)
+    }
+}
+
 impl StlElement for Ipv4Address {
     const LENGTH: usize = 4 + 2;
 
@@ -14,9 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use levin::COMMAND_BASE_ID;
-
-pub const P2P_COMMAND_BASE_ID: u32 = COMMAND_BASE_ID;
+pub use network::P2P_COMMANDS_POOL_BASE as P2P_COMMAND_BASE_ID;
 
 pub mod cmd;
 pub mod cn;
@@ -30,3 +28,18 @@ pub use self::basic_node_data::BasicNodeData;
 pub use self::ipv4_address::Ipv4Address;
 pub use self::peerid::PeerId;
 pub use self::peerlist_entry::PeerlistEntry;
+
+#[cfg(test)]
+pub mod tests {
+    use levin::COMMAND_BASE_ID;
+    use levin::bucket::LEVIN_PROTOCOL_VER_1;
+    use network::LEVIN_PROTOCOL_VERSION;
+
+    use super::*;
+
+    #[test]
+    fn levin_agrees_with_the_network_constants() {
+        assert_eq!(P2P_COMMAND_BASE_ID, COMMAND_BASE_ID);
+        assert_eq!(LEVIN_PROTOCOL_VERSION, LEVIN_PROTOCOL_VER_1);
+    }
+}
@@ -0,0 +1,114 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Delay in seconds before redialing an address that failed once.
+pub const BACKOFF_BASE: u64 = 5;
+
+/// Maximum delay in seconds before redialing a failing address.
+pub const BACKOFF_MAX: u64 = 10 * 60;
+
+/// Tracks the consecutive connection failures of each address to back off
+/// exponentially before dialing it again.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    failures: HashMap<SocketAddr, (u32, Instant)>,
+}
+
+impl Backoff {
+    pub fn new() -> Backoff {
+        Backoff::default()
+    }
+
+    /// Notes a failed connection to `addr` at `now`.
+    pub fn note_failure(&mut self, addr: SocketAddr, now: Instant) {
+        let failures = self.failures
+            .get(&addr)
+            .map(|&(failures, _)| failures)
+            .unwrap_or(0);
+        self.failures.insert(addr, (failures.saturating_add(1), now));
+    }
+
+    /// Notes a successful handshake with `addr`, resetting its failures.
+    pub fn note_success(&mut self, addr: &SocketAddr) {
+        self.failures.remove(addr);
+    }
+
+    /// Number of consecutive failures of `addr`.
+    pub fn failures(&self, addr: &SocketAddr) -> u32 {
+        self.failures
+            .get(addr)
+            .map(|&(failures, _)| failures)
+            .unwrap_or(0)
+    }
+
+    /// The time `addr` can be dialed again, `None` if it can be dialed
+    /// right away.
+    pub fn next_dial(&self, addr: &SocketAddr) -> Option<Instant> {
+        self.failures
+            .get(addr)
+            .map(|&(failures, last_failure)| last_failure + Backoff::delay(failures))
+    }
+
+    /// Time to wait before redialing an address after `failures`
+    /// consecutive failures.
+    pub fn delay(failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::from_secs(0);
+        }
+
+        let exponent = cmp::min(failures - 1, 16);
+        let secs = BACKOFF_BASE.saturating_mul(1 << exponent);
+        Duration::from_secs(cmp::min(secs, BACKOFF_MAX))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let addr: SocketAddr = "1.2.3.4:18080".parse().unwrap();
+        let mut backoff = Backoff::new();
+        let now = Instant::now();
+
+        assert!(backoff.next_dial(&addr).is_none());
+
+        backoff.note_failure(addr, now);
+        backoff.note_failure(addr, now);
+        backoff.note_failure(addr, now);
+
+        assert_eq!(backoff.failures(&addr), 3);
+        assert_eq!(backoff.next_dial(&addr),
+                   Some(now + Duration::from_secs(BACKOFF_BASE * 4)));
+
+        backoff.note_success(&addr);
+        assert_eq!(backoff.failures(&addr), 0);
+        assert!(backoff.next_dial(&addr).is_none());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(Backoff::delay(0), Duration::from_secs(0));
+        assert_eq!(Backoff::delay(1), Duration::from_secs(BACKOFF_BASE));
+        assert_eq!(Backoff::delay(100), Duration::from_secs(BACKOFF_MAX));
+    }
+}
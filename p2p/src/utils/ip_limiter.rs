@@ -0,0 +1,195 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Window in seconds of the per IP handshake rate limit.
+pub const HANDSHAKE_RATE_WINDOW: u64 = 60;
+
+/// Limits the inbound connections and handshakes coming from a single IP
+/// address.
+#[derive(Debug)]
+pub struct IpLimiter {
+    max_connections: u32,
+    max_handshakes: u32,
+    connections: HashMap<IpAddr, u32>,
+    handshakes: HashMap<IpAddr, VecDeque<Instant>>,
+    last_prune: Option<Instant>,
+}
+
+impl IpLimiter {
+    /// Allows `max_connections` open connections per IP, and
+    /// `max_handshakes` handshakes per IP every `HANDSHAKE_RATE_WINDOW`
+    /// seconds.
+    pub fn new(max_connections: u32, max_handshakes: u32) -> IpLimiter {
+        IpLimiter {
+            max_connections,
+            max_handshakes,
+            connections: HashMap::new(),
+            handshakes: HashMap::new(),
+            last_prune: None,
+        }
+    }
+
+    /// Notes a new connection from `ip`, returns false without noting it
+    /// if `ip` already has the maximum of connections.
+    pub fn try_connect(&mut self, ip: IpAddr) -> bool {
+        if self.connections(&ip) >= self.max_connections {
+            return false;
+        }
+
+        *self.connections.entry(ip).or_insert(0) += 1;
+        true
+    }
+
+    /// Notes that a connection from `ip` was closed.
+    pub fn note_disconnect(&mut self, ip: &IpAddr) {
+        let is_last = match self.connections.get_mut(ip) {
+            Some(connections) => {
+                *connections = connections.saturating_sub(1);
+                *connections == 0
+            }
+            None => false,
+        };
+        if is_last {
+            self.connections.remove(ip);
+        }
+    }
+
+    /// Number of open connections from `ip`.
+    pub fn connections(&self, ip: &IpAddr) -> u32 {
+        self.connections.get(ip).cloned().unwrap_or(0)
+    }
+
+    /// Notes a handshake from `ip` at `now`, returns false without noting
+    /// it if `ip` already used its handshakes in the current window.
+    pub fn try_handshake(&mut self, ip: IpAddr, now: Instant) -> bool {
+        self.prune_handshakes(now);
+
+        let window = Duration::from_secs(HANDSHAKE_RATE_WINDOW);
+        let (allowed, is_empty) = {
+            let handshakes = self.handshakes.entry(ip).or_insert_with(VecDeque::new);
+            while handshakes
+                      .front()
+                      .map(|&handshake| now.duration_since(handshake) >= window)
+                      .unwrap_or(false) {
+                handshakes.pop_front();
+            }
+
+            let allowed = handshakes.len() < self.max_handshakes as usize;
+            if allowed {
+                handshakes.push_back(now);
+            }
+            (allowed, handshakes.is_empty())
+        };
+
+        if is_empty {
+            self.handshakes.remove(&ip);
+        }
+        allowed
+    }
+
+    /// Forgets the IPs whose handshakes all left the window, at most once
+    /// per window, so IPs that stopped connecting don't pile up.
+    fn prune_handshakes(&mut self, now: Instant) {
+        let window = Duration::from_secs(HANDSHAKE_RATE_WINDOW);
+        if self.last_prune.map(|last| now.duration_since(last) < window).unwrap_or(false) {
+            return;
+        }
+
+        self.last_prune = Some(now);
+        self.handshakes.retain(|_, handshakes| {
+            handshakes
+                .back()
+                .map(|&handshake| now.duration_since(handshake) < window)
+                .unwrap_or(false)
+        });
+    }
+
+    /// Forgets all the connections and handshakes.
+    pub fn clear(&mut self) {
+        self.connections.clear();
+        self.handshakes.clear();
+        self.last_prune = None;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_are_capped_per_ip() {
+        let a: IpAddr = "1.2.3.4".parse().unwrap();
+        let b: IpAddr = "5.6.7.8".parse().unwrap();
+        let mut limiter = IpLimiter::new(2, 10);
+
+        assert!(limiter.try_connect(a));
+        assert!(limiter.try_connect(a));
+        assert!(!limiter.try_connect(a));
+        assert!(limiter.try_connect(b));
+        assert_eq!(limiter.connections(&a), 2);
+
+        limiter.note_disconnect(&a);
+        assert!(limiter.try_connect(a));
+
+        limiter.note_disconnect(&b);
+        limiter.note_disconnect(&b);
+        assert_eq!(limiter.connections(&b), 0);
+    }
+
+    #[test]
+    fn handshakes_are_rate_limited_per_ip() {
+        let a: IpAddr = "1.2.3.4".parse().unwrap();
+        let b: IpAddr = "5.6.7.8".parse().unwrap();
+        let mut limiter = IpLimiter::new(1, 2);
+        let now = Instant::now();
+
+        assert!(limiter.try_handshake(a, now));
+        assert!(limiter.try_handshake(a, now + Duration::from_secs(1)));
+        assert!(!limiter.try_handshake(a, now + Duration::from_secs(2)));
+        assert!(limiter.try_handshake(b, now + Duration::from_secs(2)));
+
+        let later = now + Duration::from_secs(HANDSHAKE_RATE_WINDOW);
+        assert!(limiter.try_handshake(a, later));
+        assert!(!limiter.try_handshake(a, later));
+    }
+
+    #[test]
+    fn idle_ips_are_forgotten() {
+        let mut limiter = IpLimiter::new(0, 1);
+        let now = Instant::now();
+
+        assert!(!limiter.try_connect("1.2.3.4".parse().unwrap()));
+        assert!(limiter.connections.is_empty());
+
+        for i in 0..100u16 {
+            let ip = IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, i]);
+            assert!(limiter.try_handshake(ip, now));
+        }
+        assert_eq!(limiter.handshakes.len(), 100);
+
+        let later = now + Duration::from_secs(HANDSHAKE_RATE_WINDOW);
+        assert!(limiter.try_handshake("1.2.3.4".parse().unwrap(), later));
+        assert_eq!(limiter.handshakes.len(), 1);
+
+        let mut limiter = IpLimiter::new(1, 0);
+        assert!(!limiter.try_handshake("1.2.3.4".parse().unwrap(), now));
+        assert!(limiter.handshakes.is_empty());
+    }
+}
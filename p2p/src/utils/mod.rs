@@ -14,6 +14,13 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+mod backoff;
+mod ip_limiter;
 mod peerlist;
+mod relayed_txs;
 
-pub use self::peerlist::Peerlist;
+pub use self::backoff::{Backoff, BACKOFF_BASE, BACKOFF_MAX};
+pub use self::ip_limiter::{IpLimiter, HANDSHAKE_RATE_WINDOW};
+pub use self::peerlist::{is_valid_peer_address, Peerlist, MAX_PEERS_IN_HANDSHAKE,
+                         GRAY_PEERLIST_LIMIT};
+pub use self::relayed_txs::{RelayedTxs, RELAYED_TXS_MAX_SIZE};
@@ -15,12 +15,18 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::{HashMap, LinkedList};
-use std::net::SocketAddr;
+use std::net::{SocketAddr, SocketAddrV4};
 
 use portable_storage_utils::stl::StlLinkedList;
 
 use types::PeerlistEntry;
 
+/// Maximum number of peers sent and accepted in a handshake.
+pub const MAX_PEERS_IN_HANDSHAKE: usize = 250;
+
+/// Maximum number of entries of the gray peerlist.
+pub const GRAY_PEERLIST_LIMIT: usize = 5000;
+
 #[derive(Debug)]
 pub struct Peerlist {
     pub list: HashMap<SocketAddr, PeerlistEntry>,
@@ -35,16 +41,82 @@ impl Peerlist {
         self.list.insert(address, entry);
     }
 
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn contains(&self, addr: &SocketAddr) -> bool {
+        self.list.contains_key(addr)
+    }
+
     pub fn remove(&mut self, addr: &SocketAddr) -> Option<PeerlistEntry> {
         self.list.remove(&addr)
     }
 
+    /// The peers sent to other nodes, at most `MAX_PEERS_IN_HANDSHAKE`.
     pub fn stl_peerlist(&self) -> StlLinkedList<PeerlistEntry> {
         let mut ll = LinkedList::new();
-        for peer in self.list.iter() {
+        for peer in self.list.iter().take(MAX_PEERS_IN_HANDSHAKE) {
             ll.push_back(peer.1.clone())
         }
 
         ll.into()
     }
 }
+
+/// Can `addr` be a public peer? Reserved, local and multicast addresses
+/// aren't, nor is the port 0.
+pub fn is_valid_peer_address(addr: &SocketAddrV4) -> bool {
+    let ip = addr.ip();
+    !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() ||
+      ip.is_broadcast() || ip.is_multicast() || ip.is_documentation() ||
+      ip.octets()[0] == 0 || ip.octets()[0] >= 240 || addr.port() == 0)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use types::PeerId;
+
+    use super::*;
+
+    #[test]
+    fn sent_peerlist_is_capped() {
+        let mut peerlist = Peerlist::new();
+        assert!(peerlist.is_empty());
+        for i in 0..MAX_PEERS_IN_HANDSHAKE as u64 + 10 {
+            let addr = SocketAddrV4::new([9, 9, (i / 256) as u8, (i % 256) as u8].into(), 18080);
+            peerlist.insert(SocketAddr::V4(addr),
+                            PeerlistEntry {
+                                adr: addr.into(),
+                                id: PeerId::from(i),
+                                last_seen: 0,
+                            });
+        }
+
+        assert_eq!(peerlist.stl_peerlist().len(), MAX_PEERS_IN_HANDSHAKE);
+    }
+
+    #[test]
+    fn invalid_peer_addresses() {
+        let valid = |addr: &str| is_valid_peer_address(&addr.parse().unwrap());
+
+        assert!(valid("1.2.3.4:18080"));
+        assert!(valid("8.8.8.8:28080"));
+
+        assert!(!valid("1.2.3.4:0"));
+        assert!(!valid("0.0.0.0:18080"));
+        assert!(!valid("0.1.2.3:18080"));
+        assert!(!valid("127.0.0.1:18080"));
+        assert!(!valid("10.0.0.1:18080"));
+        assert!(!valid("192.168.1.1:18080"));
+        assert!(!valid("169.254.0.1:18080"));
+        assert!(!valid("224.0.0.1:18080"));
+        assert!(!valid("240.0.0.1:18080"));
+        assert!(!valid("255.255.255.255:18080"));
+        assert!(!valid("203.0.113.1:18080"));
+    }
+}
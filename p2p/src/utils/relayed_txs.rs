@@ -0,0 +1,115 @@
+// Xmr, Monero node.
+// Copyright (C) 2018  Jean Pierre Dudey
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, VecDeque};
+
+use primitives::H256;
+use portable_storage_utils::Blob;
+
+/// Maximum total size in bytes of the transactions kept by `RelayedTxs`.
+pub const RELAYED_TXS_MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// The transactions most recently relayed by peers, used to answer the
+/// peers missing some of the transactions of a fluffy block. The oldest
+/// are forgotten once they add up to more than `RELAYED_TXS_MAX_SIZE`.
+#[derive(Debug, Default)]
+pub struct RelayedTxs {
+    txs: HashMap<H256, Blob>,
+    order: VecDeque<H256>,
+    size: usize,
+}
+
+impl RelayedTxs {
+    pub fn new() -> RelayedTxs {
+        RelayedTxs::default()
+    }
+
+    /// Remembers the transaction `id` relayed as `tx`.
+    pub fn insert(&mut self, id: H256, tx: Blob) {
+        if self.txs.contains_key(&id) {
+            return;
+        }
+
+        self.size += tx.0.len();
+        self.txs.insert(id.clone(), tx);
+        self.order.push_back(id);
+
+        while self.size > RELAYED_TXS_MAX_SIZE {
+            let oldest = match self.order.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            if let Some(tx) = self.txs.remove(&oldest) {
+                self.size -= tx.0.len();
+            }
+        }
+    }
+
+    pub fn get(&self, id: &H256) -> Option<&Blob> {
+        self.txs.get(id)
+    }
+
+    pub fn contains(&self, id: &H256) -> bool {
+        self.txs.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn relayed_txs_are_kept() {
+        let mut relayed = RelayedTxs::new();
+        assert!(relayed.is_empty());
+
+        relayed.insert(H256::from([0x01; 32]), vec![0x01; 100].into());
+        relayed.insert(H256::from([0x01; 32]), vec![0x02; 100].into());
+        relayed.insert(H256::from([0x02; 32]), vec![0x03; 100].into());
+
+        assert_eq!(relayed.len(), 2);
+        assert_eq!(relayed.get(&H256::from([0x01; 32])), Some(&vec![0x01; 100].into()));
+        assert!(relayed.contains(&H256::from([0x02; 32])));
+        assert!(!relayed.contains(&H256::from([0x03; 32])));
+    }
+
+    #[test]
+    fn oldest_are_forgotten() {
+        let mut relayed = RelayedTxs::new();
+        let half = RELAYED_TXS_MAX_SIZE / 2;
+
+        relayed.insert(H256::from([0x01; 32]), vec![0; half].into());
+        relayed.insert(H256::from([0x02; 32]), vec![0; half].into());
+        assert_eq!(relayed.len(), 2);
+
+        relayed.insert(H256::from([0x03; 32]), vec![0; 1].into());
+        assert!(!relayed.contains(&H256::from([0x01; 32])));
+        assert!(relayed.contains(&H256::from([0x02; 32])));
+        assert!(relayed.contains(&H256::from([0x03; 32])));
+
+        // a transaction bigger than the limit isn't kept either.
+        relayed.insert(H256::from([0x04; 32]), vec![0; RELAYED_TXS_MAX_SIZE + 1].into());
+        assert!(relayed.is_empty());
+    }
+}
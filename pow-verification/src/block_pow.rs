@@ -0,0 +1,120 @@
+use bytes::Bytes;
+use chain::Block;
+
+use crate::pow_verification::proof_of_work_is_valid;
+
+/// The proof of work algorithms used by Monero over its hard forks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowAlgorithm {
+    /// The original CryptoNight, up to major version 6.
+    CryptoNightV0,
+    /// CryptoNight variant 1, major version 7.
+    CryptoNightV1,
+    /// CryptoNight variant 2, major versions 8 and 9.
+    CryptoNightV2,
+    /// CryptoNight-R (variant 4), major versions 10 and 11.
+    CryptoNightR,
+    /// RandomX, from major version 12 on.
+    RandomX,
+}
+
+/// The proof of work algorithm isn't implemented yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedAlgorithm(pub PowAlgorithm);
+
+impl std::fmt::Display for UnsupportedAlgorithm {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "unsupported proof of work algorithm {:?}", self.0)
+    }
+}
+
+/// The proof of work algorithm of blocks with `major_version`.
+pub fn algorithm_for_version(major_version: u8) -> PowAlgorithm {
+    match major_version {
+        0..=6 => PowAlgorithm::CryptoNightV0,
+        7 => PowAlgorithm::CryptoNightV1,
+        8 | 9 => PowAlgorithm::CryptoNightV2,
+        10 | 11 => PowAlgorithm::CryptoNightR,
+        _ => PowAlgorithm::RandomX,
+    }
+}
+
+/// Checks the proof of work of `block` against `difficulty` with the
+/// algorithm of its major version.
+pub fn verify(block: &Block, difficulty: u64) -> Result<bool, UnsupportedAlgorithm> {
+    match algorithm_for_version(block.header.major_version) {
+        PowAlgorithm::CryptoNightV0 => Ok(verify_block_pow(block, difficulty)),
+        algorithm => Err(UnsupportedAlgorithm(algorithm)),
+    }
+}
+
+/// The bytes hashed for the proof of work of `block`.
+pub fn hashing_blob(block: &Block) -> Bytes {
+    block.hashing_blob()
+}
+
+/// Hashes the hashing blob of `block` with CryptoNight and checks it
+/// against `difficulty`.
+///
+/// Only the original CryptoNight variant is supported.
+pub fn verify_block_pow(block: &Block, difficulty: u64) -> bool {
+    proof_of_work_is_valid(block.hash().as_bytes(), difficulty)
+}
+
+#[cfg(test)]
+mod tests {
+    use xmr_format::{from_binary, Serialize};
+
+    use super::*;
+
+    const MAINNET_GENESIS_BLOCK_BLOB: &'static [u8] =
+        &[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+          0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+          0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x27, 0x00, 0x00, 0x01, 0x3c, 0x01,
+          0xff, 0x00, 0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x03, 0x02, 0x9b, 0x2e, 0x4c,
+          0x02, 0x81, 0xc0, 0xb0, 0x2e, 0x7c, 0x53, 0x29, 0x1a, 0x94, 0xd1, 0xd0, 0xcb, 0xff,
+          0x88, 0x83, 0xf8, 0x02, 0x4f, 0x51, 0x42, 0xee, 0x49, 0x4f, 0xfb, 0xbd, 0x08, 0x80,
+          0x71, 0x21, 0x01, 0x77, 0x67, 0xaa, 0xfc, 0xde, 0x9b, 0xe0, 0x0d, 0xcf, 0xd0, 0x98,
+          0x71, 0x5e, 0xbc, 0xf7, 0xf4, 0x10, 0xda, 0xeb, 0xc5, 0x82, 0xfd, 0xa6, 0x9d, 0x24,
+          0xa2, 0x8e, 0x9d, 0x0b, 0xc8, 0x90, 0xd1, 0x00];
+
+    #[test]
+    fn mainnet_genesis_block_pow() {
+        let block: Block = from_binary(MAINNET_GENESIS_BLOCK_BLOB).unwrap();
+
+        let blob = hashing_blob(&block);
+        // header, tree hash of the miner transaction and the transaction count.
+        assert_eq!(blob.len(), block.header.len() + 32 + 1);
+        assert_eq!(&blob[..block.header.len()], &MAINNET_GENESIS_BLOCK_BLOB[..block.header.len()]);
+        assert_eq!(blob[blob.len() - 1], 1);
+
+        // https://xmrchain.net/block/0
+        assert_eq!(hex::encode(block.hash().as_bytes()),
+                   "8a7b1a780e99eec31a9425b7d89c283421b2042a337d5700dfd4a7d6eb7bd774");
+
+        // the genesis block was mined with difficulty 1, its little endian
+        // pow hash starts with 0x74 from the top so it's good up to 2.
+        assert!(verify_block_pow(&block, 1));
+        assert!(verify_block_pow(&block, 2));
+        assert!(!verify_block_pow(&block, 3));
+    }
+
+    #[test]
+    fn algorithm_timeline() {
+        assert_eq!(algorithm_for_version(1), PowAlgorithm::CryptoNightV0);
+        assert_eq!(algorithm_for_version(6), PowAlgorithm::CryptoNightV0);
+        assert_eq!(algorithm_for_version(7), PowAlgorithm::CryptoNightV1);
+        assert_eq!(algorithm_for_version(8), PowAlgorithm::CryptoNightV2);
+        assert_eq!(algorithm_for_version(10), PowAlgorithm::CryptoNightR);
+        assert_eq!(algorithm_for_version(12), PowAlgorithm::RandomX);
+    }
+
+    #[test]
+    fn verify_dispatches_on_major_version() {
+        let mut block: Block = from_binary(MAINNET_GENESIS_BLOCK_BLOB).unwrap();
+        assert_eq!(verify(&block, 1), Ok(true));
+
+        block.header.major_version = 7;
+        assert_eq!(verify(&block, 1), Err(UnsupportedAlgorithm(PowAlgorithm::CryptoNightV1)));
+    }
+}
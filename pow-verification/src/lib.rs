@@ -11,7 +11,19 @@
 #[macro_use]
 extern crate lazy_static;
 extern crate uint;
+extern crate bytes;
 
+extern crate xmr_chain as chain;
+
+mod block_pow;
 mod pow_verification;
 
-pub use crate::pow_verification::{proof_of_work_is_valid};
+pub use crate::block_pow::{PowAlgorithm, UnsupportedAlgorithm, algorithm_for_version, hashing_blob,
+                           verify, verify_block_pow};
+
+pub use crate::pow_verification::{PowHash, proof_of_work_is_valid, proof_of_work_is_valid_be, proof_of_work_is_valid_ct,
+                                  proof_of_work_is_valid_u256,
+                                  proof_of_work_is_valid_typed, pow_times_difficulty, effective_difficulty,
+                                  estimated_hashrate, pow_target};
+
+pub use uint::{U256, U512};
@@ -6,12 +6,133 @@ lazy_static! {
     //                                              ^^^^^^^^^^ 2^256 - 1 ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
 }
 
+/// A 256-bit proof of work hash, stored in little-endian order, the order
+/// the hash function outputs it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PowHash(pub [u8; 32]);
+
+impl PowHash {
+    /// Creates a `PowHash` from the hash function output.
+    pub fn from_le_bytes(bytes: [u8; 32]) -> PowHash {
+        PowHash(bytes)
+    }
+
+    /// Creates a `PowHash` from big-endian (display order) bytes.
+    pub fn from_be_bytes(mut bytes: [u8; 32]) -> PowHash {
+        bytes.reverse();
+        PowHash(bytes)
+    }
+
+    /// Parses 64 hex digits of the little-endian bytes, as printed by block
+    /// explorers. Returns `None` on any other input.
+    pub fn from_hex(hex: &str) -> Option<PowHash> {
+        let hex = hex.as_bytes();
+        if hex.len() != 64 {
+            return None;
+        }
+
+        let mut bytes = [0u8; 32];
+        for (byte, digits) in bytes.iter_mut().zip(hex.chunks(2)) {
+            let high = (digits[0] as char).to_digit(16)?;
+            let low = (digits[1] as char).to_digit(16)?;
+            *byte = (high << 4 | low) as u8;
+        }
+
+        Some(PowHash(bytes))
+    }
+
+    /// The little-endian bytes.
+    pub fn as_le_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+/// Same as `proof_of_work_is_valid`, with the 32-byte length of the pow
+/// enforced by its type.
+pub fn proof_of_work_is_valid_typed(pow: PowHash, difficulty: u64) -> bool {
+    proof_of_work_is_valid(pow.as_le_bytes(), difficulty)
+}
+
 pub fn proof_of_work_is_valid(pow_bytes_le: &[u8], difficulty: u64) -> bool {
+    U256::from_little_endian(pow_bytes_le) <= pow_target(difficulty)
+}
+
+/// Same as `proof_of_work_is_valid`, with a difficulty wider than 64 bits.
+pub fn proof_of_work_is_valid_u256(pow_bytes_le: &[u8], difficulty: U256) -> bool {
     let pow_u256 = U256::from_little_endian(pow_bytes_le);
+    pow_u256.full_mul(difficulty) <= *U256_MAX
+}
+
+/// The largest pow valid for the given difficulty, floor((2^256-1) / difficulty) as defined
+/// in CNS010. Any pow is valid for a difficulty of zero.
+pub fn pow_target(difficulty: u64) -> U256 {
+    if difficulty == 0 {
+        return U256::max_value();
+    }
+
+    // long division of the 64-bit words, the U256 division is too slow for a check done on
+    // every block.
+    let difficulty = difficulty as u128;
+    let mut target = [0u64; 4];
+    let mut remainder = 0u128;
+    for word in target.iter_mut().rev() {
+        let dividend = remainder << 64 | u64::max_value() as u128;
+        *word = (dividend / difficulty) as u64;
+        remainder = dividend % difficulty;
+    }
+    U256(target)
+}
+
+/// The full 512-bit product of the pow and the difficulty, the pow is valid iff it fits in
+/// 256 bits.
+pub fn pow_times_difficulty(pow_bytes_le: &[u8], difficulty: u64) -> U512 {
+    let pow_u256 = U256::from_little_endian(pow_bytes_le);
+    let difficulty_u256 = U256::from(difficulty);
+    pow_u256.full_mul(difficulty_u256)
+}
+
+/// Same as `proof_of_work_is_valid`, but takes the pow in big-endian (display) order.
+pub fn proof_of_work_is_valid_be(pow_bytes_be: &[u8], difficulty: u64) -> bool {
+    let pow_u256 = U256::from_big_endian(pow_bytes_be);
     let difficulty_u256 = U256::from(difficulty);
     pow_u256.full_mul(difficulty_u256) <= *U256_MAX
 }
 
+/// Same as `proof_of_work_is_valid`, but the final comparison doesn't branch on its inputs.
+/// The product is within 256 bits iff its upper 32 bytes are all zero.
+pub fn proof_of_work_is_valid_ct(pow_bytes_le: &[u8], difficulty: u64) -> bool {
+    let pow_u256 = U256::from_little_endian(pow_bytes_le);
+    let difficulty_u256 = U256::from(difficulty);
+    let mut product_le: [u8; 64] = [0; 64];
+    pow_u256.full_mul(difficulty_u256).to_little_endian(&mut product_le);
+
+    let high_bits = product_le[32..].iter().fold(0u8, |acc, byte| acc | byte);
+    // (0 - 1) >> 8 is 0xff only when `high_bits` is zero.
+    let is_zero = ((high_bits as u16).wrapping_sub(1) >> 8) as u8 & 1;
+    is_zero == 1
+}
+
+/// Returns the maximum difficulty the given pow is still valid for, i.e. floor((2^256-1) / pow).
+/// Saturates to u64::MAX when the quotient doesn't fit (including a pow of zero).
+pub fn effective_difficulty(pow_bytes_le: &[u8]) -> u64 {
+    let pow_u512 = U512::from_little_endian(pow_bytes_le);
+    if pow_u512.is_zero() {
+        return u64::max_value();
+    }
+    let difficulty_u512 = *U256_MAX / pow_u512;
+    if difficulty_u512 > U512::from(u64::max_value()) {
+        u64::max_value()
+    } else {
+        difficulty_u512.low_u64()
+    }
+}
+
+/// Estimates the network hashrate in H/s from the difficulty and the target block time in
+/// seconds. Returns 0 for a target block time of zero.
+pub fn estimated_hashrate(difficulty: u64, target_block_time: u64) -> u64 {
+    difficulty.checked_div(target_block_time).unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,6 +197,128 @@ mod tests {
         assert_invalid_pow_hex(pow, difficulty);
     }
 
+    #[test]
+    fn pow_times_difficulty_is_the_full_product() {
+        let pow = u256_to_bytes_le(U256::from(1_000_000_007));
+        assert!(pow_times_difficulty(&pow, 60) == U512::from(60_000_000_420u64));
+        assert!(pow_times_difficulty(&pow, 0).is_zero());
+
+        // the product of a max pow overflows 256 bits.
+        let pow = [0xff; 32];
+        assert!(pow_times_difficulty(&pow, 2) == *U256_MAX + *U256_MAX);
+        assert!(pow_times_difficulty(&pow, 1) <= *U256_MAX);
+    }
+
+    #[test]
+    fn effective_difficulty_of_pow_precisely_at_target() {
+        let difficulty = 51638511039;
+        // target = floor((2^256-1) / difficulty)
+        let target = U256::from_dec_str("2242359179370299570181822279337156699950563511941089607981823668320").expect("to be correct uns int");
+        assert_eq!(effective_difficulty(&u256_to_bytes_le(target)), difficulty);
+        assert_eq!(effective_difficulty(&u256_to_bytes_le(target + 1_u64)), difficulty - 1);
+    }
+
+    #[test]
+    fn effective_difficulty_of_max_pow_is_1() {
+        let pow = hex::decode("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff").expect("to be correct hex");
+        assert_eq!(effective_difficulty(&pow), 1);
+    }
+
+    #[test]
+    fn effective_difficulty_saturates() {
+        assert_eq!(effective_difficulty(&u256_to_bytes_le(U256::from(1))), u64::max_value());
+        assert_eq!(effective_difficulty(&[0; 32]), u64::max_value());
+    }
+
+    #[test]
+    fn difficulty_above_u64() {
+        // 2^64, one above u64::MAX.
+        let difficulty = U256::from(u64::max_value()) + U256::from(1);
+        let mut pow = [0u8; 32];
+
+        // 2^192 - 1 times 2^64 still fits in 256 bits, 2^192 doesn't.
+        for byte in pow[..24].iter_mut() {
+            *byte = 0xff;
+        }
+        assert!(proof_of_work_is_valid_u256(&pow, difficulty));
+
+        let mut pow = [0u8; 32];
+        pow[24] = 0x01;
+        assert!(!proof_of_work_is_valid_u256(&pow, difficulty));
+
+        // the u64 difficulties agree.
+        let pow = hex::decode("baa3060d1725b71cc9018877f488eeff7633ce514f2097c7907d1a1300000000").expect("to be correct hex");
+        assert!(proof_of_work_is_valid_u256(&pow, U256::from(51638511039u64)));
+        assert!(!proof_of_work_is_valid_u256(&[0xff; 32], U256::from(2)));
+    }
+
+    #[test]
+    fn pow_target_of_small_difficulties() {
+        assert!(pow_target(1) == U256::max_value());
+        assert!(pow_target(2) == U256::max_value() / U256::from(2));
+        assert!(pow_target(2) == U256::max_value() >> 1);
+        assert!(pow_target(0) == U256::max_value());
+
+        let target = U256::from_dec_str("2242359179370299570181822279337156699950563511941089607981823668320").expect("to be correct uns int");
+        assert!(pow_target(51638511039) == target);
+    }
+
+    #[test]
+    fn hashrate_from_difficulty() {
+        // 51638511039 hashes expected every 120 seconds.
+        assert_eq!(estimated_hashrate(51638511039, 120), 430320925);
+        assert_eq!(estimated_hashrate(51638511039, 0), 0);
+    }
+
+    #[test]
+    fn big_endian_pow_at_target_is_valid() {
+        let difficulty = 51638511039;
+        let target = U256::from_dec_str("2242359179370299570181822279337156699950563511941089607981823668320").expect("to be correct uns int");
+        let mut pow_bytes_be: [u8; 32] = [0; 32];
+        target.to_big_endian(&mut pow_bytes_be);
+        assert!(proof_of_work_is_valid_be(&pow_bytes_be, difficulty));
+        (target + 1_u64).to_big_endian(&mut pow_bytes_be);
+        assert!(!proof_of_work_is_valid_be(&pow_bytes_be, difficulty));
+    }
+
+    #[test]
+    fn typed_pow_from_hex() {
+        let vectors = [("8a7b1a780e99eec31a9425b7d89c283421b2042a337d5700dfd4a7d6eb7bd774", 1),
+                       ("146f7a7ccafd32eed8f1bfefe73a69d19c655ea1ce005fcb9045fa963a0c0101", 60),
+                       ("baa3060d1725b71cc9018877f488eeff7633ce514f2097c7907d1a1300000000", 51638511039)];
+
+        for &(pow_hex_le, difficulty) in vectors.iter() {
+            let pow = PowHash::from_hex(pow_hex_le).expect("to be correct hex");
+            assert_eq!(&pow.as_le_bytes()[..], &hex::decode(pow_hex_le).unwrap()[..]);
+            assert!(proof_of_work_is_valid_typed(pow, difficulty));
+
+            let mut pow_bytes_be = *pow.as_le_bytes();
+            pow_bytes_be.reverse();
+            assert_eq!(PowHash::from_be_bytes(pow_bytes_be), pow);
+        }
+
+        let pow = PowHash::from_hex("8a085dfc3e5bef71f611d372d8c0040e9a525f08b9b53de9f0804946218e0fb8").unwrap();
+        assert!(!proof_of_work_is_valid_typed(pow, 51638511039));
+    }
+
+    #[test]
+    fn invalid_pow_hex() {
+        assert_eq!(PowHash::from_hex(""), None);
+        assert_eq!(PowHash::from_hex("8a7b"), None);
+        assert_eq!(PowHash::from_hex(&"g".repeat(64)), None);
+        assert_eq!(PowHash::from_hex(&"0".repeat(66)), None);
+        assert_eq!(PowHash::from_hex(&"F".repeat(64)), Some(PowHash::from_le_bytes([0xff; 32])));
+    }
+
+    #[test]
+    fn const_time_check_agrees_with_random_pows() {
+        for _ in 0..1000 {
+            let pow = random_32_bytes();
+            let difficulty = thread_rng().gen::<u64>() >> thread_rng().gen_range(0, 64);
+            assert_eq!(proof_of_work_is_valid_ct(&pow, difficulty), proof_of_work_is_valid(&pow, difficulty));
+        }
+    }
+
     #[test]
     fn bench_10k_pow_checks_under_100ms() {
         let n: u64 = 10_000;
@@ -94,23 +337,39 @@ mod tests {
     fn assert_valid_pow_hex(pow_hex_le: &str, difficulty: u64) {
         let pow_bytes_le = hex::decode(pow_hex_le).expect("to be correct hex");
         assert!(proof_of_work_is_valid(&pow_bytes_le, difficulty));
+        assert!(proof_of_work_is_valid_ct(&pow_bytes_le, difficulty));
+        assert!(proof_of_work_is_valid_be(&reversed(&pow_bytes_le), difficulty));
     }
 
     fn assert_invalid_pow_hex(pow_hex_le: &str, difficulty: u64) {
         let pow_bytes_le = hex::decode(pow_hex_le).expect("to be correct hex");
         assert!(!proof_of_work_is_valid(&pow_bytes_le, difficulty));
+        assert!(!proof_of_work_is_valid_ct(&pow_bytes_le, difficulty));
+        assert!(!proof_of_work_is_valid_be(&reversed(&pow_bytes_le), difficulty));
+    }
+
+    fn reversed(bytes: &[u8]) -> Vec<u8> {
+        bytes.iter().rev().cloned().collect()
     }
 
     fn assert_valid_pow_u256(pow_u256_le: U256, difficulty: u64) {
         let mut pow_bytes_le: [u8; 32] = [0; 32];
         pow_u256_le.to_little_endian(&mut pow_bytes_le);
         assert!(proof_of_work_is_valid(&pow_bytes_le, difficulty));
+        assert!(proof_of_work_is_valid_ct(&pow_bytes_le, difficulty));
     }
 
     fn assert_invalid_pow_u256(pow_u256_le: U256, difficulty: u64) {
         let mut pow_bytes_le: [u8; 32] = [0; 32];
         pow_u256_le.to_little_endian(&mut pow_bytes_le);
         assert!(!proof_of_work_is_valid(&pow_bytes_le, difficulty));
+        assert!(!proof_of_work_is_valid_ct(&pow_bytes_le, difficulty));
+    }
+
+    fn u256_to_bytes_le(u256: U256) -> [u8; 32] {
+        let mut bytes_le: [u8; 32] = [0; 32];
+        u256.to_little_endian(&mut bytes_le);
+        bytes_le
     }
 
     fn random_32_bytes() -> Vec<u8> {
@@ -18,7 +18,7 @@ use format::{Deserialize, DeserializerStream, Error, Serialize, SerializerStream
 pub const H256_LENGTH: usize = 32;
 
 /// A 256-bit hash.
-#[derive(Default, Clone, Eq, PartialEq)]
+#[derive(Default, Clone, Eq, PartialEq, Hash)]
 pub struct H256(pub [u8; H256_LENGTH]);
 
 impl H256 {
@@ -14,17 +14,34 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::{HashSet, VecDeque};
+
+use parking_lot::RwLock;
+
 use primitives::H256;
 
 use types::StorageRef;
 
 pub struct Chain {
     storage: StorageRef,
+    /// Ids of the blocks we know about but we don't have yet.
+    scheduled: RwLock<Scheduled>,
+}
+
+/// The scheduled ids in chain order, and the same ids as a set to look
+/// them up.
+#[derive(Default)]
+struct Scheduled {
+    queue: VecDeque<H256>,
+    ids: HashSet<H256>,
 }
 
 impl Chain {
     pub fn new(storage: StorageRef) -> Chain {
-        Chain { storage }
+        Chain {
+            storage,
+            scheduled: RwLock::new(Scheduled::default()),
+        }
     }
 
     pub fn storage(&self) -> StorageRef {
@@ -38,4 +55,27 @@ impl Chain {
     pub fn height(&self) -> u64 {
         self.storage.height()
     }
+
+    /// Schedules the blocks we don't have to be requested, returns
+    /// how many of them were scheduled.
+    pub fn schedule_blocks<I>(&self, ids: I) -> usize
+        where I: IntoIterator<Item = H256>
+    {
+        let mut scheduled = self.scheduled.write();
+        let before = scheduled.queue.len();
+
+        for id in ids {
+            if !scheduled.ids.contains(&id) && !self.have_block(id.clone()) {
+                scheduled.ids.insert(id.clone());
+                scheduled.queue.push_back(id);
+            }
+        }
+
+        scheduled.queue.len() - before
+    }
+
+    /// The blocks scheduled to be requested, in chain order.
+    pub fn scheduled_blocks(&self) -> Vec<H256> {
+        self.scheduled.read().queue.iter().cloned().collect()
+    }
 }
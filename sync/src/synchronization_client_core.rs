@@ -112,15 +112,16 @@ impl SynchronizationClientCore {
 
         self.contexes.write().insert(peer_index, context);
 
-        if self.chain.have_block(sync_data.top_id) {
-            let our_height = self.chain.height();
+        let our_height = self.chain.height();
+        if self.chain.have_block(sync_data.top_id) || sync_data.current_height <= our_height {
             if our_height == sync_data.current_height {
                 info!("Peer #{} is synchronized with us.", peer_index);
-                Some(SyncState::Synchronized)
-            } else {
-                Some(SyncState::Synchronizing)
             }
+            Some(SyncState::Synchronized)
         } else {
+            info!("Peer #{} is ahead of us ({} blocks), synchronizing.",
+                  peer_index,
+                  sync_data.current_height - our_height);
             Some(SyncState::Synchronizing)
         }
     }
@@ -188,6 +189,9 @@ impl ClientCore for SynchronizationClientCore {
             self.misbehaving(peer_index, reason);
             return;
         }
+
+        let scheduled = self.chain.schedule_blocks(arg.block_ids.0.iter().cloned());
+        debug!("Scheduled {} blocks from peer #{}", scheduled, peer_index);
     }
 
     fn on_response_get_objects(&self, _peer_index: PeerIndex, _arg: &ResponseGetObjects) {
@@ -211,3 +215,184 @@ pub struct Context {
     pub last_response_height: Option<u64>,
     pub support_flags: Option<u32>,
 }
+
+#[cfg(test)]
+pub mod tests {
+    extern crate xmr_chain as chain;
+
+    use std::collections::LinkedList;
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use primitives::H256;
+    use storage::{BestBlock, BlockChain, BlockProvider, BlockRef, CanonStore,
+                  IndexedBlockProvider, Store};
+    use p2p::protocol::OutboundSyncConnection;
+    use p2p::types::cn::CoreSyncData;
+
+    use self::chain::IndexedBlock;
+
+    use synchronization_peers::{Peers, PeersImpl};
+
+    use super::*;
+
+    pub struct DummyStore {
+        height: u64,
+        history: Vec<H256>,
+    }
+
+    impl BlockChain for DummyStore {
+        fn insert(&self, _block: IndexedBlock) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn canonize(&self, _id: &H256) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl BlockProvider for DummyStore {
+        fn block_id(&self, height: u64) -> Option<H256> {
+            self.history.get(height as usize).cloned()
+        }
+    }
+
+    impl IndexedBlockProvider for DummyStore {
+        fn indexed_block(&self, _block_ref: BlockRef) -> Option<IndexedBlock> {
+            None
+        }
+    }
+
+    impl Store for DummyStore {
+        fn best_block(&self) -> BestBlock {
+            BestBlock {
+                height: self.height - 1,
+                id: self.history[0].clone(),
+            }
+        }
+
+        fn height(&self) -> u64 {
+            self.height
+        }
+
+        fn short_chain_history(&self) -> LinkedList<H256> {
+            self.history.iter().cloned().collect()
+        }
+    }
+
+    impl CanonStore for DummyStore {
+        fn as_store(&self) -> &Store {
+            &*self
+        }
+    }
+
+    #[derive(Default)]
+    pub struct DummyExecutor {
+        tasks: Mutex<Vec<Task>>,
+    }
+
+    impl TaskExecutor for DummyExecutor {
+        fn execute(&self, task: Task) {
+            self.tasks.lock().push(task);
+        }
+    }
+
+    pub struct DummyConnection;
+
+    impl OutboundSyncConnection for DummyConnection {
+        fn notify_new_block(&self, _arg: &NewBlock) {}
+        fn notify_new_fluffy_block(&self, _arg: &NewFluffyBlock) {}
+        fn notify_new_transactions(&self, _arg: &NewTransactions) {}
+        fn notify_request_chain(&self, _arg: &RequestChain) {}
+        fn notify_request_fluffy_missing_tx(&self, _arg: &RequestFluffyMissingTx) {}
+        fn notify_request_get_objects(&self, _arg: &RequestGetObjects) {}
+        fn notify_response_chain_entry(&self, _arg: &ResponseChainEntry) {}
+        fn notify_response_get_objects(&self, _arg: &ResponseGetObjects) {}
+        fn close(&self) {}
+    }
+
+    fn test_core(executor: Arc<DummyExecutor>,
+                 peers: Arc<PeersImpl>)
+                 -> SynchronizationClientCore {
+        let store = Arc::new(DummyStore {
+                                 height: 10,
+                                 history: vec![H256::from([1; 32]), H256::from([2; 32])],
+                             });
+
+        SynchronizationClientCore {
+            executor,
+            chain: Chain::new(store),
+            network: Network::Testnet,
+            peers,
+            contexes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn sync_data(current_height: u64) -> CoreSyncData {
+        CoreSyncData {
+            cumulative_difficulty: current_height,
+            current_height,
+            top_id: H256::from([0xff; 32]),
+            top_version: 1,
+        }
+    }
+
+    #[test]
+    fn higher_peer_triggers_request_chain() {
+        let executor = Arc::new(DummyExecutor::default());
+        let peers = Arc::new(PeersImpl::new());
+        let core = test_core(executor.clone(), peers.clone());
+
+        peers.insert(0, &sync_data(20), Arc::new(DummyConnection));
+        core.on_connect(0);
+
+        let tasks = executor.tasks.lock();
+        assert_eq!(tasks.len(), 1);
+        match tasks[0] {
+            Task::RequestChain(peer_index, ref request) => {
+                assert_eq!(peer_index, 0);
+                let block_ids: Vec<H256> = request.block_ids.0.iter().cloned().collect();
+                assert_eq!(block_ids, vec![H256::from([1; 32]), H256::from([2; 32])]);
+            }
+        }
+    }
+
+    #[test]
+    fn synchronized_peer_does_not_trigger_request_chain() {
+        let executor = Arc::new(DummyExecutor::default());
+        let peers = Arc::new(PeersImpl::new());
+        let core = test_core(executor.clone(), peers.clone());
+
+        peers.insert(0, &sync_data(10), Arc::new(DummyConnection));
+        peers.insert(1, &sync_data(5), Arc::new(DummyConnection));
+        core.on_connect(0);
+        core.on_connect(1);
+
+        assert!(executor.tasks.lock().is_empty());
+    }
+
+    #[test]
+    fn response_chain_entry_schedules_blocks() {
+        let executor = Arc::new(DummyExecutor::default());
+        let peers = Arc::new(PeersImpl::new());
+        let core = test_core(executor.clone(), peers.clone());
+
+        peers.insert(0, &sync_data(20), Arc::new(DummyConnection));
+        core.on_connect(0);
+
+        let block_ids: LinkedList<H256> = vec![H256::from([3; 32]), H256::from([4; 32])]
+            .into_iter()
+            .collect();
+        core.on_response_chain_entry(0,
+                                     &ResponseChainEntry {
+                                          start_height: 9,
+                                          total_height: 20,
+                                          cummulative_difficulty: 20,
+                                          block_ids: block_ids.into(),
+                                      });
+
+        assert_eq!(core.chain.scheduled_blocks(),
+                   vec![H256::from([3; 32]), H256::from([4; 32])]);
+    }
+}
@@ -22,6 +22,7 @@ pub trait TaskExecutor: Send + Sync + 'static {
     fn execute(&self, task: Task);
 }
 
+#[derive(Debug)]
 pub enum Task {
     RequestChain(PeerIndex, cn::cmd::RequestChain),
 }
@@ -23,13 +23,13 @@ use storage::SharedStore;
 use local_node::LocalNode;
 use synchronization_client::SynchronizationClient;
 use synchronization_client_core::SynchronizationClientCore;
-use synchronization_executor::LocalSynchronizationTaskExecutor;
+use synchronization_executor::TaskExecutor;
 use synchronization_peers::Peers;
 
 pub type LocalNodeRef = Arc<LocalNode>;
 pub type ClientRef = Arc<SynchronizationClient>;
 pub type ClientCoreRef = Arc<Mutex<SynchronizationClientCore>>;
 pub type PeersRef = Arc<Peers>;
-pub type ExecutorRef = Arc<LocalSynchronizationTaskExecutor>;
+pub type ExecutorRef = Arc<dyn TaskExecutor>;
 pub type StorageRef = SharedStore;
 pub type PeerIndex = usize;
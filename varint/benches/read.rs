@@ -0,0 +1,56 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Throughput of `read` on a buffer of mostly small varints.
+//!
+//! Run with `cargo bench -p xmr-varint`.
+
+use std::io::Cursor;
+use std::time::Instant;
+
+use bytes::{Buf, Bytes, BytesMut, IntoBuf};
+use xmr_varint::ReadError;
+
+const VARINTS: usize = 1_000_000;
+const ROUNDS: usize = 20;
+
+fn main() {
+    let mut buf = BytesMut::with_capacity(VARINTS * 10);
+    for i in 0..VARINTS {
+        // 9 out of 10 varints fit in one or two bytes.
+        let n = if i % 10 == 0 { i as u64 * 1_000_003 } else { i as u64 % 16384 };
+        xmr_varint::write(&mut buf, n);
+    }
+    let buf = buf.freeze();
+
+    bench("read", &buf, xmr_varint::read);
+    bench("read_slow", &buf, xmr_varint::read_slow);
+}
+
+fn bench<F>(name: &str, buf: &Bytes, read: F)
+    where F: Fn(&mut Cursor<Bytes>) -> Result<u64, ReadError>
+{
+    let start = Instant::now();
+    let mut sum = 0u64;
+    for _ in 0..ROUNDS {
+        let mut reader = buf.clone().into_buf();
+        while reader.has_remaining() {
+            sum = sum.wrapping_add(read(&mut reader).unwrap());
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let total = (VARINTS * ROUNDS) as f64;
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    println!("{}: {} varints in {:?} ({:.1} Mvarint/s, checksum {})",
+             name,
+             VARINTS * ROUNDS,
+             elapsed,
+             total / secs / 1e6,
+             sum);
+}
@@ -0,0 +1,178 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Varint length-delimited framing.
+
+use std::io;
+
+use bytes::{Bytes, BytesMut, IntoBuf};
+use tokio_io::codec::{Decoder, Encoder};
+
+use crate::{read, write, length, ReadError, MOST_SIGNIFICANT_BIT};
+
+/// Maximum length of an `u64` varint.
+const MAX_VARINT_LENGTH: usize = 10;
+
+/// Default maximum payload length, the same as tokio's
+/// `LengthDelimitedCodec`.
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+/// Frames a stream as payloads prefixed by their varint length.
+#[derive(Debug, Clone, Copy)]
+pub struct VarintLengthDelimitedCodec {
+    max_frame_length: usize,
+}
+
+impl Default for VarintLengthDelimitedCodec {
+    fn default() -> VarintLengthDelimitedCodec {
+        VarintLengthDelimitedCodec { max_frame_length: DEFAULT_MAX_FRAME_LENGTH }
+    }
+}
+
+impl VarintLengthDelimitedCodec {
+    /// Creates a new `VarintLengthDelimitedCodec` accepting payloads up to
+    /// `DEFAULT_MAX_FRAME_LENGTH` bytes.
+    pub fn new() -> VarintLengthDelimitedCodec {
+        VarintLengthDelimitedCodec::default()
+    }
+
+    /// The maximum length of the payloads.
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    /// Sets the maximum length of the payloads, longer payloads are
+    /// rejected with `InvalidData`.
+    pub fn set_max_frame_length(&mut self, max_frame_length: usize) {
+        self.max_frame_length = max_frame_length;
+    }
+
+    fn frame_too_big(&self, len: u64) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData,
+                       format!("frame of {} bytes exceeds the maximum of {} bytes",
+                               len,
+                               self.max_frame_length))
+    }
+}
+
+impl Decoder for VarintLengthDelimitedCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BytesMut>, io::Error> {
+        let prefix_len = match src.iter().position(|b| b & MOST_SIGNIFICANT_BIT == 0) {
+            Some(position) => position + 1,
+            None if src.len() >= MAX_VARINT_LENGTH => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, ReadError::TooLong));
+            }
+            None => return Ok(None),
+        };
+
+        let payload_len: u64 = read(&mut (&src[..prefix_len]).into_buf())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        // checked against the maximum first so it fits in an usize.
+        if payload_len > self.max_frame_length as u64 {
+            return Err(self.frame_too_big(payload_len));
+        }
+        let payload_len = payload_len as usize;
+
+        if src.len() - prefix_len < payload_len {
+            return Ok(None);
+        }
+
+        src.advance(prefix_len);
+        Ok(Some(src.split_to(payload_len)))
+    }
+}
+
+impl Encoder for VarintLengthDelimitedCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn encode(&mut self, payload: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+        if payload.len() > self.max_frame_length {
+            return Err(self.frame_too_big(payload.len() as u64));
+        }
+
+        dst.reserve(length(payload.len()) + payload.len());
+        write(dst, payload.len());
+        dst.extend_from_slice(&payload);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_frame_split_across_reads() {
+        let mut codec = VarintLengthDelimitedCodec::new();
+        let payload = vec![0x42u8; 300];
+
+        let mut frame = BytesMut::new();
+        codec.encode(Bytes::from(payload.clone()), &mut frame).unwrap();
+        assert_eq!(&frame[..2], &[0b1010_1100, 0b0000_0010]);
+
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&frame[..1]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&frame[1..100]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        src.extend_from_slice(&frame[100..]);
+
+        let decoded = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(&decoded[..], &payload[..]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let mut codec = VarintLengthDelimitedCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode(Bytes::from(&b"monero"[..]), &mut buf).unwrap();
+        codec.encode(Bytes::new(), &mut buf).unwrap();
+
+        assert_eq!(&codec.decode(&mut buf).unwrap().unwrap()[..], b"monero");
+        assert!(codec.decode(&mut buf).unwrap().unwrap().is_empty());
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_rejects_overlong_prefix() {
+        let mut codec = VarintLengthDelimitedCodec::new();
+        let mut src = BytesMut::from(vec![0xff; MAX_VARINT_LENGTH]);
+        assert!(codec.decode(&mut src).is_err());
+    }
+
+    #[test]
+    fn frames_over_the_max_length_are_rejected() {
+        let mut codec = VarintLengthDelimitedCodec::new();
+        assert_eq!(codec.max_frame_length(), DEFAULT_MAX_FRAME_LENGTH);
+        codec.set_max_frame_length(4);
+
+        let mut buf = BytesMut::new();
+        codec.encode(Bytes::from(&b"xmr!"[..]), &mut buf).unwrap();
+        assert_eq!(&codec.decode(&mut buf).unwrap().unwrap()[..], b"xmr!");
+
+        // rejected from the prefix alone, before the payload arrives.
+        let mut src = BytesMut::from(vec![5]);
+        let e = codec.decode(&mut src).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        // a length that doesn't fit in 32 bits.
+        let mut src = BytesMut::new();
+        write(&mut src, u64::max_value());
+        let e = codec.decode(&mut src).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let e = codec.encode(Bytes::from(&b"monero"[..]), &mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
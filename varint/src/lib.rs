@@ -18,6 +18,12 @@ use std::mem::size_of;
 use bytes::{BytesMut, Buf, BufMut};
 use num::cast::ToPrimitive;
 
+#[cfg(feature = "codec")]
+mod codec;
+
+#[cfg(feature = "codec")]
+pub use crate::codec::{VarintLengthDelimitedCodec, DEFAULT_MAX_FRAME_LENGTH};
+
 pub const MOST_SIGNIFICANT_BIT: u8 = 0b10000000;
 //const DROP_MSB: u8 = 0b01111111;
 const EXTRACT_SEVEN_LEAST_SIGNIFICANT_BITS: u64 = 0b01111111;
@@ -33,39 +39,231 @@ pub fn write<I: ToPrimitive>(output: &mut BytesMut, number: I) {
     output.put_u8(number as u8);
 }
 
+/// Write an integer as a varint into `dst`, returns the number of bytes
+/// written. `dst` is left untouched if it's too small.
+pub fn write_slice(dst: &mut [u8], mut number: u64) -> Result<usize, WriteError> {
+    let len = length(number);
+    if dst.len() < len {
+        return Err(WriteError::BufferTooSmall);
+    }
+
+    for byte in dst[..len - 1].iter_mut() {
+        *byte = (number & EXTRACT_SEVEN_LEAST_SIGNIFICANT_BITS) as u8 | MOST_SIGNIFICANT_BIT;
+        number >>= 7;
+    }
+    dst[len - 1] = number as u8;
+
+    Ok(len)
+}
+
+/// The varint encoding of `number`, for diagnostics.
+pub fn encoded_bytes(number: u64) -> Vec<u8> {
+    let mut bytes = vec![0; length(number)];
+    write_slice(&mut bytes, number).expect("the buffer fits the varint");
+    bytes
+}
+
+/// The varint encoding of `number` as space separated hex bytes, e.g.
+/// `"ac 02"` for 300.
+pub fn encoded_hex(number: u64) -> String {
+    encoded_bytes(number)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Write an `u128` as a varint, up to 19 bytes long.
+pub fn write_u128(output: &mut BytesMut, mut number: u128) {
+    while number > 127 {
+        let byte = (number & EXTRACT_SEVEN_LEAST_SIGNIFICANT_BITS as u128) as u8 |
+                   MOST_SIGNIFICANT_BIT;
+        output.put_u8(byte);
+        number >>= 7;
+    }
+    output.put_u8(number as u8);
+}
+
 /// Read a varint.
 pub fn read<B: Buf>(buf: &mut B) -> Result<u64, ReadError> {
-    let bits = (size_of::<u64>() * 8) as u64;
+    // Fast path, most varints are one or two bytes long.
+    let fast = {
+        let bytes = buf.bytes();
+        match bytes.len() {
+            0 => None,
+            1 if bytes[0] & MOST_SIGNIFICANT_BIT == 0 => Some((1, bytes[0] as u64)),
+            1 => None,
+            _ if bytes[0] & MOST_SIGNIFICANT_BIT == 0 => Some((1, bytes[0] as u64)),
+            _ if bytes[1] & MOST_SIGNIFICANT_BIT == 0 => {
+                if bytes[1] == 0 {
+                    return Err(ReadError::Represent);
+                }
+
+                Some((2, (bytes[0] & 0x7f) as u64 | (bytes[1] as u64) << 7))
+            }
+            _ => None,
+        }
+    };
+
+    if let Some((len, output)) = fast {
+        buf.advance(len);
+        return Ok(output);
+    }
+
+    read_slow(buf)
+}
+
+/// Read a varint, rejecting any encoding longer than the minimal one.
+pub fn read_canonical<B: Buf>(buf: &mut B) -> Result<u64, ReadError> {
+    let before = buf.remaining();
+    let value = read(buf)?;
+
+    if before - buf.remaining() != length(value) {
+        return Err(ReadError::Represent);
+    }
+
+    Ok(value)
+}
+
+/// Read a varint that must be one of the `allowed` values, e.g. a tag.
+pub fn read_one_of<B: Buf>(buf: &mut B, allowed: &[u64]) -> Result<u64, ReadError> {
+    let value = read(buf)?;
+
+    if !allowed.contains(&value) {
+        return Err(ReadError::Unexpected(value));
+    }
+
+    Ok(value)
+}
+
+/// Read a varint without the fast path for small values.
+#[doc(hidden)]
+pub fn read_slow<B: Buf>(buf: &mut B) -> Result<u64, ReadError> {
+    let bits = (size_of::<u64>() * 8) as u32;
     let mut output = 0u64;
-    let mut shift = 0u64;
+    let mut shift = 0u32;
     loop {
+        if !buf.has_remaining() {
+            return Err(ReadError::Truncated);
+        }
         let byte = buf.get_u8();
 
-        if shift + 7 >= bits && byte >= 1 << (bits - shift) {
-            return Err(ReadError::Overflow);
-        }
+        // the shifts are checked, bits shifted out of the integer are an
+        // overflow and so is a continuation past its last byte.
+        let value = (byte & 0x7f) as u64;
+        let shifted = value
+            .checked_shl(shift)
+            .filter(|shifted| shifted.checked_shr(shift) == Some(value))
+            .ok_or(ReadError::Overflow)?;
 
         if byte == 0 && shift != 0 {
             return Err(ReadError::Represent);
         }
 
-        // Does the actualy placing into output, stripping the first bit
-        output |= ((byte & 0x7f) as u64) << shift;
+        output |= shifted;
 
         /* If there is no next */
         if (byte & 0x80) == 0 {
             break;
         }
 
-        shift += 7;
+        shift = shift
+            .checked_add(7)
+            .filter(|&shift| shift < bits)
+            .ok_or(ReadError::Overflow)?;
     }
 
     Ok(output)
 }
 
+/// Read a varint written with `write_u128`.
+pub fn read_u128<B: Buf>(buf: &mut B) -> Result<u128, ReadError> {
+    let bits = (size_of::<u128>() * 8) as u32;
+    let mut output = 0u128;
+    let mut shift = 0u32;
+    loop {
+        if !buf.has_remaining() {
+            return Err(ReadError::Truncated);
+        }
+        let byte = buf.get_u8();
+
+        let value = (byte & 0x7f) as u128;
+        let shifted = value
+            .checked_shl(shift)
+            .filter(|shifted| shifted.checked_shr(shift) == Some(value))
+            .ok_or(ReadError::Overflow)?;
+
+        if byte == 0 && shift != 0 {
+            return Err(ReadError::Represent);
+        }
+
+        output |= shifted;
+
+        if (byte & 0x80) == 0 {
+            break;
+        }
+
+        shift = shift
+            .checked_add(7)
+            .filter(|&shift| shift < bits)
+            .ok_or(ReadError::Overflow)?;
+    }
+
+    Ok(output)
+}
+
+/// Write `data` prefixed by its length as a varint, the encoding of
+/// strings and blobs.
+pub fn write_bytes(output: &mut BytesMut, data: &[u8]) {
+    output.reserve(length(data.len()) + data.len());
+    write(output, data.len());
+    output.put_slice(data);
+}
+
+/// Read a byte string written with `write_bytes`, lengths above `max_len`
+/// are rejected before anything is allocated.
+pub fn read_bytes<B: Buf>(buf: &mut B, max_len: usize) -> Result<Vec<u8>, ReadError> {
+    let len = read(buf)?;
+    if len > max_len as u64 {
+        return Err(ReadError::LengthExceeded(len));
+    }
+
+    let len = len as usize;
+    if buf.remaining() < len {
+        return Err(ReadError::Truncated);
+    }
+
+    let mut data = vec![0; len];
+    buf.copy_to_slice(&mut data);
+    Ok(data)
+}
+
 /// Calcuate how many bytes a varint occupies in memory.
+///
+/// Panics on negative numbers, like `write`.
 pub fn length<I: ToPrimitive>(i: I) -> usize {
-    let mut i = i.to_u64().unwrap();
+    try_length(i).expect("varint number must not be negative")
+}
+
+/// Calcuate how many bytes a varint occupies in memory, `None` if the
+/// number doesn't fit in an `u64`.
+pub fn try_length<I: ToPrimitive>(i: I) -> Option<usize> {
+    Some(const_length(i.to_u64()?))
+}
+
+/// Calcuate how many bytes an `u64` varint occupies in memory, usable in
+/// constants to size buffers.
+pub const fn const_length(mut i: u64) -> usize {
+    let mut count = 1;
+    while i >= 0x80 {
+        count += 1;
+        i >>= 7;
+    }
+    count
+}
+
+/// Calcuate how many bytes an `u128` varint occupies in memory.
+pub fn length_u128(mut i: u128) -> usize {
     let mut count = 1;
     while i >= 0x80 {
         count += 1;
@@ -81,6 +279,14 @@ pub enum ReadError {
     Overflow,
     /// The integer cannot be represented.
     Represent,
+    /// The integer isn't one of the allowed values.
+    Unexpected(u64),
+    /// The buffer ended in the middle of the integer.
+    Truncated,
+    /// The integer is encoded in more bytes than its type allows.
+    TooLong,
+    /// The length prefix of a byte string is above the allowed maximum.
+    LengthExceeded(u64),
 }
 
 impl std::fmt::Display for ReadError {
@@ -88,10 +294,34 @@ impl std::fmt::Display for ReadError {
         match *self {
             ReadError::Overflow => write!(fmt, "the integer is too large"),
             ReadError::Represent => write!(fmt, "the integer cannot be represented"),
+            ReadError::Unexpected(value) => write!(fmt, "unexpected integer {}", value),
+            ReadError::Truncated => write!(fmt, "the integer is truncated"),
+            ReadError::TooLong => write!(fmt, "the integer encoding is too long"),
+            ReadError::LengthExceeded(len) => {
+                write!(fmt, "the length {} is above the allowed maximum", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+/// An error occurred during writing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteError {
+    /// The destination can't hold the whole varint.
+    BufferTooSmall,
+}
+
+impl std::fmt::Display for WriteError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WriteError::BufferTooSmall => write!(fmt, "the buffer is too small"),
         }
     }
 }
 
+impl std::error::Error for WriteError {}
 
 #[cfg(test)]
 pub mod tests {
@@ -175,6 +405,253 @@ pub mod tests {
         }
     }
 
+    #[test]
+    #[should_panic(expected = "varint number must not be negative")]
+    fn length_of_negative_should_panick() {
+        length(-1);
+    }
+
+    #[test]
+    fn try_length_of_negative_is_none() {
+        assert_eq!(try_length(-1), None);
+        assert_eq!(try_length(std::i64::MIN), None);
+    }
+
+    #[test]
+    fn const_length_at_boundaries() {
+        const X: usize = const_length(300);
+        const BUFFER: [u8; const_length(std::u64::MAX)] = [0; const_length(std::u64::MAX)];
+        assert_eq!(X, 2);
+        assert_eq!(BUFFER.len(), 10);
+
+        for &(number, len) in [(0, 1), (127, 1), (128, 2), (16383, 2), (16384, 3),
+                               ((1 << 56) - 1, 8), (1 << 56, 9), (1 << 63, 10)].iter() {
+            assert_eq!(const_length(number), len);
+            assert_eq!(length(number), len);
+        }
+    }
+
+    #[test]
+    fn length_of_u64_max() {
+        assert_eq!(length(std::u64::MAX), 10);
+        assert_eq!(try_length(std::u64::MAX), Some(10));
+        assert_eq!(try_length(0), Some(1));
+        assert_eq!(try_length(16384), Some(3));
+    }
+
+    #[test]
+    fn read_canonical_rejects_overlong_encodings() {
+        for bytes in [&[0x80u8, 0x00][..], &[0x81, 0x00], &[0xff, 0x80, 0x00]].iter() {
+            match read_canonical(&mut bytes.into_buf()) {
+                Err(ReadError::Represent) => { /* happy path */ }
+                other => panic!("{:?} should be rejected, got {:?}", bytes, other),
+            }
+        }
+
+        assert_eq!(read_canonical(&mut [0x00u8][..].into_buf()).unwrap(), 0);
+        assert_eq!(read_canonical(&mut [0x01u8][..].into_buf()).unwrap(), 1);
+        assert_eq!(read_canonical(&mut [0xac, 0x02][..].into_buf()).unwrap(), 300);
+    }
+
+    #[test]
+    fn u128_roundtrip() {
+        let numbers = [0u128, 127, 128, 300, std::u64::MAX as u128, std::u64::MAX as u128 + 1,
+                       std::u128::MAX];
+        for &number in numbers.iter() {
+            let mut buf = BytesMut::with_capacity(19);
+            write_u128(&mut buf, number);
+            assert_eq!(buf.len(), length_u128(number));
+            assert_eq!(read_u128(&mut buf.as_ref().into_buf()).unwrap(), number);
+        }
+
+        assert_eq!(length_u128(std::u128::MAX), 19);
+
+        // the u64 encoding is the same.
+        let mut buf = BytesMut::with_capacity(10);
+        write(&mut buf, std::u64::MAX);
+        let mut buf_u128 = BytesMut::with_capacity(19);
+        write_u128(&mut buf_u128, std::u64::MAX as u128);
+        assert_eq!(buf, buf_u128);
+    }
+
+    #[test]
+    fn read_u128_overflow() {
+        let mut bytes = vec![0xffu8; 18];
+        bytes.push(0x04);
+        match read_u128(&mut (&bytes[..]).into_buf()) {
+            Err(ReadError::Overflow) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let data = vec![0x42u8; 300];
+        for data in [&[][..], &[0x01][..], &data[..]].iter() {
+            let mut buf = BytesMut::new();
+            write_bytes(&mut buf, data);
+            assert_eq!(buf.len(), length(data.len()) + data.len());
+
+            let mut reader = (&buf[..]).into_buf();
+            assert_eq!(read_bytes(&mut reader, 300).unwrap(), data.to_vec());
+            assert_eq!(reader.remaining(), 0);
+        }
+    }
+
+    #[test]
+    fn bytes_above_max_len() {
+        let mut buf = BytesMut::new();
+        write_bytes(&mut buf, &[0x42; 11]);
+
+        match read_bytes(&mut (&buf[..]).into_buf(), 10) {
+            Err(ReadError::LengthExceeded(11)) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(read_bytes(&mut (&buf[..]).into_buf(), 11).unwrap(), vec![0x42; 11]);
+    }
+
+    #[test]
+    fn truncated_bytes() {
+        let mut buf = BytesMut::new();
+        write_bytes(&mut buf, &[0x42; 11]);
+
+        match read_bytes(&mut (&buf[..buf.len() - 1]).into_buf(), 100) {
+            Err(ReadError::Truncated) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        for bytes in [&[][..], &[0x80][..], &[0xff, 0xff, 0xff][..]].iter() {
+            match read(&mut bytes.into_buf()) {
+                Err(ReadError::Truncated) => { /* happy path */ }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        match read_u128(&mut (&[0x80u8][..]).into_buf()) {
+            Err(ReadError::Truncated) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_error_is_std_error() {
+        let error: Box<dyn std::error::Error> = Box::new(ReadError::Truncated);
+        assert_eq!(error.to_string(), "the integer is truncated");
+
+        let result: Result<u64, Box<dyn std::error::Error>> = (|| {
+            Ok(read_one_of(&mut (&[0x03u8][..]).into_buf(), &[1, 2])?)
+        })();
+        assert_eq!(result.unwrap_err().to_string(), "unexpected integer 3");
+    }
+
+    #[test]
+    fn ten_byte_overflow() {
+        // the tenth byte only holds the top bit of an u64.
+        let mut bytes = vec![0xffu8; 9];
+        bytes.push(0x02);
+        match read(&mut (&bytes[..]).into_buf()) {
+            Err(ReadError::Overflow) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut bytes = vec![0x80u8; 9];
+        bytes.push(0x01);
+        assert_eq!(read(&mut (&bytes[..]).into_buf()).unwrap(), 1 << 63);
+    }
+
+    #[test]
+    fn eleven_byte_overflow() {
+        for last in [0x00u8, 0x01, 0x7f].iter() {
+            let mut bytes = vec![0x80u8; 9];
+            bytes.push(0x81);
+            bytes.push(*last);
+            match read(&mut (&bytes[..]).into_buf()) {
+                Err(ReadError::Overflow) => { /* happy path */ }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        let bytes = [0xffu8; 20];
+        match read_u128(&mut (&bytes[..]).into_buf()) {
+            Err(ReadError::Overflow) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn write_slice_exact() {
+        let mut dst = [0u8; 2];
+        assert_eq!(write_slice(&mut dst, 300), Ok(2));
+        assert_eq!(dst, [0b1010_1100, 0b0000_0010]);
+    }
+
+    #[test]
+    fn write_slice_too_small() {
+        let mut dst = [0xaa; 1];
+        assert_eq!(write_slice(&mut dst, 300), Err(WriteError::BufferTooSmall));
+        assert_eq!(dst, [0xaa]);
+        assert_eq!(write_slice(&mut [], 0), Err(WriteError::BufferTooSmall));
+    }
+
+    #[test]
+    fn write_slice_oversized() {
+        let mut dst = [0xaa; 12];
+        assert_eq!(write_slice(&mut dst, std::u64::MAX), Ok(10));
+        assert_eq!(&dst[..10], &[255, 255, 255, 255, 255, 255, 255, 255, 255, 1]);
+        assert_eq!(&dst[10..], &[0xaa, 0xaa]);
+    }
+
+    #[test]
+    fn encoded_300() {
+        assert_eq!(encoded_bytes(300), vec![0b1010_1100, 0b0000_0010]);
+        assert_eq!(encoded_hex(300), "ac 02");
+        assert_eq!(encoded_hex(0), "00");
+    }
+
+    #[test]
+    fn read_one_of_allowed_tags() {
+        const TAGS: &[u64] = &[0x01, 0x02, 0xff];
+
+        assert_eq!(read_one_of(&mut [0x02u8][..].into_buf(), TAGS).unwrap(), 0x02);
+        assert_eq!(read_one_of(&mut [0xffu8, 0x01][..].into_buf(), TAGS).unwrap(), 0xff);
+        match read_one_of(&mut [0x03u8][..].into_buf(), TAGS) {
+            Err(ReadError::Unexpected(3)) => { /* happy path */ }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fast_path_matches_general_path() {
+        let mut write_buf = BytesMut::new();
+        for input in 0..=u16::MAX {
+            write(&mut write_buf, input);
+            let fast = read(&mut write_buf.as_ref().into_buf()).unwrap();
+            let slow = read_slow(&mut write_buf.as_ref().into_buf()).unwrap();
+            assert_eq!(fast, slow);
+            assert_eq!(fast, input as u64);
+            write_buf.clear();
+        }
+
+        // non-canonical two byte encoding.
+        let bytes = [0x85u8, 0x00];
+        match (read(&mut (&bytes[..]).into_buf()), read_slow(&mut (&bytes[..]).into_buf())) {
+            (Err(ReadError::Represent), Err(ReadError::Represent)) => { /* happy path */ }
+            other => panic!("unexpected results: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fast_path_consumes_only_the_varint() {
+        let bytes = [0xac, 0x02, 0x07];
+        let mut buf = (&bytes[..]).into_buf();
+        assert_eq!(read(&mut buf).unwrap(), 300);
+        assert_eq!(read(&mut buf).unwrap(), 7);
+        assert_eq!(buf.remaining(), 0);
+    }
+
     fn assert_varint<T: ToPrimitive>(n: T, bytes: &[u8]) {
         let mut buf = BytesMut::new();
         write(&mut buf, n);